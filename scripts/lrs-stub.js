@@ -0,0 +1,97 @@
+// Local stand-in for an xAPI Learning Record Store, for trying out LMS
+// packages without a real LRS. Statements are kept in memory and logged.
+//
+//   npm run lrs-stub
+//   open index.html?endpoint=http://localhost:8088/xapi
+//
+// Set LRS_PORT to listen elsewhere and LRS_AUTH (e.g. "Basic dXNlcjpwYXNz")
+// to require that Authorization header.
+const http = require('http');
+const crypto = require('crypto');
+
+const PORT = parseInt(process.env.LRS_PORT || '8088', 10);
+const AUTH = process.env.LRS_AUTH || null;
+const statements = [];
+
+function send(res, status, body) {
+    res.writeHead(status, {
+        'Content-Type': 'application/json',
+        'X-Experience-API-Version': '1.0.3',
+        'Access-Control-Allow-Origin': '*',
+        'Access-Control-Allow-Headers': 'Authorization, Content-Type, X-Experience-API-Version',
+        'Access-Control-Allow-Methods': 'GET, POST, PUT, OPTIONS'
+    });
+    res.end(body === undefined ? '' : JSON.stringify(body, null, 2));
+}
+
+function validate(statement) {
+    if (!statement || typeof statement !== 'object') return 'statement must be an object';
+    if (!statement.actor) return 'missing actor';
+    if (!statement.verb || !statement.verb.id) return 'missing verb.id';
+    if (!statement.object || !statement.object.id) return 'missing object.id';
+    return null;
+}
+
+http.createServer((req, res) => {
+    const url = new URL(req.url, `http://localhost:${PORT}`);
+
+    if (req.method === 'OPTIONS') return send(res, 204);
+
+    if (url.pathname === '/xapi/about') {
+        return send(res, 200, { version: ['1.0.3'] });
+    }
+
+    if (url.pathname !== '/xapi/statements') return send(res, 404, { error: 'not found' });
+
+    if (AUTH && req.headers.authorization !== AUTH) {
+        return send(res, 401, { error: 'bad or missing Authorization header' });
+    }
+
+    if (!req.headers['x-experience-api-version']) {
+        return send(res, 400, { error: 'missing X-Experience-API-Version header' });
+    }
+
+    if (req.method === 'GET') {
+        const verb = url.searchParams.get('verb');
+        return send(res, 200, {
+            statements: statements.filter(s => !verb || s.verb.id === verb),
+            more: ''
+        });
+    }
+
+    if (req.method !== 'POST' && req.method !== 'PUT') return send(res, 405, { error: 'method not allowed' });
+
+    let body = '';
+    req.on('data', chunk => { body += chunk; });
+    req.on('end', () => {
+        let batch;
+        try {
+            const parsed = JSON.parse(body);
+            batch = Array.isArray(parsed) ? parsed : [parsed];
+        } catch (error) {
+            return send(res, 400, { error: `invalid JSON: ${error.message}` });
+        }
+
+        for (const statement of batch) {
+            const problem = validate(statement);
+            if (problem) return send(res, 400, { error: problem });
+        }
+
+        const ids = batch.map(statement => {
+            statement.id = statement.id || crypto.randomUUID();
+            statement.stored = new Date().toISOString();
+            statements.push(statement);
+
+            const verb = statement.verb.id.split('/').pop();
+            const progress = statement.result && statement.result.extensions
+                ? Object.values(statement.result.extensions)[0]
+                : undefined;
+            console.log(`${statement.stored}  ${verb.padEnd(11)} ${statement.object.id}${progress !== undefined ? `  ${progress}%` : ''}`);
+            return statement.id;
+        });
+
+        send(res, 200, ids);
+    });
+}).listen(PORT, () => {
+    console.log(`xAPI LRS stand-in on http://localhost:${PORT}/xapi`);
+});
@@ -1,5 +1,5 @@
 use std::path::Path;
-use std::process::Command;
+use crate::commands::media_tools::{self, MediaTool};
 
 pub fn compress_for_transcription(input_path: &str) -> Result<String, String> {
     // Create temp output path
@@ -13,7 +13,7 @@ pub fn compress_for_transcription(input_path: &str) -> Result<String, String> {
     println!("Compressing audio for transcription: {} -> {:?}", input_path, output_path);
     
     // Compress to 16kHz mono 32kbps (perfect for speech recognition)
-    let status = Command::new("ffmpeg")
+    let status = media_tools::ffmpeg()
         .args([
             "-i", input_path,
             "-ar", "16000",      // 16kHz sample rate
@@ -23,7 +23,7 @@ pub fn compress_for_transcription(input_path: &str) -> Result<String, String> {
             output_path.to_str().unwrap()
         ])
         .status()
-        .map_err(|e| format!("Failed to run FFmpeg: {}", e))?;
+        .map_err(|e| media_tools::spawn_error(MediaTool::FFmpeg, e))?;
     
     if !status.success() {
         return Err("FFmpeg compression failed".to_string());
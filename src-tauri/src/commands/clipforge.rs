@@ -1,4 +1,9 @@
 use tauri::AppHandle;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use crate::commands::media_tools::{self, MediaTool};
+
+// Numbers concat list files, so a preview and an export can run at once
+static CONCAT_LIST_COUNTER: AtomicUsize = AtomicUsize::new(0);
 
 #[tauri::command]
 pub async fn select_video_files(app: AppHandle) -> Result<Vec<String>, String> {
@@ -40,7 +45,6 @@ pub async fn select_output_path(app: AppHandle) -> Result<Option<String>, String
 pub async fn concat_videos(clips: Vec<String>, output_path: String) -> Result<String, String> {
     use std::fs::File;
     use std::io::Write;
-    use std::process::Command;
     
     println!("Concatenating {} clips to {}", clips.len(), output_path);
     
@@ -48,7 +52,7 @@ pub async fn concat_videos(clips: Vec<String>, output_path: String) -> Result<St
     let mut audio_info: Vec<(String, bool)> = Vec::new();
     
     for clip in &clips {
-        let output = Command::new("ffprobe")
+        let output = media_tools::ffprobe()
             .args(&[
                 "-v", "error",
                 "-select_streams", "a:0",
@@ -57,7 +61,7 @@ pub async fn concat_videos(clips: Vec<String>, output_path: String) -> Result<St
                 clip
             ])
             .output()
-            .map_err(|e| format!("Failed to check audio: {}", e))?;
+            .map_err(|e| media_tools::spawn_error(MediaTool::FFprobe, e))?;
         
         let has_audio = !output.stdout.is_empty();
         let filename = std::path::Path::new(clip)
@@ -110,7 +114,9 @@ pub async fn concat_videos(clips: Vec<String>, output_path: String) -> Result<St
     }
     
     // All compatible or correctly ordered - proceed with fast concat
-    let temp_list = std::env::temp_dir().join("clipforge_concat_list.txt");
+    let list_id = CONCAT_LIST_COUNTER.fetch_add(1, Ordering::Relaxed);
+    let temp_list = std::env::temp_dir()
+        .join(format!("clipforge_concat_list_{}_{}.txt", std::process::id(), list_id));
     let mut file = File::create(&temp_list)
         .map_err(|e| format!("Failed to create temp file: {}", e))?;
     
@@ -121,7 +127,7 @@ pub async fn concat_videos(clips: Vec<String>, output_path: String) -> Result<St
     
     drop(file);
     
-    let output = Command::new("ffmpeg")
+    let output = media_tools::ffmpeg()
         .args(&[
             "-f", "concat",
             "-safe", "0",
@@ -130,7 +136,7 @@ pub async fn concat_videos(clips: Vec<String>, output_path: String) -> Result<St
             &output_path
         ])
         .output()
-        .map_err(|e| format!("Failed to execute FFmpeg: {}", e))?;
+        .map_err(|e| media_tools::spawn_error(MediaTool::FFmpeg, e))?;
     
     let _ = std::fs::remove_file(&temp_list);
     
@@ -142,6 +148,31 @@ pub async fn concat_videos(clips: Vec<String>, output_path: String) -> Result<St
     }
 }
 
+/// Concatenates the low-resolution proxies of `clips` for a quick preview.
+/// Final exports go through `concat_videos`, which always uses the originals.
+#[tauri::command]
+pub async fn preview_concat_videos(app: AppHandle, clips: Vec<String>) -> Result<String, String> {
+    use crate::commands::proxy;
+    
+    let cache_dir = proxy::proxy_cache_dir(&app)?;
+    
+    let mut proxies = Vec::new();
+    for clip in &clips {
+        match proxy::find_fresh_proxy(&cache_dir, clip) {
+            Some(proxy_path) => proxies.push(proxy_path),
+            None => return Err(format!("Preview proxy is not ready yet for {}", clip)),
+        }
+    }
+    
+    let output_path = cache_dir.join("preview.mp4");
+    let _ = std::fs::remove_file(&output_path);
+    let output_path = output_path.to_string_lossy().to_string();
+    
+    concat_videos(proxies, output_path.clone()).await?;
+    
+    Ok(output_path)
+}
+
 #[tauri::command]
 pub async fn get_file_size(path: String) -> Result<u64, String> {
     use std::fs;
@@ -165,9 +196,7 @@ pub async fn confirm_dialog(app: AppHandle, title: String, message: String) -> R
 
 #[tauri::command]
 pub async fn get_video_duration(path: String) -> Result<f64, String> {
-    use std::process::Command;
-    
-    let output = Command::new("ffprobe")
+    let output = media_tools::ffprobe()
         .args(&[
             "-v", "error",
             "-show_entries", "format=duration",
@@ -175,7 +204,7 @@ pub async fn get_video_duration(path: String) -> Result<f64, String> {
             &path
         ])
         .output()
-        .map_err(|e| format!("Failed to execute ffprobe: {}", e))?;
+        .map_err(|e| media_tools::spawn_error(MediaTool::FFprobe, e))?;
     
     if output.status.success() {
         let duration_str = String::from_utf8_lossy(&output.stdout);
@@ -1,6 +1,7 @@
 use serde::Deserialize;
 use std::fs;
 use crate::commands::audio_compress;
+use crate::commands::transcript::Transcript;
 
 // Worker config
 #[derive(serde::Serialize, serde::Deserialize, Debug)]
@@ -77,4 +78,20 @@ pub async fn transcribe_audio(
     } else {
         Err(result.error.unwrap_or_else(|| "Unknown error".to_string()))
     }
+}
+
+/// Transcribes the narration of a single page, so the text carries that
+/// page's id instead of being matched by its wording.
+#[tauri::command]
+pub async fn transcribe_page(
+    config: CloudflareConfig,
+    page_id: String,
+    audio_path: String,
+) -> Result<Transcript, String> {
+    let segments = transcribe_audio(config, audio_path).await?;
+    
+    Ok(Transcript {
+        page_id: Some(page_id),
+        text: segments.join("\n\n"),
+    })
 }
\ No newline at end of file
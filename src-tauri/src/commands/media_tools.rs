@@ -0,0 +1,349 @@
+use tauri::{AppHandle, Manager};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Mutex;
+use std::fs;
+
+const SETTINGS_FILE: &str = "media_tools.json";
+
+// Encoders and filters the export pipelines rely on
+const REQUIRED_ENCODERS: &[&str] = &["libx264", "aac"];
+const REQUIRED_FILTERS: &[&str] = &["loudnorm", "xfade"];
+
+// Paths picked in the app, loaded at startup and updated by set_media_tool_paths
+static CONFIGURED: Mutex<MediaToolSettings> = Mutex::new(MediaToolSettings {
+    ffmpeg_path: None,
+    ffprobe_path: None,
+});
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Default)]
+pub struct MediaToolSettings {
+    pub ffmpeg_path: Option<String>,
+    pub ffprobe_path: Option<String>,
+}
+
+#[derive(serde::Serialize, Debug, Clone, Copy, PartialEq)]
+pub enum MediaTool {
+    FFmpeg,
+    FFprobe,
+}
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct ToolLocation {
+    pub path: String,
+    /// Where the binary was found: "setting", "env", "sidecar" or "path"
+    pub source: String,
+}
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct ToolInfo {
+    pub path: String,
+    pub source: String,
+    pub version: Option<String>,
+}
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct MediaToolsReport {
+    pub ok: bool,
+    pub ffmpeg: Option<ToolInfo>,
+    pub ffprobe: Option<ToolInfo>,
+    pub missing_encoders: Vec<String>,
+    pub missing_filters: Vec<String>,
+    pub problems: Vec<String>,
+}
+
+impl MediaTool {
+    fn binary_name(self) -> &'static str {
+        match self {
+            MediaTool::FFmpeg => "ffmpeg",
+            MediaTool::FFprobe => "ffprobe",
+        }
+    }
+
+    fn display_name(self) -> &'static str {
+        match self {
+            MediaTool::FFmpeg => "FFmpeg",
+            MediaTool::FFprobe => "ffprobe",
+        }
+    }
+
+    fn env_var(self) -> &'static str {
+        match self {
+            MediaTool::FFmpeg => "CLIPFORGE_FFMPEG",
+            MediaTool::FFprobe => "CLIPFORGE_FFPROBE",
+        }
+    }
+}
+
+/// A `Command` for the resolved ffmpeg binary.
+pub fn ffmpeg() -> Command {
+    command_for(MediaTool::FFmpeg)
+}
+
+/// A `Command` for the resolved ffprobe binary.
+pub fn ffprobe() -> Command {
+    command_for(MediaTool::FFprobe)
+}
+
+/// Turns a spawn failure into a message that says how to fix it.
+pub fn spawn_error(tool: MediaTool, e: std::io::Error) -> String {
+    if e.kind() == std::io::ErrorKind::NotFound {
+        format!(
+            "{} was not found. Set its location in the app, set {}, or install it on your PATH.",
+            tool.display_name(),
+            tool.env_var()
+        )
+    } else {
+        format!("Failed to execute {}: {}", tool.display_name(), e)
+    }
+}
+
+/// Finds a binary by checking, in order: the app setting, the environment
+/// variable, a sidecar next to the executable, then PATH.
+pub fn locate(tool: MediaTool) -> Option<ToolLocation> {
+    let configured = CONFIGURED.lock().ok().and_then(|settings| match tool {
+        MediaTool::FFmpeg => settings.ffmpeg_path.clone(),
+        MediaTool::FFprobe => settings.ffprobe_path.clone(),
+    });
+
+    if let Some(path) = configured.filter(|p| Path::new(p).is_file()) {
+        return Some(ToolLocation { path, source: "setting".to_string() });
+    }
+
+    if let Ok(path) = std::env::var(tool.env_var()) {
+        if Path::new(&path).is_file() {
+            return Some(ToolLocation { path, source: "env".to_string() });
+        }
+    }
+
+    let file_name = format!("{}{}", tool.binary_name(), std::env::consts::EXE_SUFFIX);
+
+    let sidecar = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(|dir| dir.join(&file_name)))
+        .filter(|p| p.is_file());
+
+    if let Some(path) = sidecar {
+        return Some(ToolLocation {
+            path: path.to_string_lossy().to_string(),
+            source: "sidecar".to_string(),
+        });
+    }
+
+    find_on_path(&file_name).map(|path| ToolLocation {
+        path: path.to_string_lossy().to_string(),
+        source: "path".to_string(),
+    })
+}
+
+/// Loads the saved tool paths; called once from the app setup hook.
+pub fn load_settings(app: &AppHandle) {
+    let settings = settings_path(app)
+        .ok()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|json| serde_json::from_str::<MediaToolSettings>(&json).ok())
+        .unwrap_or_default();
+
+    if let Ok(mut configured) = CONFIGURED.lock() {
+        *configured = settings;
+    }
+}
+
+#[tauri::command]
+pub async fn set_media_tool_paths(
+    app: AppHandle,
+    settings: MediaToolSettings,
+) -> Result<MediaToolsReport, String> {
+    let json = serde_json::to_string_pretty(&settings)
+        .map_err(|e| format!("Failed to serialize settings: {}", e))?;
+
+    fs::write(settings_path(&app)?, json)
+        .map_err(|e| format!("Failed to save media tool settings: {}", e))?;
+
+    if let Ok(mut configured) = CONFIGURED.lock() {
+        *configured = settings;
+    }
+
+    check_media_tools().await
+}
+
+/// The saved tool paths, used to fill in the settings form.
+#[tauri::command]
+pub async fn get_media_tool_paths() -> Result<MediaToolSettings, String> {
+    CONFIGURED.lock()
+        .map(|settings| settings.clone())
+        .map_err(|_| "Media tool settings lock poisoned".to_string())
+}
+
+#[tauri::command]
+pub async fn select_media_tool(app: AppHandle, title: String) -> Result<Option<String>, String> {
+    use tauri_plugin_dialog::DialogExt;
+    
+    let file = app.dialog()
+        .file()
+        .set_title(&title)
+        .blocking_pick_file();
+    
+    Ok(file.map(|p| p.to_string()))
+}
+
+#[tauri::command]
+pub async fn check_media_tools() -> Result<MediaToolsReport, String> {
+    let mut problems = Vec::new();
+
+    let ffmpeg = probe_tool(MediaTool::FFmpeg, &mut problems);
+    let ffprobe = probe_tool(MediaTool::FFprobe, &mut problems);
+
+    let mut missing_encoders = Vec::new();
+    let mut missing_filters = Vec::new();
+
+    if ffmpeg.is_some() {
+        let encoders = list_capabilities("-encoders")?;
+        let filters = list_capabilities("-filters")?;
+
+        for encoder in REQUIRED_ENCODERS {
+            if !encoders.iter().any(|e| e == encoder) {
+                missing_encoders.push(encoder.to_string());
+                problems.push(format!("FFmpeg is missing the {} encoder", encoder));
+            }
+        }
+
+        for filter in REQUIRED_FILTERS {
+            if !filters.iter().any(|f| f == filter) {
+                missing_filters.push(filter.to_string());
+                problems.push(format!("FFmpeg is missing the {} filter", filter));
+            }
+        }
+    }
+
+    Ok(MediaToolsReport {
+        ok: problems.is_empty(),
+        ffmpeg,
+        ffprobe,
+        missing_encoders,
+        missing_filters,
+        problems,
+    })
+}
+
+fn command_for(tool: MediaTool) -> Command {
+    match locate(tool) {
+        Some(location) => Command::new(location.path),
+        // Let the OS report the failure; spawn_error explains it
+        None => Command::new(tool.binary_name()),
+    }
+}
+
+fn probe_tool(tool: MediaTool, problems: &mut Vec<String>) -> Option<ToolInfo> {
+    let location = match locate(tool) {
+        Some(location) => location,
+        None => {
+            problems.push(format!(
+                "{} was not found (checked app setting, {}, bundled sidecar and PATH)",
+                tool.display_name(),
+                tool.env_var()
+            ));
+            return None;
+        }
+    };
+
+    let output = match Command::new(&location.path).arg("-version").output() {
+        Ok(output) if output.status.success() => output,
+        _ => {
+            problems.push(format!("{} at {} did not run", tool.display_name(), location.path));
+            return None;
+        }
+    };
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    Some(ToolInfo {
+        path: location.path,
+        source: location.source,
+        version: parse_version(&stdout),
+    })
+}
+
+// "ffmpeg version 6.1.1-essentials Copyright ..." -> "6.1.1-essentials"
+fn parse_version(output: &str) -> Option<String> {
+    let first_line = output.lines().next()?;
+    let mut words = first_line.split_whitespace();
+
+    words.find(|w| *w == "version")?;
+    words.next().map(|v| v.to_string())
+}
+
+/// Duration of a media file in seconds.
+pub fn probe_duration(path: &str) -> Result<f64, String> {
+    let output = ffprobe()
+        .args([
+            "-v", "error",
+            "-show_entries", "format=duration",
+            "-of", "default=noprint_wrappers=1:nokey=1",
+            path,
+        ])
+        .output()
+        .map_err(|e| spawn_error(MediaTool::FFprobe, e))?;
+
+    if !output.status.success() {
+        return Err(format!("Failed to read duration of {}", path));
+    }
+
+    String::from_utf8_lossy(&output.stdout)
+        .trim()
+        .parse()
+        .map_err(|e| format!("Failed to parse duration of {}: {}", path, e))
+}
+
+/// Names of the encoders the resolved ffmpeg was built with.
+pub fn available_encoders() -> Result<Vec<String>, String> {
+    list_capabilities("-encoders")
+}
+
+fn list_capabilities(flag: &str) -> Result<Vec<String>, String> {
+    let output = ffmpeg()
+        .args(["-hide_banner", flag])
+        .output()
+        .map_err(|e| spawn_error(MediaTool::FFmpeg, e))?;
+
+    Ok(parse_capability_names(&String::from_utf8_lossy(&output.stdout)))
+}
+
+// Both listings are "<flags> <name> ..." rows after a legend of "<flags> = ..." rows
+fn parse_capability_names(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut words = line.split_whitespace();
+            let flags = words.next()?;
+            let name = words.next()?;
+
+            let is_flags = flags.chars().all(|c| c.is_ascii_uppercase() || c == '.' || c == '|');
+
+            if is_flags && name != "=" {
+                Some(name.to_string())
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+fn find_on_path(file_name: &str) -> Option<PathBuf> {
+    let paths = std::env::var_os("PATH")?;
+
+    std::env::split_paths(&paths)
+        .map(|dir| dir.join(file_name))
+        .find(|candidate| candidate.is_file())
+}
+
+fn settings_path(app: &AppHandle) -> Result<PathBuf, String> {
+    let dir = app.path()
+        .app_config_dir()
+        .map_err(|e| format!("Failed to resolve config directory: {}", e))?;
+
+    fs::create_dir_all(&dir)
+        .map_err(|e| format!("Failed to create config folder: {}", e))?;
+
+    Ok(dir.join(SETTINGS_FILE))
+}
\ No newline at end of file
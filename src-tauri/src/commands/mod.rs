@@ -2,4 +2,24 @@
 pub mod storypack;
 pub mod publish;
 pub mod cloudflare;
-pub mod audio_compress;
\ No newline at end of file
+pub mod audio_compress;
+pub mod proxy;
+pub mod media_tools;
+pub mod transcript;
+pub mod storypack_template;
+pub mod storypack_theme;
+pub mod storypack_images;
+pub mod storypack_audio;
+pub mod storypack_video;
+pub mod storypack_inline;
+pub mod storypack_zip;
+pub mod storypack_epub;
+pub mod storypack_pdf;
+pub mod storypack_scorm;
+pub mod storypack_validate;
+pub mod storypack_output;
+pub mod storypack_build;
+pub mod storypack_manifest;
+pub mod storypack_import;
+pub mod storypack_project;
+pub mod storypack_autofill;
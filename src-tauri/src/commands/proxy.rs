@@ -0,0 +1,243 @@
+use tauri::{AppHandle, Emitter, Manager};
+use std::collections::{BTreeSet, HashMap};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::{Condvar, Mutex};
+use std::time::UNIX_EPOCH;
+use std::fs;
+use crate::commands::media_tools::{self, MediaTool};
+
+// Proxies are scaled down to this height, width follows the aspect ratio
+const PROXY_HEIGHT: u32 = 540;
+const INDEX_FILE: &str = "proxies.json";
+
+// Background workers share the index file, so writes go through this lock
+static INDEX_LOCK: Mutex<()> = Mutex::new(());
+
+// Originals currently being encoded. A second job for the same clip waits
+// on IN_FLIGHT_DONE instead of encoding to the same temp file.
+static IN_FLIGHT: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+static IN_FLIGHT_DONE: Condvar = Condvar::new();
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct ProxyEntry {
+    pub original_path: String,
+    pub original_size: u64,
+    pub original_mtime: u64,
+    pub proxy_path: String,
+}
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct ProxyStatus {
+    pub original_path: String,
+    pub proxy_path: Option<String>,
+    pub error: Option<String>,
+}
+
+#[tauri::command]
+pub async fn generate_proxies(app: AppHandle, clips: Vec<String>) -> Result<(), String> {
+    let cache_dir = proxy_cache_dir(&app)?;
+
+    println!("Generating proxies for {} clips in {:?}", clips.len(), cache_dir);
+
+    // Encoding runs in the background; the UI is told about each clip as it finishes
+    std::thread::spawn(move || {
+        for clip in clips {
+            let status = match ensure_proxy(&cache_dir, &clip) {
+                Ok(proxy_path) => ProxyStatus {
+                    original_path: clip,
+                    proxy_path: Some(proxy_path),
+                    error: None,
+                },
+                Err(e) => {
+                    println!("Proxy generation failed for {}: {}", clip, e);
+                    ProxyStatus {
+                        original_path: clip,
+                        proxy_path: None,
+                        error: Some(e),
+                    }
+                }
+            };
+
+            let _ = app.emit("proxy-ready", status);
+        }
+    });
+
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn get_proxy_path(app: AppHandle, path: String) -> Result<Option<String>, String> {
+    let cache_dir = proxy_cache_dir(&app)?;
+    Ok(find_fresh_proxy(&cache_dir, &path))
+}
+
+/// Returns the proxy for `original` if one exists and still matches the
+/// original's size and modification time.
+pub fn find_fresh_proxy(cache_dir: &Path, original: &str) -> Option<String> {
+    let (size, mtime) = source_fingerprint(original).ok()?;
+    let index = load_index(cache_dir);
+    let entry = index.get(original)?;
+
+    if entry.original_size == size
+        && entry.original_mtime == mtime
+        && Path::new(&entry.proxy_path).exists()
+    {
+        Some(entry.proxy_path.clone())
+    } else {
+        None
+    }
+}
+
+pub fn proxy_cache_dir(app: &AppHandle) -> Result<PathBuf, String> {
+    let dir = app.path()
+        .app_cache_dir()
+        .map_err(|e| format!("Failed to resolve cache directory: {}", e))?
+        .join("proxies");
+
+    fs::create_dir_all(&dir)
+        .map_err(|e| format!("Failed to create proxy cache folder: {}", e))?;
+
+    Ok(dir)
+}
+
+fn ensure_proxy(cache_dir: &Path, original: &str) -> Result<String, String> {
+    // Held until this function returns; an earlier job's proxy is picked up below
+    let _claim = InFlight::claim(original)?;
+
+    if let Some(existing) = find_fresh_proxy(cache_dir, original) {
+        println!("Proxy up to date: {}", existing);
+        return Ok(existing);
+    }
+
+    let (size, mtime) = source_fingerprint(original)?;
+    let proxy_path = cache_dir.join(proxy_file_name(original, size, mtime));
+
+    create_proxy(original, &proxy_path)?;
+
+    let entry = ProxyEntry {
+        original_path: original.to_string(),
+        original_size: size,
+        original_mtime: mtime,
+        proxy_path: proxy_path.to_string_lossy().to_string(),
+    };
+
+    let _guard = INDEX_LOCK.lock().map_err(|_| "Proxy index lock poisoned".to_string())?;
+    let mut index = load_index(cache_dir);
+
+    // Drop the proxy of a previous version of this file
+    if let Some(old) = index.insert(original.to_string(), entry.clone()) {
+        if old.proxy_path != entry.proxy_path {
+            let _ = fs::remove_file(&old.proxy_path);
+        }
+    }
+
+    save_index(cache_dir, &index)?;
+
+    Ok(entry.proxy_path)
+}
+
+/// Marks an original as being encoded until dropped.
+struct InFlight(String);
+
+impl InFlight {
+    fn claim(original: &str) -> Result<Self, String> {
+        let poisoned = |_| "Proxy job lock poisoned".to_string();
+        let mut in_flight = IN_FLIGHT.lock().map_err(poisoned)?;
+
+        while in_flight.contains(original) {
+            in_flight = IN_FLIGHT_DONE.wait(in_flight).map_err(poisoned)?;
+        }
+
+        in_flight.insert(original.to_string());
+        Ok(InFlight(original.to_string()))
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        if let Ok(mut in_flight) = IN_FLIGHT.lock() {
+            in_flight.remove(&self.0);
+        }
+        IN_FLIGHT_DONE.notify_all();
+    }
+}
+
+fn create_proxy(original: &str, proxy_path: &Path) -> Result<(), String> {
+    println!("Creating proxy: {} -> {:?}", original, proxy_path);
+
+    // Encode to a temp name first so a half-written proxy is never picked up
+    let temp_path = proxy_path.with_extension("partial.mp4");
+    let scale = format!("scale=-2:'min({},ih)'", PROXY_HEIGHT);
+
+    let output = media_tools::ffmpeg()
+        .args([
+            "-i", original,
+            "-vf", &scale,
+            "-c:v", "libx264",
+            "-preset", "veryfast",
+            "-crf", "28",
+            "-c:a", "aac",
+            "-b:a", "96k",
+            "-movflags", "+faststart",
+            "-y",
+        ])
+        .arg(&temp_path)
+        .output()
+        .map_err(|e| media_tools::spawn_error(MediaTool::FFmpeg, e))?;
+
+    if !output.status.success() {
+        let _ = fs::remove_file(&temp_path);
+        let error_msg = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("FFmpeg error: {}", error_msg));
+    }
+
+    fs::rename(&temp_path, proxy_path)
+        .map_err(|e| format!("Failed to store proxy: {}", e))?;
+
+    Ok(())
+}
+
+fn source_fingerprint(path: &str) -> Result<(u64, u64), String> {
+    let metadata = fs::metadata(path)
+        .map_err(|e| format!("Failed to read {}: {}", path, e))?;
+
+    let mtime = metadata.modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    Ok((metadata.len(), mtime))
+}
+
+fn proxy_file_name(original: &str, size: u64, mtime: u64) -> String {
+    let mut hasher = DefaultHasher::new();
+    original.hash(&mut hasher);
+    size.hash(&mut hasher);
+    mtime.hash(&mut hasher);
+
+    // Keep the original stem so ffprobe/ffmpeg messages stay readable
+    let stem = Path::new(original)
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("clip");
+
+    format!("{}_{:016x}.mp4", stem, hasher.finish())
+}
+
+fn load_index(cache_dir: &Path) -> HashMap<String, ProxyEntry> {
+    fs::read_to_string(cache_dir.join(INDEX_FILE))
+        .ok()
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+fn save_index(cache_dir: &Path, index: &HashMap<String, ProxyEntry>) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(index)
+        .map_err(|e| format!("Failed to serialize proxy index: {}", e))?;
+
+    fs::write(cache_dir.join(INDEX_FILE), json)
+        .map_err(|e| format!("Failed to write proxy index: {}", e))
+}
\ No newline at end of file
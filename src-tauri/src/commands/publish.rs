@@ -18,7 +18,7 @@ pub async fn select_storypack_folder(app: AppHandle) -> Result<Option<String>, S
     
     let folder = app.dialog()
         .file()
-        .set_title("Select Storypack Folder to Upload")
+        .set_title("Select Storypack Folder")
         .blocking_pick_folder();
     
     Ok(folder.map(|p| p.to_string()))
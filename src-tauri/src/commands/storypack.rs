@@ -1,19 +1,154 @@
 use tauri::{AppHandle};
-use std::path::Path;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
 use std::fs;
-
-#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+use crate::commands::transcript::{self, Transcript};
+use crate::commands::storypack_template::{self, narration_name, GeneratedAssets};
+use crate::commands::storypack_theme::{self, ThemeConfig};
+use crate::commands::storypack_images::{ImageOptions, ImagePipeline};
+use crate::commands::storypack_audio::{AudioKind, AudioOptions, AudioPipeline};
+use crate::commands::storypack_video::{VideoOptions, VideoPipeline};
+use crate::commands::storypack_inline::SingleFileOptions;
+use crate::commands::storypack_epub::{self, EpubOptions};
+use crate::commands::storypack_pdf::{self, PdfOptions};
+use crate::commands::storypack_zip::{self, ArchiveOptions};
+use crate::commands::storypack_scorm::{self, LmsOptions};
+use crate::commands::storypack_validate::{self, ValidationReport};
+use crate::commands::storypack_output::{self, ConflictPolicy, StagingDir};
+use crate::commands::storypack_build::{self, BuildCache};
+use crate::commands::storypack_manifest;
+use serde_json::json;
+
+// Windows refuses these as file or folder names, even with an extension
+const RESERVED_NAMES: &[&str] = &[
+    "con", "prn", "aux", "nul",
+    "com1", "com2", "com3", "com4", "com5", "com6", "com7", "com8", "com9",
+    "lpt1", "lpt2", "lpt3", "lpt4", "lpt5", "lpt6", "lpt7", "lpt8", "lpt9",
+];
+
+// Page ids that name other assets
+const RESERVED_PAGE_IDS: &[&str] = &["video"];
+
+// Leaves room for suffixes like `-v2` and `-scorm.zip` within the usual
+// 255-byte file name limit
+const MAX_FOLDER_NAME_CHARS: usize = 100;
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Default)]
 pub struct StoryspackConfig {
+    /// Display title; the folder name is derived from it with
+    /// `project_folder_name`
     pub project_name: String,
+    /// Pages in reading order. When empty, the fixed cover/prologue/chapters/
+    /// epilogue/credits fields below are used instead.
+    #[serde(default)]
+    pub pages: Vec<Page>,
+    #[serde(default)]
     pub cover_image: Option<String>,
+    #[serde(default)]
     pub prologue_image: Option<String>,
+    #[serde(default)]
     pub chapter_images: Vec<String>,
+    #[serde(default)]
     pub epilogue_image: Option<String>,
+    #[serde(default)]
     pub credits_image: Option<String>,
+    /// Narration bound to pages by id
+    #[serde(default)]
+    pub narration: Vec<NarrationAssignment>,
+    /// Old layout: one file per page in reading order, only used when
+    /// `narration` is empty
+    #[serde(default)]
     pub narration_audio: Vec<String>,
+    #[serde(default)]
     pub theme_audio: Option<String>,
+    #[serde(default)]
     pub video_source: Option<VideoSource>,
-    pub transcriptions: Vec<String>,
+    /// Page text, tagged with a page id or placed by its heading
+    #[serde(default)]
+    pub transcriptions: Vec<Transcript>,
+    /// Folder whose index.html / styles.css / player.js replace the
+    /// built-in templates
+    #[serde(default)]
+    pub template_dir: Option<String>,
+    /// Built-in theme plus color and font overrides
+    #[serde(default)]
+    pub theme: ThemeConfig,
+    /// Resizing and re-encoding of page images
+    #[serde(default)]
+    pub images: ImageOptions,
+    /// Transcoding of narration and theme music
+    #[serde(default)]
+    pub audio: AudioOptions,
+    /// Conversion of local videos to web-playable MP4
+    #[serde(default)]
+    pub video: VideoOptions,
+    /// Export as one self-contained index.html
+    #[serde(default)]
+    pub single_file: SingleFileOptions,
+    /// Fixed-layout EPUB 3 with read-aloud narration
+    #[serde(default)]
+    pub epub: EpubOptions,
+    /// Printable picture-book PDF
+    #[serde(default)]
+    pub pdf: PdfOptions,
+    /// `<project>.zip` of the finished folder
+    #[serde(default)]
+    pub archive: ArchiveOptions,
+    /// SCORM 1.2 / xAPI package for learning management systems
+    #[serde(default)]
+    pub lms: LmsOptions,
+    /// What to do when the storypack folder already exists. Stops by default;
+    /// the app form opts into `new_version`.
+    #[serde(default)]
+    pub on_conflict: ConflictPolicy,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum PageKind {
+    Cover,
+    Prologue,
+    Chapter,
+    Interlude,
+    Epilogue,
+    Credits,
+    Custom,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct Page {
+    /// Stable id used for asset names and `data-page`; generated from the
+    /// kind when left empty (cover, chapter1, chapter2, ...)
+    #[serde(default)]
+    pub id: String,
+    pub kind: PageKind,
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub image: Option<String>,
+    /// Narration clips for this page, played one after another. A single
+    /// path is accepted as well.
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub narration: Vec<String>,
+    #[serde(default)]
+    pub text: Option<String>,
+    #[serde(default)]
+    pub video: Option<VideoSource>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct NarrationAssignment {
+    pub page_id: String,
+    pub path: String,
+}
+
+#[derive(serde::Serialize, Debug, Clone, Default)]
+pub struct NarrationReport {
+    /// Clips pointing at a page id that doesn't exist, or left over after
+    /// every page got one
+    pub unassigned: Vec<String>,
+    /// Clips used more than once
+    pub duplicates: Vec<String>,
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
@@ -24,6 +159,285 @@ pub enum VideoSource {
     Local { path: String },
 }
 
+impl PageKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PageKind::Cover => "cover",
+            PageKind::Prologue => "prologue",
+            PageKind::Chapter => "chapter",
+            PageKind::Interlude => "interlude",
+            PageKind::Epilogue => "epilogue",
+            PageKind::Credits => "credits",
+            PageKind::Custom => "page",
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            PageKind::Cover => "Cover",
+            PageKind::Prologue => "Prologue",
+            PageKind::Chapter => "Chapter",
+            PageKind::Interlude => "Interlude",
+            PageKind::Epilogue => "Epilogue",
+            PageKind::Credits => "Credits",
+            PageKind::Custom => "Page",
+        }
+    }
+
+    // Kinds that normally appear many times are always numbered
+    fn is_numbered(self) -> bool {
+        matches!(self, PageKind::Chapter | PageKind::Interlude | PageKind::Custom)
+    }
+}
+
+impl Page {
+    pub fn new(kind: PageKind, image: Option<String>) -> Self {
+        Page {
+            id: String::new(),
+            kind,
+            title: None,
+            image,
+            narration: Vec::new(),
+            text: None,
+            video: None,
+        }
+    }
+
+    pub fn display_title(&self) -> &str {
+        self.title.as_deref().unwrap_or(self.kind.label())
+    }
+}
+
+impl StoryspackConfig {
+    /// Pages in reading order with ids and titles filled in. Configs that only
+    /// use the fixed five-part fields are converted to pages here.
+    pub fn resolved_pages(&self) -> Vec<Page> {
+        self.resolve().0
+    }
+
+    /// Warnings about transcripts that couldn't be placed and pages without text.
+    pub fn transcript_warnings(&self) -> Vec<String> {
+        let (pages, mut warnings) = self.resolve();
+
+        for idx in transcript::pages_without_text(&pages) {
+            warnings.push(format!("Page \"{}\" has no text", pages[idx].id));
+        }
+
+        warnings
+    }
+
+    /// Resolved pages plus warnings about transcripts that couldn't be placed.
+    pub fn resolve(&self) -> (Vec<Page>, Vec<String>) {
+        let mut pages = if self.pages.is_empty() {
+            self.legacy_pages()
+        } else {
+            self.pages.clone()
+        };
+
+        assign_page_ids(&mut pages);
+        let warnings = transcript::assign_transcripts(&mut pages, &self.transcriptions);
+
+        for assignment in &self.narration {
+            if let Some(page) = pages.iter_mut().find(|p| p.id == assignment.page_id) {
+                page.narration.push(assignment.path.clone());
+            }
+        }
+
+        (pages, warnings)
+    }
+
+    /// Reports narration that won't be played or is played more than once.
+    pub fn narration_report(&self, pages: &[Page]) -> NarrationReport {
+        let mut report = NarrationReport::default();
+
+        for assignment in &self.narration {
+            if !pages.iter().any(|p| p.id == assignment.page_id) {
+                report.unassigned.push(format!("{} (no page \"{}\")", assignment.path, assignment.page_id));
+            }
+        }
+
+        if self.pages.is_empty() && self.narration.is_empty() {
+            for extra in self.narration_audio.iter().skip(pages.len()) {
+                report.unassigned.push(format!("{} (more narration files than pages)", extra));
+            }
+        }
+
+        let mut seen = HashSet::new();
+        for clip in pages.iter().flat_map(|p| p.narration.iter()) {
+            if !seen.insert(clip) && !report.duplicates.contains(clip) {
+                report.duplicates.push(clip.clone());
+            }
+        }
+
+        report
+    }
+
+    fn legacy_pages(&self) -> Vec<Page> {
+        let mut pages = Vec::new();
+
+        if let Some(cover) = &self.cover_image {
+            pages.push(Page::new(PageKind::Cover, Some(cover.clone())));
+        }
+
+        if let Some(prologue) = &self.prologue_image {
+            pages.push(Page::new(PageKind::Prologue, Some(prologue.clone())));
+        }
+
+        for chapter in &self.chapter_images {
+            pages.push(Page::new(PageKind::Chapter, Some(chapter.clone())));
+        }
+
+        if let Some(epilogue) = &self.epilogue_image {
+            pages.push(Page::new(PageKind::Epilogue, Some(epilogue.clone())));
+        }
+
+        if let Some(credits) = &self.credits_image {
+            pages.push(Page::new(PageKind::Credits, Some(credits.clone())));
+        }
+
+        // Without explicit assignments the old layout hands out one
+        // narration file per page, in order
+        let mut narration = self.narration_audio.iter()
+            .filter(|_| self.narration.is_empty());
+
+        for page in &mut pages {
+            page.narration = narration.next().cloned().into_iter().collect();
+        }
+
+        pages
+    }
+}
+
+fn one_or_many<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(serde::Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    let value = <Option<OneOrMany> as serde::Deserialize>::deserialize(deserializer)?;
+
+    Ok(match value {
+        Some(OneOrMany::One(path)) => vec![path],
+        Some(OneOrMany::Many(paths)) => paths,
+        None => Vec::new(),
+    })
+}
+
+// Fills in missing ids and titles and makes every id unique and file-name safe
+fn assign_page_ids(pages: &mut [Page]) {
+    let mut kind_counts: HashMap<PageKind, usize> = HashMap::new();
+    // The global `video_source` is written as `assets/video/video.*`, so a
+    // page called "video" becomes "video-2"
+    let mut used: HashSet<String> = RESERVED_PAGE_IDS.iter().map(|id| id.to_string()).collect();
+
+    for page in pages.iter_mut() {
+        let count = kind_counts.entry(page.kind).or_insert(0);
+        *count += 1;
+
+        let numbered = page.kind.is_numbered() || *count > 1;
+
+        if page.title.is_none() && numbered {
+            page.title = Some(format!("{} {}", page.kind.label(), count));
+        }
+
+        let base = if page.id.trim().is_empty() {
+            if numbered {
+                format!("{}{}", page.kind.as_str(), count)
+            } else {
+                page.kind.as_str().to_string()
+            }
+        } else {
+            sanitize_page_id(&page.id)
+        };
+
+        let mut id = base.clone();
+        let mut suffix = 2;
+        while !used.insert(id.clone()) {
+            id = format!("{}-{}", base, suffix);
+            suffix += 1;
+        }
+
+        page.id = id;
+    }
+}
+
+/// Folder name for a project. Characters that aren't allowed in file names
+/// become dashes, leading and trailing dots and spaces are dropped, and
+/// reserved Windows names get a `_`. Names that try to leave the output
+/// folder with `..` are rejected.
+pub fn project_folder_name(project_name: &str) -> Result<String, String> {
+    if project_name.split(['/', '\\']).any(|part| part.trim() == "..") {
+        return Err(format!("Project name \"{}\" can't contain \"..\"", project_name));
+    }
+
+    let invalid = |c: char| c.is_control() || matches!(c, '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|');
+    let chars: Vec<char> = project_name.chars().collect();
+    let mut replaced = String::new();
+
+    // "a/b" becomes "a-b", but "Tale: <1>" becomes "Tale 1" rather than "Tale- -1-"
+    for (idx, &c) in chars.iter().enumerate() {
+        if !invalid(c) {
+            replaced.push(c);
+            continue;
+        }
+
+        let after_word = replaced.chars().last().is_some_and(|prev| !prev.is_whitespace() && prev != '-');
+        let before_word = chars.get(idx + 1).is_some_and(|&next| !next.is_whitespace() && !invalid(next));
+        if after_word && before_word {
+            replaced.push('-');
+        }
+    }
+
+    let name = replaced.split_whitespace().collect::<Vec<_>>().join(" ");
+
+    let trim = |c: char| c == '.' || c == ' ' || c == '-';
+    let name: String = name.trim_matches(trim).chars().take(MAX_FOLDER_NAME_CHARS).collect();
+    let mut name = name.trim_end_matches(trim).to_string();
+
+    if name.is_empty() {
+        return Err(format!("Project name \"{}\" has nothing usable as a folder name", project_name.trim()));
+    }
+
+    let stem_len = name.find('.').unwrap_or(name.len());
+    if RESERVED_NAMES.contains(&name[..stem_len].to_lowercase().as_str()) {
+        name.insert(stem_len, '_');
+    }
+
+    Ok(name)
+}
+
+/// A file next to the storypack folder named after it, e.g. `<folder>.epub`.
+/// `with_extension` would cut folder names containing dots.
+pub fn export_path(project_path: &Path, suffix: &str) -> PathBuf {
+    let folder_name = project_path.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    project_path.with_file_name(format!("{}{}", folder_name, suffix))
+}
+
+fn sanitize_page_id(id: &str) -> String {
+    let cleaned: String = id
+        .trim()
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '-' })
+        .collect();
+
+    let cleaned = cleaned.trim_matches('-').to_string();
+
+    if cleaned.is_empty() {
+        "page".to_string()
+    } else {
+        cleaned
+    }
+}
+
 #[tauri::command]
 pub async fn select_output_folder(app: AppHandle) -> Result<Option<String>, String> {
     use tauri_plugin_dialog::DialogExt;
@@ -36,17 +450,46 @@ pub async fn select_output_folder(app: AppHandle) -> Result<Option<String>, Stri
     Ok(folder.map(|p| p.to_string()))
 }
 
+/// Where `generate_storypack` wrote the pack, plus anything the user should
+/// know about the result.
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct GeneratedStorypack {
+    pub path: String,
+    /// Files written next to the folder, such as `<project>.epub`
+    pub exports: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
 #[tauri::command]
 pub async fn generate_storypack(
+    app: AppHandle,
     config: StoryspackConfig,
     output_folder: String
-) -> Result<String, String> {
+) -> Result<GeneratedStorypack, String> {
     println!("Generating storypack: {}", config.project_name);
     
-    // Create project folder
-    let project_path = Path::new(&output_folder).join(&config.project_name);
-    fs::create_dir_all(&project_path)
-        .map_err(|e| format!("Failed to create project folder: {}", e))?;
+    // Catch missing files and bad settings before anything is written
+    let validation = storypack_validate::validate(&config);
+    if !validation.is_valid() {
+        return Err(validation.describe());
+    }
+    
+    for issue in &validation.warnings {
+        println!("Warning: {}: {}", issue.field, issue.message);
+    }
+    
+    let theme = storypack_theme::resolve_theme(&config.theme)?;
+    let image_pipeline = ImagePipeline::new(&config.images)?;
+    let audio_pipeline = AudioPipeline::new(&config.audio)?;
+    let video_pipeline = VideoPipeline::new(&config.video)?;
+    
+    // Build in a hidden sibling folder that is only swapped in once every
+    // step succeeded; returning early deletes it
+    let folder_name = project_folder_name(&config.project_name)?;
+    let (target_path, previous_build) =
+        storypack_output::target_path(Path::new(&output_folder), &folder_name, config.on_conflict)?;
+    let staging = StagingDir::create(&target_path)?;
+    let project_path = staging.project_path().to_path_buf();
     
     // Create asset folders
     let assets_path = project_path.join("assets");
@@ -61,50 +504,190 @@ pub async fn generate_storypack(
     fs::create_dir_all(&video_path)
         .map_err(|e| format!("Failed to create video folder: {}", e))?;
     
-    // Copy images
-    if let Some(cover) = &config.cover_image {
-        copy_file(cover, &images_path, "cover")?;
+    let pages = config.resolved_pages();
+    
+    // Copy page assets, optimizing images and audio on the way. Outputs of
+    // the previous build are reused when source and settings are unchanged.
+    let manifest_dir = storypack_build::manifest_dir(&app)?;
+    let mut cache = BuildCache::load(&manifest_dir, previous_build.as_deref());
+    let mut assets = GeneratedAssets::default();
+    
+    let image_settings = json!({ "images": &config.images });
+    let video_settings = json!({ "video": &config.video });
+    let audio_settings = |kind: &str| json!({ "audio": &config.audio, "kind": kind });
+    // Copies are named after the source extension
+    let copy_settings = |source: &str| json!({ "copy": storypack_template::safe_extension(source) });
+    
+    for page in &pages {
+        if let Some(image) = &page.image {
+            let key = format!("images/{}", page.id);
+            
+            match &image_pipeline {
+                Some(pipeline) if ImagePipeline::handles(image) => {
+                    let picture = cache.asset(&key, image, image_settings.clone(), &project_path, || {
+                        pipeline.process(image, &images_path, &page.id, "assets/images")
+                    })?;
+                    assets.pictures.insert(page.id.clone(), picture);
+                }
+                _ => cache.asset(&key, image, copy_settings(image), &project_path, || {
+                    copy_file(image, &images_path, &page.id)
+                })?,
+            }
+        }
+        
+        for (idx, narration) in page.narration.iter().enumerate() {
+            let name = narration_name(&page.id, idx);
+            let key = format!("audio/{}", name);
+            
+            match &audio_pipeline {
+                Some(pipeline) => {
+                    let sources = cache.asset(&key, narration, audio_settings("narration"), &project_path, || {
+                        pipeline.process(narration, &audio_path, &name, "assets/audio", AudioKind::Narration)
+                    })?;
+                    assets.audio.insert(name, sources);
+                }
+                None => cache.asset(&key, narration, copy_settings(narration), &project_path, || {
+                    copy_file(narration, &audio_path, &name)
+                })?,
+            }
+        }
+        
+        if let Some(VideoSource::Local { path }) = &page.video {
+            let key = format!("video/{}", page.id);
+            
+            match &video_pipeline {
+                Some(pipeline) => {
+                    let video = cache.asset(&key, path, video_settings.clone(), &project_path, || {
+                        pipeline.process(path, &video_path, &page.id, "assets/video")
+                    })?;
+                    assets.videos.insert(page.id.clone(), video);
+                }
+                None => cache.asset(&key, path, copy_settings(path), &project_path, || {
+                    copy_file(path, &video_path, &page.id)
+                })?,
+            }
+        }
     }
     
-    if let Some(prologue) = &config.prologue_image {
-        copy_file(prologue, &images_path, "prologue")?;
+    if let Some(theme) = &config.theme_audio {
+        match &audio_pipeline {
+            Some(pipeline) => {
+                let sources = cache.asset("audio/theme", theme, audio_settings("music"), &project_path, || {
+                    pipeline.process(theme, &audio_path, "theme", "assets/audio", AudioKind::Music)
+                })?;
+                assets.audio.insert("theme".to_string(), sources);
+            }
+            None => cache.asset("audio/theme", theme, copy_settings(theme), &project_path, || {
+                copy_file(theme, &audio_path, "theme")
+            })?,
+        }
     }
     
-    for (idx, chapter) in config.chapter_images.iter().enumerate() {
-        let name = format!("chapter{}", idx + 1);
-        copy_file(chapter, &images_path, &name)?;
+    // Convert or copy local video if provided
+    if let Some(VideoSource::Local { path }) = &config.video_source {
+        match &video_pipeline {
+            Some(pipeline) => {
+                let video = cache.asset("video/video", path, video_settings.clone(), &project_path, || {
+                    pipeline.process(path, &video_path, "video", "assets/video")
+                })?;
+                assets.videos.insert("video".to_string(), video);
+            }
+            None => cache.asset("video/video", path, copy_settings(path), &project_path, || {
+                copy_file(path, &video_path, "video")
+            })?,
+        }
     }
     
-    if let Some(epilogue) = &config.epilogue_image {
-        copy_file(epilogue, &images_path, "epilogue")?;
+    let (reused, built) = cache.counts();
+    println!("Assets: {} reused from the previous build, {} processed", reused, built);
+    
+    // Bundle the theme fonts so the pack works offline
+    theme.write_fonts(&assets_path.join("fonts"))?;
+    
+    // Render index.html, styles.css and player.js from templates
+    storypack_template::write_storypack_files(&config, &pages, &theme.context, &assets, &project_path)?;
+    
+    println!("Generated HTML, CSS and player files");
+    
+    let mut warnings = Vec::new();
+    let mut exports = Vec::new();
+    
+    let context = storypack_template::build_context(&config, &pages, theme.context.clone(), &assets);
+    
+    // storypack.json goes in first so the LMS package and archive carry it
+    let manifest = storypack_manifest::build_manifest(&config, &pages, &context, &project_path);
+    storypack_manifest::write_manifest(manifest.clone(), &project_path)?;
+    
+    // The EPUB, PDF and LMS package read the assets folder, so build them
+    // before single-file output removes what it inlined
+    if config.epub.enabled {
+        let (epub_path, epub_warnings) = storypack_epub::write_epub(&config, &context, &project_path)?;
+        println!("Generated EPUB: {}", epub_path.display());
+        exports.push(epub_path.to_string_lossy().to_string());
+        warnings.extend(epub_warnings);
     }
     
-    if let Some(credits) = &config.credits_image {
-        copy_file(credits, &images_path, "credits")?;
+    if config.pdf.enabled {
+        let (pdf_path, pdf_warnings) = storypack_pdf::write_pdf(&config, &context, &project_path)?;
+        println!("Generated PDF: {}", pdf_path.display());
+        exports.push(pdf_path.to_string_lossy().to_string());
+        warnings.extend(pdf_warnings);
     }
     
-    // Copy audio files
-    for (idx, audio) in config.narration_audio.iter().enumerate() {
-        let name = format!("narration{}", idx + 1);
-        copy_file(audio, &audio_path, &name)?;
+    if config.lms.enabled {
+        let package_path = storypack_scorm::write_scorm_package(&config, &context, &project_path)?;
+        println!("Generated SCORM package: {}", package_path.display());
+        exports.push(package_path.to_string_lossy().to_string());
     }
     
-    if let Some(theme) = &config.theme_audio {
-        copy_file(theme, &audio_path, "theme")?;
+    if config.single_file.enabled {
+        warnings.extend(storypack_template::write_single_file(&config, &pages, &theme.context, &assets, &project_path)?);
+        println!("Inlined assets into a single index.html");
+        
+        // Drop the files that were just inlined from the manifest
+        storypack_manifest::write_manifest(manifest, &project_path)?;
     }
     
-    // Copy local video if provided
-    if let Some(VideoSource::Local { path }) = &config.video_source {
-        copy_file(path, &video_path, "video")?;
+    // Last, so the archive holds the folder exactly as it ends up
+    if config.archive.enabled {
+        let zip_path = storypack_zip::write_archive(&project_path)?;
+        println!("Generated archive: {}", zip_path.display());
+        exports.push(zip_path.to_string_lossy().to_string());
     }
     
-    // Generate HTML and CSS
-    generate_html(&config, &project_path)?;
-    generate_css(&project_path)?;
+    for warning in &warnings {
+        println!("Warning: {}", warning);
+    }
     
-    println!("Generated HTML and CSS files");
+    let exports = staging.commit(&target_path, &exports, config.on_conflict)?;
+    println!("Storypack written to {}", target_path.display());
     
-    Ok(project_path.to_string_lossy().to_string())
+    // Only a committed build can be reused; the storypack is fine without it
+    if let Err(e) = cache.save(&target_path) {
+        println!("Warning: {}", e);
+    }
+    
+    Ok(GeneratedStorypack {
+        path: target_path.to_string_lossy().to_string(),
+        exports,
+        warnings,
+    })
+}
+
+#[tauri::command]
+pub async fn check_narration(config: StoryspackConfig) -> Result<NarrationReport, String> {
+    let pages = config.resolved_pages();
+    Ok(config.narration_report(&pages))
+}
+
+#[tauri::command]
+pub async fn validate_storypack(config: StoryspackConfig) -> Result<ValidationReport, String> {
+    Ok(storypack_validate::validate(&config))
+}
+
+#[tauri::command]
+pub async fn check_transcripts(config: StoryspackConfig) -> Result<Vec<String>, String> {
+    Ok(config.transcript_warnings())
 }
 
 #[tauri::command]
@@ -208,14 +791,11 @@ pub async fn open_folder(path: String) -> Result<(), String> {
 
 fn copy_file(source: &str, dest_folder: &Path, base_name: &str) -> Result<(), String> {
     let source_path = Path::new(source);
-    let extension = source_path.extension()
-        .and_then(|e| e.to_str())
-        .unwrap_or("");
     
-    let dest_name = if extension.is_empty() {
-        base_name.to_string()
-    } else {
-        format!("{}.{}", base_name, extension)
+    // Same sanitized extension the templates link to
+    let dest_name = match storypack_template::safe_extension(source) {
+        Some(extension) => format!("{}.{}", base_name, extension),
+        None => base_name.to_string(),
     };
     
     let dest_path = dest_folder.join(dest_name);
@@ -224,635 +804,4 @@ fn copy_file(source: &str, dest_folder: &Path, base_name: &str) -> Result<(), St
         .map_err(|e| format!("Failed to copy {}: {}", source, e))?;
     
     Ok(())
-}
-
-fn generate_html(config: &StoryspackConfig, project_path: &Path) -> Result<(), String> {
-    let html = create_html_template(config);
-    let html_path = project_path.join("index.html");
-    
-    fs::write(&html_path, html)
-        .map_err(|e| format!("Failed to write index.html: {}", e))?;
-    
-    Ok(())
-}
-
-fn generate_css(project_path: &Path) -> Result<(), String> {
-    let css = create_css_template();
-    let css_path = project_path.join("styles.css");
-    
-    fs::write(&css_path, css)
-        .map_err(|e| format!("Failed to write styles.css: {}", e))?;
-    
-    Ok(())
-}
-
-fn create_html_template(config: &StoryspackConfig) -> String {
-    let mut pages = Vec::new();
-    let mut audio_index = 0;
-    
-    // Helper function to get file extension
-    fn get_extension(path: &str) -> String {
-        Path::new(path)
-            .extension()
-            .and_then(|e| e.to_str())
-            .unwrap_or("jpg")
-            .to_string()
-    }
-    
-    // Helper to escape HTML but preserve <strong> tags
-    fn escape_html(text: &str) -> String {
-        // First escape everything
-        let escaped = text.replace('&', "&amp;")
-            .replace('<', "&lt;")
-            .replace('>', "&gt;")
-            .replace('"', "&quot;")
-            .replace('\'', "&#39;");
-        
-        // Then unescape <strong> and </strong> tags
-        escaped
-            .replace("&lt;strong&gt;", "<strong>")
-            .replace("&lt;/strong&gt;", "</strong>")
-    }
-    
-	// Helper to find matching transcription segment by page type
-	fn find_segment(transcriptions: &[String], page_type: &str) -> Option<String> {
-		for segment in transcriptions {
-			let segment_lower = segment.to_lowercase();
-			let page_type_lower = page_type.to_lowercase();
-			
-			// Check if segment contains the page type anywhere in the text
-			// This handles both formatted "<strong>Prologue" and unformatted "Prologue"
-			if segment_lower.contains(&format!("<strong>{}", page_type_lower)) ||
-			   segment_lower.contains(&format!("<strong>{}:", page_type_lower)) ||
-			   segment_lower.contains(&format!(" {}", page_type_lower)) ||
-			   segment_lower.contains(&format!(" {}.", page_type_lower)) {
-				return Some(segment.clone());
-			}
-		}
-		None
-	}
-    
-    // Helper to get audio element for current page
-    let get_audio = |idx: &mut usize| -> String {
-        if *idx < config.narration_audio.len() {
-            let audio_path = &config.narration_audio[*idx];
-            let ext = get_extension(audio_path);
-            *idx += 1;
-            format!(r#"<audio class="page-audio" src="assets/audio/narration{}.{}" preload="auto"></audio>"#, *idx, ext)
-        } else {
-            String::new()
-        }
-    };
-    
-    // Helper to get transcription text for a specific page type
-    let get_transcription_for_page = |page_type: &str| -> String {
-        if let Some(segment) = find_segment(&config.transcriptions, page_type) {
-            format!(r#"<p class="page-text">{}</p>"#, escape_html(&segment))
-        } else {
-            String::new()
-        }
-    };
-    
-    // Cover page - typically no text
-    if let Some(cover) = &config.cover_image {
-        let ext = get_extension(cover);
-        let audio = get_audio(&mut audio_index);
-        let transcription = get_transcription_for_page("cover");
-        pages.push(format!(r#"
-        <div class="page" data-page="cover">
-            <div class="page-content">
-                <img src="assets/images/cover.{}" alt="Cover" class="page-image" />
-                {}
-                {}
-            </div>
-        </div>"#, ext, transcription, audio));
-    }
-    
-    // Prologue page
-    if let Some(prologue) = &config.prologue_image {
-        let ext = get_extension(prologue);
-        let audio = get_audio(&mut audio_index);
-        let transcription = get_transcription_for_page("prologue");
-        pages.push(format!(r#"
-        <div class="page" data-page="prologue">
-            <div class="page-content">
-                <img src="assets/images/prologue.{}" alt="Prologue" class="page-image" />
-                {}
-                {}
-            </div>
-        </div>"#, ext, transcription, audio));
-    }
-    
-    // Chapter pages
-    for (i, chapter) in config.chapter_images.iter().enumerate() {
-        let ext = get_extension(chapter);
-        let audio = get_audio(&mut audio_index);
-        let chapter_num = i + 1;
-        let transcription = get_transcription_for_page(&format!("chapter {}", chapter_num));
-        let chapter_html = format!(r#"
-        <div class="page" data-page="chapter{}">
-            <div class="page-content">
-                <img src="assets/images/chapter{}.{}" alt="Chapter {}" class="page-image" />
-                {}
-                {}
-            </div>
-        </div>"#, chapter_num, chapter_num, ext, chapter_num, transcription, audio);
-        pages.push(chapter_html);
-    }
-    
-    // Epilogue page
-    if let Some(epilogue) = &config.epilogue_image {
-        let ext = get_extension(epilogue);
-        let audio = get_audio(&mut audio_index);
-        let transcription = get_transcription_for_page("epilogue");
-        pages.push(format!(r#"
-        <div class="page" data-page="epilogue">
-            <div class="page-content">
-                <img src="assets/images/epilogue.{}" alt="Epilogue" class="page-image" />
-                {}
-                {}
-            </div>
-        </div>"#, ext, transcription, audio));
-    }
-    
-    // Credits page - typically no text
-    if let Some(credits) = &config.credits_image {
-        let ext = get_extension(credits);
-        let audio = get_audio(&mut audio_index);
-        let transcription = get_transcription_for_page("credits");
-        pages.push(format!(r#"
-        <div class="page" data-page="credits">
-            <div class="page-content">
-                <img src="assets/images/credits.{}" alt="Credits" class="page-image" />
-                {}
-                {}
-            </div>
-        </div>"#, ext, transcription, audio));
-    }
-    
-    let pages_html = pages.join("\n");
-    
-    // Background music element
-    let theme_audio = if let Some(theme) = &config.theme_audio {
-        let ext = get_extension(theme);
-        format!(r#"<audio id="theme-music" src="assets/audio/theme.{}" loop></audio>"#, ext)
-    } else {
-        String::new()
-    };
-    
-    // Video element
-    let video_html = match &config.video_source {
-        Some(VideoSource::YouTube { video_id }) => {
-            format!(r#"
-            <div class="video-container">
-                <iframe 
-                    src="https://www.youtube.com/embed/{}" 
-                    frameborder="0" 
-                    allow="accelerometer; autoplay; clipboard-write; encrypted-media; gyroscope; picture-in-picture" 
-                    allowfullscreen>
-                </iframe>
-            </div>"#, video_id)
-        },
-        Some(VideoSource::Hosted { url }) => {
-            format!(r#"
-            <div class="video-container">
-                <video controls>
-                    <source src="{}" type="video/mp4">
-                    Your browser does not support the video tag.
-                </video>
-            </div>"#, url)
-        },
-        Some(VideoSource::Local { path: _ }) => {
-            let ext = config.video_source.as_ref()
-                .and_then(|v| match v {
-                    VideoSource::Local { path } => Some(get_extension(path)),
-                    _ => None
-                })
-                .unwrap_or_else(|| "mp4".to_string());
-            
-            format!(r#"
-            <div class="video-container">
-                <video controls>
-                    <source src="assets/video/video.{}" type="video/mp4">
-                    Your browser does not support the video tag.
-                </video>
-            </div>"#, ext)
-        },
-        None => String::new()
-    };
-    
-    format!(r#"<!DOCTYPE html>
-<html lang="en">
-<head>
-    <meta charset="UTF-8">
-    <meta name="viewport" content="width=device-width, initial-scale=1.0">
-    <title>{}</title>
-    <link rel="stylesheet" href="styles.css">
-</head>
-<body>
-    {}
-    
-    <div class="book-container">
-        {}
-        <div class="pages-wrapper">
-            {}
-        </div>
-        
-        <div class="navigation">
-            <button id="prev-btn" class="nav-btn" disabled>◀ Previous</button>
-            <span id="page-indicator">Page 1</span>
-            <button id="next-btn" class="nav-btn">Next ▶</button>
-        </div>
-        
-        <div class="audio-controls">
-            <button id="play-narration" class="audio-btn" title="Play narration">🔊 Narration</button>
-            <button id="toggle-music" class="audio-btn" title="Toggle background music">🎵 Music</button>
-        </div>
-    </div>
-    
-    <script>
-        let currentPage = 0;
-        const pages = document.querySelectorAll('.page');
-        const totalPages = pages.length;
-        const prevBtn = document.getElementById('prev-btn');
-        const nextBtn = document.getElementById('next-btn');
-        const pageIndicator = document.getElementById('page-indicator');
-        const playNarrationBtn = document.getElementById('play-narration');
-        const toggleMusicBtn = document.getElementById('toggle-music');
-        const themeMusic = document.getElementById('theme-music');
-        
-        let musicPlaying = false;
-        let currentlyPlayingAudio = null;
-        
-        // Check if storypack has any narration at all
-        const hasAnyNarration = Array.from(pages).some(page => 
-            page.querySelector('.page-audio') !== null
-        );
-        
-        function updateNarrationButton() {{
-            if (!hasAnyNarration) {{
-                playNarrationBtn.style.display = 'none';
-                return;
-            }}
-            
-            // Always show button if storypack has narration
-            playNarrationBtn.style.display = 'block';
-            
-            // Find any currently playing audio
-            let playingAudio = null;
-            pages.forEach(page => {{
-                const audio = page.querySelector('.page-audio');
-                if (audio && !audio.paused) {{
-                    playingAudio = audio;
-                }}
-            }});
-            
-            if (playingAudio) {{
-                playNarrationBtn.textContent = '⏸️ Narration';
-                currentlyPlayingAudio = playingAudio;
-            }} else {{
-                playNarrationBtn.textContent = '🔊 Narration';
-                currentlyPlayingAudio = null;
-            }}
-        }}
-        
-        function showPage(index) {{
-            pages.forEach((page, i) => {{
-                page.classList.toggle('active', i === index);
-            }});
-            
-            currentPage = index;
-            prevBtn.disabled = index === 0;
-            nextBtn.disabled = index === totalPages - 1;
-            pageIndicator.textContent = `Page ${{index + 1}} of ${{totalPages}}`;
-            
-            updateNarrationButton();
-        }}
-        
-        function toggleNarration() {{
-            // If something is playing, pause it
-            if (currentlyPlayingAudio && !currentlyPlayingAudio.paused) {{
-                currentlyPlayingAudio.pause();
-                playNarrationBtn.textContent = '🔊 Narration';
-                currentlyPlayingAudio = null;
-                return;
-            }}
-            
-            // Find next page with audio, starting from current page
-            let audioToPlay = null;
-            let searchIndex = currentPage;
-            
-            // First try current page
-            audioToPlay = pages[currentPage].querySelector('.page-audio');
-            
-            // If current page has no audio, find the next page with audio
-            if (!audioToPlay) {{
-                for (let i = 1; i <= totalPages; i++) {{
-                    const nextIndex = (currentPage + i) % totalPages;
-                    audioToPlay = pages[nextIndex].querySelector('.page-audio');
-                    if (audioToPlay) {{
-                        searchIndex = nextIndex;
-                        break;
-                    }}
-                }}
-            }}
-            
-            if (!audioToPlay) return;
-            
-            // Jump to that page and play
-            if (searchIndex !== currentPage) {{
-                showPage(searchIndex);
-            }}
-            
-            audioToPlay.currentTime = 0;
-            const playPromise = audioToPlay.play();
-            
-            if (playPromise !== undefined) {{
-                playPromise.then(() => {{
-                    currentlyPlayingAudio = audioToPlay;
-                    playNarrationBtn.textContent = '⏸️ Narration';
-                }}).catch(error => {{
-                    console.log('Play interrupted:', error);
-                }});
-            }}
-        }}
-        
-        function toggleMusic() {{
-            if (!themeMusic) return;
-            
-            if (musicPlaying) {{
-                themeMusic.pause();
-                toggleMusicBtn.textContent = '🎵 Music';
-                musicPlaying = false;
-            }} else {{
-                themeMusic.play();
-                toggleMusicBtn.textContent = '⏸️ Music';
-                musicPlaying = true;
-            }}
-        }}
-        
-        prevBtn.addEventListener('click', () => {{
-            if (currentPage > 0) {{
-                showPage(currentPage - 1);
-            }}
-        }});
-        
-        nextBtn.addEventListener('click', () => {{
-            if (currentPage < totalPages - 1) {{
-                showPage(currentPage + 1);
-            }}
-        }});
-        
-        playNarrationBtn.addEventListener('click', toggleNarration);
-        toggleMusicBtn.addEventListener('click', toggleMusic);
-        
-        // Only listen for 'ended' event to clean up
-        pages.forEach(page => {{
-            const audio = page.querySelector('.page-audio');
-            if (audio) {{
-                audio.addEventListener('ended', () => {{
-                    currentlyPlayingAudio = null;
-                    updateNarrationButton();
-                }});
-            }}
-        }});
-        
-        // Hide music button if no theme music
-        if (!themeMusic) {{
-            toggleMusicBtn.style.display = 'none';
-        }}
-        
-        // Keyboard navigation
-        document.addEventListener('keydown', (e) => {{
-            if (e.key === 'ArrowLeft' && currentPage > 0) {{
-                showPage(currentPage - 1);
-            }} else if (e.key === 'ArrowRight' && currentPage < totalPages - 1) {{
-                showPage(currentPage + 1);
-            }} else if (e.key === ' ') {{
-                e.preventDefault();
-                toggleNarration();
-            }}
-        }});
-        
-        // Initialize
-        showPage(0);
-    </script>
-</body>
-</html>"#, config.project_name, theme_audio, video_html, pages_html)
-}
-
-fn create_css_template() -> String {
-    r#"* {
-    margin: 0;
-    padding: 0;
-    box-sizing: border-box;
-}
-
-body {
-    font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, Oxygen, Ubuntu, Cantarell, sans-serif;
-    background: linear-gradient(135deg, #667eea 0%, #764ba2 100%);
-    min-height: 100vh;
-    display: flex;
-    justify-content: center;
-    align-items: center;
-    padding: 20px;
-}
-
-.book-container {
-    background: white;
-    border-radius: 12px;
-    box-shadow: 0 20px 60px rgba(0, 0, 0, 0.3);
-    max-width: 900px;
-    width: 100%;
-    overflow: hidden;
-}
-
-.video-container {
-    position: relative;
-    width: 100%;
-    padding-top: 56.25%;
-    background: #000;
-}
-
-.video-container iframe,
-.video-container video {
-    position: absolute;
-    top: 0;
-    left: 0;
-    width: 100%;
-    height: 100%;
-}
-
-.pages-wrapper {
-    position: relative;
-    width: 100%;
-    min-height: 600px;
-    background: #f5f5f5;
-}
-
-.page {
-    display: none;
-    width: 100%;
-    min-height: 600px;
-}
-
-.page.active {
-    display: flex;
-}
-
-.page-content {
-    width: 100%;
-    display: flex;
-    justify-content: center;
-    align-items: center;
-    padding: 20px;
-    flex-direction: column;
-    gap: 20px;
-}
-
-.page-image {
-    max-width: 100%;
-    max-height: 450px;
-    object-fit: contain;
-    border-radius: 4px;
-}
-
-.page-text {
-    max-width: 700px;
-    width: 100%;
-    padding: 25px 30px;
-    background: white;
-    border-radius: 8px;
-    box-shadow: 0 2px 8px rgba(0, 0, 0, 0.1);
-    font-size: 17px;
-    line-height: 1.8;
-    color: #333;
-    text-align: left;
-    white-space: pre-wrap;
-    word-wrap: break-word;
-    font-family: Georgia, 'Times New Roman', serif;
-}
-
-.page-text strong {
-    display: block;
-    font-size: 19px;
-    color: #667eea;
-    margin-bottom: 15px;
-    font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, sans-serif;
-}
-
-.page-audio {
-    display: none;
-}
-
-.navigation {
-    display: flex;
-    justify-content: space-between;
-    align-items: center;
-    padding: 20px 30px;
-    background: white;
-    border-top: 1px solid #e0e0e0;
-}
-
-.nav-btn {
-    background: #667eea;
-    color: white;
-    border: none;
-    padding: 12px 24px;
-    border-radius: 6px;
-    cursor: pointer;
-    font-size: 14px;
-    font-weight: 500;
-    transition: all 0.3s ease;
-}
-
-.nav-btn:hover:not(:disabled) {
-    background: #5568d3;
-    transform: translateY(-2px);
-    box-shadow: 0 4px 12px rgba(102, 126, 234, 0.4);
-}
-
-.nav-btn:disabled {
-    background: #ccc;
-    cursor: not-allowed;
-    transform: none;
-}
-
-#page-indicator {
-    font-size: 14px;
-    color: #666;
-    font-weight: 500;
-}
-
-.audio-controls {
-    display: flex;
-    justify-content: center;
-    gap: 12px;
-    padding: 15px;
-    background: #f8f8f8;
-    border-top: 1px solid #e0e0e0;
-}
-
-.audio-btn {
-    background: #764ba2;
-    color: white;
-    border: none;
-    padding: 10px 20px;
-    border-radius: 6px;
-    cursor: pointer;
-    font-size: 13px;
-    font-weight: 500;
-    transition: all 0.3s ease;
-}
-
-.audio-btn:hover {
-    background: #5e3882;
-    transform: translateY(-2px);
-}
-
-@media (max-width: 768px) {
-    .book-container {
-        border-radius: 0;
-    }
-    
-    .pages-wrapper {
-        min-height: 400px;
-    }
-    
-    .page {
-        min-height: 400px;
-    }
-    
-    .page-image {
-        max-height: 300px;
-    }
-    
-    .page-text {
-        font-size: 15px;
-        padding: 20px 25px;
-    }
-    
-    .navigation {
-        padding: 15px 20px;
-    }
-    
-    .nav-btn {
-        padding: 10px 16px;
-        font-size: 12px;
-    }
-    
-    #page-indicator {
-        font-size: 12px;
-    }
-    
-    .audio-controls {
-        padding: 12px;
-        gap: 8px;
-    }
-    
-    .audio-btn {
-        padding: 8px 16px;
-        font-size: 12px;
-    }
-}"#.to_string()
 }
\ No newline at end of file
@@ -0,0 +1,220 @@
+use std::path::Path;
+use crate::commands::media_tools::{self, MediaTool};
+
+/// How narration and theme music are prepared for the web.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct AudioOptions {
+    /// Transcode audio; when false files are copied as-is
+    pub transcode: bool,
+    pub format: AudioFormat,
+    pub bitrate_kbps: u32,
+    /// Also write an MP3 for browsers without `format` support
+    pub mp3_fallback: bool,
+    pub fallback_bitrate_kbps: u32,
+    /// Bring every narration clip to `loudness_target`
+    pub normalize_narration: bool,
+    /// Integrated loudness in LUFS
+    pub loudness_target: f64,
+    /// Cut leading and trailing silence from narration
+    pub trim_silence: bool,
+}
+
+impl Default for AudioOptions {
+    fn default() -> Self {
+        AudioOptions {
+            transcode: false,
+            format: AudioFormat::Opus,
+            bitrate_kbps: 64,
+            mp3_fallback: true,
+            fallback_bitrate_kbps: 128,
+            normalize_narration: true,
+            loudness_target: -16.0,
+            trim_silence: true,
+        }
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioFormat {
+    /// Opus in WebM
+    Opus,
+    Mp3,
+    /// AAC in M4A
+    Aac,
+}
+
+impl AudioFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            AudioFormat::Opus => "webm",
+            AudioFormat::Mp3 => "mp3",
+            AudioFormat::Aac => "m4a",
+        }
+    }
+
+    fn mime(self) -> &'static str {
+        match self {
+            AudioFormat::Opus => "audio/webm",
+            AudioFormat::Mp3 => "audio/mpeg",
+            AudioFormat::Aac => "audio/mp4",
+        }
+    }
+
+    fn encoder(self) -> &'static str {
+        match self {
+            AudioFormat::Opus => "libopus",
+            AudioFormat::Mp3 => "libmp3lame",
+            AudioFormat::Aac => "aac",
+        }
+    }
+
+    fn sample_rate(self) -> &'static str {
+        match self {
+            AudioFormat::Opus => "48000",
+            AudioFormat::Mp3 | AudioFormat::Aac => "44100",
+        }
+    }
+}
+
+/// One `<source>` of an `<audio>` element.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct AudioSource {
+    pub src: String,
+    pub mime: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AudioKind {
+    Narration,
+    Music,
+}
+
+/// Transcodes narration and theme music. Create one per storypack so the
+/// encoder check runs once.
+pub struct AudioPipeline {
+    options: AudioOptions,
+    outputs: Vec<(AudioFormat, u32)>,
+}
+
+impl AudioPipeline {
+    /// Returns `None` when transcoding is turned off.
+    pub fn new(options: &AudioOptions) -> Result<Option<Self>, String> {
+        if !options.transcode {
+            return Ok(None);
+        }
+
+        let mut wanted = vec![(options.format, options.bitrate_kbps)];
+        if options.mp3_fallback && options.format != AudioFormat::Mp3 {
+            wanted.push((AudioFormat::Mp3, options.fallback_bitrate_kbps));
+        }
+
+        let encoders = media_tools::available_encoders()?;
+        let mut outputs = Vec::new();
+
+        for (format, bitrate) in wanted {
+            if encoders.iter().any(|e| e == format.encoder()) {
+                outputs.push((format, bitrate.max(8)));
+            } else {
+                println!(
+                    "Warning: FFmpeg has no {} encoder, skipping {} audio",
+                    format.encoder(),
+                    format.extension()
+                );
+            }
+        }
+
+        if outputs.is_empty() {
+            return Err("FFmpeg has none of the encoders needed for the chosen audio format".to_string());
+        }
+
+        Ok(Some(AudioPipeline {
+            options: options.clone(),
+            outputs,
+        }))
+    }
+
+    /// Writes `<base_name>.<ext>` for every output format into `dest_folder`,
+    /// in one FFmpeg run. Sources are returned in `<source>` order.
+    pub fn process(
+        &self,
+        source: &str,
+        dest_folder: &Path,
+        base_name: &str,
+        url_prefix: &str,
+        kind: AudioKind,
+    ) -> Result<Vec<AudioSource>, String> {
+        let mut filters = Vec::new();
+
+        if kind == AudioKind::Narration {
+            if self.options.trim_silence {
+                // silenceremove only trims the start, so run it on the reversed clip for the end
+                let trim = "silenceremove=start_periods=1:start_threshold=-50dB:start_silence=0.1";
+                filters.push(format!("{},areverse,{},areverse", trim, trim));
+            }
+
+            if self.options.normalize_narration {
+                filters.push(format!("loudnorm=I={}:TP=-1.5:LRA=11", self.options.loudness_target));
+            }
+        }
+
+        let split_labels: Vec<String> = (0..self.outputs.len()).map(|i| format!("[out{}]", i)).collect();
+        filters.push(format!("asplit={}{}", self.outputs.len(), split_labels.concat()));
+
+        let mut command = media_tools::ffmpeg();
+        command
+            .args(["-y", "-v", "error", "-i", source])
+            .args(["-filter_complex", &format!("[0:a:0]{}", filters.join(","))]);
+
+        let mut sources = Vec::new();
+
+        for ((format, bitrate), label) in self.outputs.iter().zip(&split_labels) {
+            let file_name = format!("{}.{}", base_name, format.extension());
+
+            command
+                .args(["-map", label, "-map_metadata", "-1", "-vn"])
+                .args(["-c:a", format.encoder(), "-b:a", &format!("{}k", bitrate)])
+                .args(["-ar", format.sample_rate()])
+                .arg(dest_folder.join(&file_name));
+
+            sources.push(AudioSource {
+                src: format!("{}/{}", url_prefix, file_name),
+                mime: format.mime().to_string(),
+            });
+        }
+
+        let output = command
+            .output()
+            .map_err(|e| media_tools::spawn_error(MediaTool::FFmpeg, e))?;
+
+        if output.status.success() {
+            Ok(sources)
+        } else {
+            Err(format!(
+                "Failed to transcode {}: {}",
+                source,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ))
+        }
+    }
+}
+
+/// MIME type for an audio file copied without transcoding.
+pub fn audio_mime(path: &str) -> &'static str {
+    let extension = Path::new(path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase())
+        .unwrap_or_default();
+
+    match extension.as_str() {
+        "mp3" => "audio/mpeg",
+        "m4a" | "aac" | "mp4" => "audio/mp4",
+        "ogg" | "oga" | "opus" => "audio/ogg",
+        "webm" => "audio/webm",
+        "wav" => "audio/wav",
+        "flac" => "audio/flac",
+        _ => "audio/mpeg",
+    }
+}
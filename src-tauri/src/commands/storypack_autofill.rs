@@ -0,0 +1,582 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::AppHandle;
+use crate::commands::storypack::{NarrationAssignment, PageKind, StoryspackConfig, VideoSource};
+use crate::commands::storypack_validate::{
+    AUDIO_CONVERTIBLE, AUDIO_FORMATS, IMAGE_CONVERTIBLE, IMAGE_FORMATS, VIDEO_CONVERTIBLE, VIDEO_FORMATS,
+};
+
+// Whole words in file names that name a page: `cover.png`, `ch3.png`,
+// `01-prologue.jpg`
+const PAGE_WORDS: &[(PageKind, &[&str])] = &[
+    (PageKind::Cover, &["cover", "front", "title"]),
+    (PageKind::Prologue, &["prologue", "intro", "introduction", "preface"]),
+    (PageKind::Chapter, &["chapter", "chap", "ch", "page", "pg", "scene", "part"]),
+    (PageKind::Epilogue, &["epilogue", "outro", "ending", "afterword"]),
+    (PageKind::Credits, &["credits", "credit", "thanks", "acknowledgements"]),
+];
+
+// Best match first, so `theme.ogg` wins over `bgm-alt.mp3`
+const THEME_WORDS: &[&str] = &["theme", "music", "soundtrack", "bgm", "background", "score"];
+
+// Subfolders such as `audio/` or `art/chapters/` are picked up too
+const SCAN_DEPTH: usize = 2;
+
+/// How sure autofill is about one placement.
+#[derive(serde::Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Confidence {
+    /// The name says what the file is: `cover.png`, `chapter-3.png`
+    High,
+    /// Placed by its number alone: `03.png`, `narration_03.mp3`
+    Medium,
+    /// A guess, e.g. the only image without a page name used as the cover
+    Low,
+}
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct AutofillMatch {
+    /// Relative to the scanned folder
+    pub file: String,
+    /// Config field it was put in, e.g. `chapter_images[2]`
+    pub field: String,
+    pub confidence: Confidence,
+    pub reason: String,
+}
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct UnplacedFile {
+    pub file: String,
+    pub reason: String,
+}
+
+/// A config proposed from a folder of assets, with what went where.
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct AutofillReport {
+    pub config: StoryspackConfig,
+    pub matched: Vec<AutofillMatch>,
+    pub unplaced: Vec<UnplacedFile>,
+    pub warnings: Vec<String>,
+}
+
+// A file and what its name says about it
+struct Asset {
+    path: PathBuf,
+    name: String,
+    role: Option<PageKind>,
+    /// The chapter number for chapters, otherwise the first number in the
+    /// name (`01-prologue` -> 1)
+    number: Option<u32>,
+    /// Position of the theme word in `THEME_WORDS`
+    theme: Option<usize>,
+}
+
+/// Proposes a storypack from the images, audio and video in `folder`.
+/// Pages are recognised by name (`cover`, `prologue`, `chapter-3`, ...) or
+/// by number (`03.png` is chapter 3), chapters are ordered numerically and
+/// narration goes to the page its name or number points at.
+pub fn autofill(folder: &Path) -> Result<AutofillReport, String> {
+    if !folder.is_dir() {
+        return Err(format!("{} is not a folder", folder.display()));
+    }
+
+    let mut files = Vec::new();
+    collect_files(folder, folder, 0, &mut files)?;
+    files.sort_by(|a, b| natural_cmp(&sort_name(a), &sort_name(b)));
+
+    let mut report = AutofillReport {
+        config: StoryspackConfig {
+            project_name: project_name(folder),
+            ..StoryspackConfig::default()
+        },
+        matched: Vec::new(),
+        unplaced: Vec::new(),
+        warnings: Vec::new(),
+    };
+
+    let (mut images, mut audio, mut videos) = (Vec::new(), Vec::new(), Vec::new());
+    for asset in files {
+        let extension = asset.path.extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        let is = |formats: &[&str], convertible: &[&str]| {
+            formats.contains(&extension.as_str()) || convertible.contains(&extension.as_str())
+        };
+
+        // `.webm` holds audio or video; one named for a page or as theme
+        // music is taken for audio, e.g. `chapter-3.webm` narration
+        let audio_name = asset.role.is_some() || asset.theme.is_some();
+
+        if is(IMAGE_FORMATS, IMAGE_CONVERTIBLE) {
+            images.push(asset);
+        } else if audio_name && is(AUDIO_FORMATS, AUDIO_CONVERTIBLE) {
+            audio.push(asset);
+        } else if is(VIDEO_FORMATS, VIDEO_CONVERTIBLE) {
+            videos.push(asset);
+        } else if is(AUDIO_FORMATS, AUDIO_CONVERTIBLE) {
+            audio.push(asset);
+        } else {
+            report.unplace(&asset, "not an image, audio or video file".to_string());
+        }
+    }
+
+    let pages = place_images(&images, &mut report);
+    place_audio(&audio, &pages, &mut report);
+    place_video(&videos, &mut report);
+
+    Ok(report)
+}
+
+#[tauri::command]
+pub async fn autofill_storypack_from_folder(folder: String) -> Result<AutofillReport, String> {
+    autofill(Path::new(&folder))
+}
+
+#[tauri::command]
+pub async fn select_asset_folder(app: AppHandle) -> Result<Option<String>, String> {
+    use tauri_plugin_dialog::DialogExt;
+
+    let folder = app.dialog()
+        .file()
+        .set_title("Select Folder of Story Assets")
+        .blocking_pick_folder();
+
+    Ok(folder.map(|p| p.to_string()))
+}
+
+impl AutofillReport {
+    fn place(&mut self, asset: &Asset, field: String, confidence: Confidence, reason: String) {
+        self.matched.push(AutofillMatch { file: asset.name.clone(), field, confidence, reason });
+    }
+
+    fn unplace(&mut self, asset: &Asset, reason: String) {
+        self.unplaced.push(UnplacedFile { file: asset.name.clone(), reason });
+    }
+}
+
+// Page ids the placed images ended up with, for matching narration
+struct PlacedPages {
+    /// Cover, prologue, epilogue and credits
+    single: Vec<PageKind>,
+    /// Chapter number -> page id
+    chapters: HashMap<u32, String>,
+    /// Leading numbers on named pages (`01-prologue`) -> page id
+    numbered: HashMap<u32, String>,
+}
+
+fn place_images(images: &[Asset], report: &mut AutofillReport) -> PlacedPages {
+    let mut single: Vec<(PageKind, &Asset, Confidence, String)> = Vec::new();
+    let mut chapters: Vec<(&Asset, Confidence, String)> = Vec::new();
+    let mut unnamed = Vec::new();
+
+    for image in images {
+        match (image.role, image.number) {
+            (Some(PageKind::Chapter), _) => chapters.push((image, Confidence::High, "named as a chapter".to_string())),
+            (Some(kind), _) => match single.iter().find(|(placed, ..)| *placed == kind) {
+                Some((_, other, ..)) => {
+                    let reason = format!("{} is already the {} image", other.name, label(kind));
+                    report.unplace(image, reason);
+                }
+                None => single.push((kind, image, Confidence::High, format!("named as the {}", label(kind)))),
+            },
+            (None, Some(number)) => chapters.push((image, Confidence::Medium, format!("numbered {} without a page name", number))),
+            (None, None) => unnamed.push(image),
+        }
+    }
+
+    // Without a named cover, the first image that doesn't name a page is
+    // the likeliest one
+    let mut unnamed = unnamed.into_iter();
+    if !single.iter().any(|(kind, ..)| *kind == PageKind::Cover) {
+        if let Some(image) = unnamed.next() {
+            single.push((PageKind::Cover, image, Confidence::Low, "first image without a page name or number".to_string()));
+        } else {
+            report.warnings.push("No cover image found; choose one before generating".to_string());
+        }
+    }
+    for image in unnamed {
+        report.unplace(image, "the name doesn't say which page it is".to_string());
+    }
+
+    // Numeric order, a named chapter ahead of a bare number for the same
+    // chapter, unnumbered chapters last in name order
+    let rank = |confidence: Confidence| confidence != Confidence::High;
+    chapters.sort_by_key(|(image, confidence, _)| (image.number.is_none(), image.number, rank(*confidence)));
+
+    let mut placed = PlacedPages {
+        single: Vec::new(),
+        chapters: HashMap::new(),
+        numbered: HashMap::new(),
+    };
+
+    for (kind, image, confidence, reason) in single {
+        let (field, target) = match kind {
+            PageKind::Cover => ("cover_image", &mut report.config.cover_image),
+            PageKind::Prologue => ("prologue_image", &mut report.config.prologue_image),
+            PageKind::Epilogue => ("epilogue_image", &mut report.config.epilogue_image),
+            _ => ("credits_image", &mut report.config.credits_image),
+        };
+        *target = Some(image.path.to_string_lossy().to_string());
+
+        if let Some(number) = image.number {
+            placed.numbered.insert(number, kind.as_str().to_string());
+        }
+        placed.single.push(kind);
+        report.place(image, field.to_string(), confidence, reason);
+    }
+
+    for (image, confidence, reason) in chapters {
+        if let Some(number) = image.number {
+            if placed.chapters.contains_key(&number) {
+                report.unplace(image, format!("another image is already chapter {}", number));
+                continue;
+            }
+        }
+
+        let idx = report.config.chapter_images.len();
+        let id = format!("chapter{}", idx + 1);
+        if let Some(number) = image.number {
+            placed.chapters.insert(number, id.clone());
+        }
+
+        report.config.chapter_images.push(image.path.to_string_lossy().to_string());
+        report.place(image, format!("chapter_images[{}]", idx), confidence, format!("{} (page \"{}\")", reason, id));
+    }
+
+    // Gaps usually mean a file is missing or misnamed
+    let mut numbers: Vec<u32> = placed.chapters.keys().copied().collect();
+    numbers.sort_unstable();
+    for pair in numbers.windows(2) {
+        let (first, last) = (pair[0] + 1, pair[1] - 1);
+        match first.cmp(&last) {
+            Ordering::Equal => report.warnings.push(format!("No image for chapter {}", first)),
+            Ordering::Less => report.warnings.push(format!("No images for chapters {}-{}", first, last)),
+            Ordering::Greater => {}
+        }
+    }
+
+    placed
+}
+
+fn place_audio(audio: &[Asset], pages: &PlacedPages, report: &mut AutofillReport) {
+    let is_theme = |clip: &Asset| clip.theme.is_some() && clip.role.is_none();
+    let theme = audio.iter().filter(|clip| is_theme(clip)).min_by_key(|clip| clip.theme);
+
+    if let Some(theme) = theme {
+        report.config.theme_audio = Some(theme.path.to_string_lossy().to_string());
+        report.place(theme, "theme_audio".to_string(), Confidence::High, "named as theme music".to_string());
+    }
+
+    for clip in audio {
+        if is_theme(clip) {
+            if let Some(theme) = theme.filter(|theme| !std::ptr::eq(*theme, clip)) {
+                report.unplace(clip, format!("{} is already the theme music", theme.name));
+            }
+            continue;
+        }
+
+        let target = match (clip.role, clip.number) {
+            (Some(PageKind::Chapter), Some(number)) => pages.chapters.get(&number)
+                .map(|id| (id.clone(), Confidence::High, format!("named for chapter {}", number)))
+                .ok_or_else(|| format!("there is no image for chapter {}", number)),
+            (Some(PageKind::Chapter), None) => Err("it names a chapter but not which one".to_string()),
+            (Some(kind), _) if pages.single.contains(&kind) => {
+                Ok((kind.as_str().to_string(), Confidence::High, format!("named for the {}", label(kind))))
+            }
+            (Some(kind), _) => Err(format!("there is no {} image", label(kind))),
+            (None, Some(number)) => pages.chapters.get(&number)
+                .map(|id| (id.clone(), format!("number {} matches chapter {}", number, number)))
+                .or_else(|| pages.numbered.get(&number).map(|id| (id.clone(), format!("number {} matches the {} image", number, id))))
+                .map(|(id, reason)| (id, Confidence::Medium, reason))
+                .ok_or_else(|| format!("no page is numbered {}", number)),
+            (None, None) => Err("the name doesn't say which page it is".to_string()),
+        };
+
+        match target {
+            Ok((page_id, confidence, reason)) => {
+                let idx = report.config.narration.len();
+                report.config.narration.push(NarrationAssignment {
+                    page_id: page_id.clone(),
+                    path: clip.path.to_string_lossy().to_string(),
+                });
+                report.place(clip, format!("narration[{}]", idx), confidence, format!("{} (page \"{}\")", reason, page_id));
+            }
+            Err(reason) => report.unplace(clip, reason),
+        }
+    }
+}
+
+fn place_video(videos: &[Asset], report: &mut AutofillReport) {
+    let Some((first, rest)) = videos.split_first() else { return };
+
+    report.config.video_source = Some(VideoSource::Local { path: first.path.to_string_lossy().to_string() });
+    let reason = if rest.is_empty() {
+        "the only video in the folder".to_string()
+    } else {
+        format!("first of {} videos", videos.len())
+    };
+    report.place(first, "video_source".to_string(), Confidence::Medium, reason);
+
+    for video in rest {
+        report.unplace(video, "a storypack has one video".to_string());
+    }
+}
+
+fn collect_files(root: &Path, folder: &Path, depth: usize, files: &mut Vec<Asset>) -> Result<(), String> {
+    let entries = fs::read_dir(folder)
+        .map_err(|e| format!("Failed to read {}: {}", folder.display(), e))?;
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let hidden = entry.file_name().to_string_lossy().starts_with('.');
+        let Ok(file_type) = entry.file_type() else { continue };
+
+        if hidden {
+            continue;
+        } else if file_type.is_dir() {
+            if depth < SCAN_DEPTH {
+                collect_files(root, &path, depth + 1, files)?;
+            }
+        } else if file_type.is_file() {
+            files.push(describe(root, path));
+        }
+    }
+
+    Ok(())
+}
+
+fn describe(root: &Path, path: PathBuf) -> Asset {
+    let name = path.strip_prefix(root)
+        .unwrap_or(&path)
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().to_string())
+        .collect::<Vec<_>>()
+        .join("/");
+    let stem = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+    let words = words(&stem);
+
+    let role = words.iter().enumerate().find_map(|(idx, word)| {
+        PAGE_WORDS.iter()
+            .find(|(_, names)| names.contains(&word.as_str()))
+            .map(|(kind, _)| (idx, *kind))
+    });
+
+    // A chapter's number follows the word (`chapter-3`, `ch03_v2`);
+    // anywhere else the first number is the page's position
+    let numbers_from = match role {
+        Some((idx, PageKind::Chapter)) => idx + 1,
+        _ => 0,
+    };
+    let number = words[numbers_from.min(words.len())..].iter()
+        .find(|word| word.starts_with(|c: char| c.is_ascii_digit()))
+        .and_then(|word| word.parse().ok());
+
+    Asset {
+        theme: THEME_WORDS.iter().position(|theme| words.iter().any(|word| word == theme)),
+        role: role.map(|(_, kind)| kind),
+        number,
+        name,
+        path,
+    }
+}
+
+// Lowercase runs of letters or digits: `Chapter03_final` -> chapter, 03, final
+fn words(stem: &str) -> Vec<String> {
+    let mut words: Vec<String> = Vec::new();
+    let mut previous: Option<bool> = None;
+
+    for c in stem.chars() {
+        if !c.is_alphanumeric() {
+            previous = None;
+            continue;
+        }
+
+        let digit = c.is_ascii_digit();
+        match words.last_mut() {
+            Some(word) if previous == Some(digit) => word.extend(c.to_lowercase()),
+            _ => words.push(c.to_lowercase().collect()),
+        }
+        previous = Some(digit);
+    }
+
+    words
+}
+
+// Without the extension, so `epilogue.png` sorts before `epilogue_old.png`
+fn sort_name(asset: &Asset) -> String {
+    match asset.name.rsplit_once('.') {
+        Some((stem, _)) if !stem.is_empty() && !stem.ends_with('/') => stem.to_string(),
+        _ => asset.name.clone(),
+    }
+}
+
+// Numbers compare by value, so `chapter-10` sorts after `chapter-9`
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (a_words, b_words) = (words(a), words(b));
+
+    for (x, y) in a_words.iter().zip(&b_words) {
+        let order = match (x.parse::<u64>(), y.parse::<u64>()) {
+            (Ok(x), Ok(y)) => x.cmp(&y),
+            _ => x.cmp(y),
+        };
+        if order != Ordering::Equal {
+            return order;
+        }
+    }
+
+    a_words.len().cmp(&b_words.len()).then_with(|| a.cmp(b))
+}
+
+// `my_story-pack` -> `my story pack`
+fn project_name(folder: &Path) -> String {
+    folder.file_name()
+        .map(|name| name.to_string_lossy().replace(['_', '-'], " "))
+        .map(|name| name.split_whitespace().collect::<Vec<_>>().join(" "))
+        .unwrap_or_default()
+}
+
+fn label(kind: PageKind) -> &'static str {
+    match kind {
+        PageKind::Cover => "cover",
+        PageKind::Prologue => "prologue",
+        PageKind::Epilogue => "epilogue",
+        PageKind::Credits => "credits",
+        _ => "chapter",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Empty files in `<temp>/<test>/my_story-pack`, removed again on drop
+    struct Fixture(PathBuf);
+
+    impl Fixture {
+        fn new(test: &str, files: &[&str]) -> Self {
+            let root = std::env::temp_dir().join(format!("storypack-autofill-{}-{}", test, std::process::id()));
+            let folder = root.join("my_story-pack");
+            fs::create_dir_all(&folder).unwrap();
+
+            for file in files {
+                fs::write(folder.join(file), b"").unwrap();
+            }
+
+            Fixture(root)
+        }
+
+        fn folder(&self) -> PathBuf {
+            self.0.join("my_story-pack")
+        }
+
+        fn path(&self, file: &str) -> String {
+            self.folder().join(file).to_string_lossy().to_string()
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn matched<'a>(report: &'a AutofillReport, file: &str) -> &'a AutofillMatch {
+        report.matched.iter()
+            .find(|m| m.file == file)
+            .unwrap_or_else(|| panic!("{} was not placed", file))
+    }
+
+    #[test]
+    fn words_split_letters_from_digits() {
+        assert_eq!(words("Chapter03_final"), ["chapter", "03", "final"]);
+        assert_eq!(words("01-prologue"), ["01", "prologue"]);
+        assert_eq!(words("narration_03"), ["narration", "03"]);
+        assert_eq!(words("ch2v2"), ["ch", "2", "v", "2"]);
+        assert!(words("--_").is_empty());
+    }
+
+    #[test]
+    fn natural_cmp_orders_numbers_by_value() {
+        assert_eq!(natural_cmp("chapter-9", "chapter-10"), Ordering::Less);
+        assert_eq!(natural_cmp("chapter-10", "chapter-9"), Ordering::Greater);
+        assert_eq!(natural_cmp("epilogue", "epilogue_old"), Ordering::Less);
+        // Equal words fall back to the raw names, so the order is stable
+        assert_eq!(natural_cmp("Chapter01", "chapter-1"), Ordering::Less);
+        assert_eq!(natural_cmp("cover", "cover"), Ordering::Equal);
+
+        let mut names = vec!["ch10", "ch2", "ch1", "ch02b"];
+        names.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(names, ["ch1", "ch2", "ch02b", "ch10"]);
+    }
+
+    #[test]
+    fn autofill_maps_the_illustrator_folder() {
+        let fixture = Fixture::new("example", &[
+            "cover.png", "01-prologue.jpg", "chapter-3.png", "narration_03.mp3", "theme.ogg",
+        ]);
+        let report = autofill(&fixture.folder()).unwrap();
+        let config = &report.config;
+
+        assert_eq!(config.project_name, "my story pack");
+        assert_eq!(config.cover_image, Some(fixture.path("cover.png")));
+        assert_eq!(config.prologue_image, Some(fixture.path("01-prologue.jpg")));
+        assert_eq!(config.chapter_images, [fixture.path("chapter-3.png")]);
+        assert_eq!(config.theme_audio, Some(fixture.path("theme.ogg")));
+
+        // Chapter 3 is the first chapter page, so its narration goes to chapter1
+        assert_eq!(config.narration.len(), 1);
+        assert_eq!(config.narration[0].page_id, "chapter1");
+        assert_eq!(config.narration[0].path, fixture.path("narration_03.mp3"));
+
+        assert_eq!(matched(&report, "cover.png").confidence, Confidence::High);
+        assert_eq!(matched(&report, "01-prologue.jpg").field, "prologue_image");
+        assert_eq!(matched(&report, "chapter-3.png").field, "chapter_images[0]");
+        assert_eq!(matched(&report, "narration_03.mp3").confidence, Confidence::Medium);
+        assert!(report.unplaced.is_empty());
+        assert!(report.warnings.is_empty());
+    }
+
+    #[test]
+    fn autofill_reports_gaps_and_duplicates() {
+        let fixture = Fixture::new("gaps", &[
+            "cover.png", "01-prologue.jpg", "Chapter01.png", "chapter-1.png", "chapter-3.png",
+            "chapter-6.png", "narration_03.mp3", "theme.ogg", "epilogue.png", "epilogue_old.png",
+        ]);
+        let report = autofill(&fixture.folder()).unwrap();
+
+        assert_eq!(report.config.chapter_images, [
+            fixture.path("Chapter01.png"),
+            fixture.path("chapter-3.png"),
+            fixture.path("chapter-6.png"),
+        ]);
+        assert_eq!(report.config.epilogue_image, Some(fixture.path("epilogue.png")));
+        assert_eq!(report.config.narration[0].page_id, "chapter2");
+
+        let unplaced: Vec<(&str, &str)> = report.unplaced.iter()
+            .map(|file| (file.file.as_str(), file.reason.as_str()))
+            .collect();
+        assert!(unplaced.contains(&("epilogue_old.png", "epilogue.png is already the epilogue image")));
+        assert!(unplaced.contains(&("chapter-1.png", "another image is already chapter 1")));
+        assert_eq!(unplaced.len(), 2);
+
+        assert_eq!(report.warnings, ["No image for chapter 2", "No images for chapters 4-5"]);
+    }
+
+    #[test]
+    fn autofill_takes_webm_named_for_a_page_as_narration() {
+        let fixture = Fixture::new("webm", &["chapter-3.png", "chapter-3.webm", "trailer.webm"]);
+        let report = autofill(&fixture.folder()).unwrap();
+        let config = &report.config;
+
+        assert_eq!(config.narration.len(), 1);
+        assert_eq!(config.narration[0].page_id, "chapter1");
+        assert_eq!(config.narration[0].path, fixture.path("chapter-3.webm"));
+        assert!(matches!(
+            &config.video_source,
+            Some(VideoSource::Local { path }) if *path == fixture.path("trailer.webm")
+        ));
+        assert!(report.unplaced.is_empty());
+    }
+}
@@ -0,0 +1,268 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tauri::{AppHandle, Manager};
+use crate::commands::storypack_zip;
+
+// Bump when the manifest layout changes; older manifests are ignored
+const MANIFEST_VERSION: u32 = 1;
+
+/// Lists every copied or converted asset of a storypack with its source
+/// fingerprint, so the next run can reuse outputs whose source and settings
+/// haven't changed. Kept in the app cache, keyed by the storypack folder, so
+/// source paths and hashes never end up in the folder that gets shared.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Default)]
+struct BuildManifest {
+    version: u32,
+    /// Outputs from another generator version are never reused
+    generator: String,
+    /// Keyed by output base name, e.g. `images/cover` or `audio/theme`
+    assets: BTreeMap<String, AssetRecord>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+struct AssetRecord {
+    source: SourceFingerprint,
+    /// Pipeline options the outputs were made with
+    settings: serde_json::Value,
+    /// Files written for this asset, relative to the storypack folder
+    outputs: Vec<String>,
+    /// What the pipeline returned, handed back when the outputs are reused
+    result: serde_json::Value,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+struct SourceFingerprint {
+    path: String,
+    size: u64,
+    /// Milliseconds since the Unix epoch
+    mtime: u64,
+    sha256: String,
+}
+
+/// Reuses asset outputs from the previous build of the same storypack.
+pub struct BuildCache {
+    manifest_dir: PathBuf,
+    previous_root: Option<PathBuf>,
+    previous: BuildManifest,
+    current: BuildManifest,
+    reused: usize,
+    built: usize,
+}
+
+impl BuildCache {
+    /// Reads the manifest of the storypack in `previous_root` from
+    /// `manifest_dir`, if there is one from this generator version.
+    pub fn load(manifest_dir: &Path, previous_root: Option<&Path>) -> Self {
+        let generator = env!("CARGO_PKG_VERSION").to_string();
+
+        let previous = previous_root
+            .and_then(|root| fs::read_to_string(manifest_path(manifest_dir, root)).ok())
+            .and_then(|json| serde_json::from_str::<BuildManifest>(&json).ok())
+            .filter(|manifest| manifest.version == MANIFEST_VERSION && manifest.generator == generator)
+            .unwrap_or_default();
+
+        BuildCache {
+            manifest_dir: manifest_dir.to_path_buf(),
+            previous_root: previous_root.map(Path::to_path_buf).filter(|_| !previous.assets.is_empty()),
+            previous,
+            current: BuildManifest {
+                version: MANIFEST_VERSION,
+                generator,
+                assets: BTreeMap::new(),
+            },
+            reused: 0,
+            built: 0,
+        }
+    }
+
+    /// Produces the asset `key` from `source` into `project_path`. When the
+    /// previous build made it from the same content (even if the file was
+    /// moved or touched since) with the same `settings`, its outputs are
+    /// linked or copied over and its result is returned; otherwise `build`
+    /// runs and whatever files it writes are recorded as the outputs.
+    /// `key` is `<folder>/<name>`, and `build` writes to `assets/<folder>`
+    /// with file or folder names starting with `<name>`.
+    pub fn asset<T, F>(
+        &mut self,
+        key: &str,
+        source: &str,
+        settings: serde_json::Value,
+        project_path: &Path,
+        build: F,
+    ) -> Result<T, String>
+    where
+        T: Serialize + DeserializeOwned,
+        F: FnOnce() -> Result<T, String>,
+    {
+        let previous = self.previous.assets.get(key);
+        let fingerprint = fingerprint(source, previous.map(|record| &record.source))?;
+
+        let unchanged = previous.filter(|record| {
+            record.source.sha256 == fingerprint.sha256 && record.settings == settings
+        });
+
+        if let Some(record) = unchanged {
+            if let Some(result) = self.reuse(record, project_path) {
+                let record = AssetRecord { source: fingerprint, ..record.clone() };
+                self.current.assets.insert(key.to_string(), record);
+                self.reused += 1;
+                return Ok(result);
+            }
+        }
+
+        let before = list_outputs(project_path, key)?;
+        let result = build()?;
+        let mut outputs: Vec<String> = list_outputs(project_path, key)?
+            .difference(&before)
+            .cloned()
+            .collect();
+        outputs.sort();
+
+        let record = AssetRecord {
+            source: fingerprint,
+            settings,
+            outputs,
+            result: serde_json::to_value(&result)
+                .map_err(|e| format!("Failed to record {}: {}", key, e))?,
+        };
+        self.current.assets.insert(key.to_string(), record);
+        self.built += 1;
+
+        Ok(result)
+    }
+
+    /// Records this build as the one in `root`, where the storypack ended up.
+    pub fn save(&self, root: &Path) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(&self.current)
+            .map_err(|e| format!("Failed to serialize build manifest: {}", e))?;
+
+        fs::write(manifest_path(&self.manifest_dir, root), json)
+            .map_err(|e| format!("Failed to write build manifest: {}", e))
+    }
+
+    /// Assets reused from the previous build and assets built this time.
+    pub fn counts(&self) -> (usize, usize) {
+        (self.reused, self.built)
+    }
+
+    // Brings over every output of `record`, or nothing if one is missing
+    fn reuse<T: DeserializeOwned>(&self, record: &AssetRecord, project_path: &Path) -> Option<T> {
+        let previous_root = self.previous_root.as_ref()?;
+        let result = serde_json::from_value(record.result.clone()).ok()?;
+
+        if !record.outputs.iter().all(|output| previous_root.join(output).is_file()) {
+            return None;
+        }
+
+        for output in &record.outputs {
+            let from = previous_root.join(output);
+            let to = project_path.join(output);
+
+            if let Some(parent) = to.parent() {
+                fs::create_dir_all(parent).ok()?;
+            }
+
+            // A hard link costs nothing; copy across file systems
+            if fs::hard_link(&from, &to).is_err() {
+                fs::copy(&from, &to).ok()?;
+            }
+        }
+
+        Some(result)
+    }
+}
+
+pub fn manifest_dir(app: &AppHandle) -> Result<PathBuf, String> {
+    let dir = app.path()
+        .app_cache_dir()
+        .map_err(|e| format!("Failed to resolve cache directory: {}", e))?
+        .join("storypack-builds");
+
+    fs::create_dir_all(&dir)
+        .map_err(|e| format!("Failed to create build cache folder: {}", e))?;
+
+    Ok(dir)
+}
+
+// One manifest per storypack folder, named after a hash of its path
+fn manifest_path(manifest_dir: &Path, root: &Path) -> PathBuf {
+    let digest = Sha256::digest(root.to_string_lossy().as_bytes());
+    let name: String = digest[..8].iter().map(|byte| format!("{:02x}", byte)).collect();
+    manifest_dir.join(format!("{}.json", name))
+}
+
+// Size and mtime decide whether the file needs hashing again; an unchanged
+// file keeps its recorded hash
+fn fingerprint(source: &str, previous: Option<&SourceFingerprint>) -> Result<SourceFingerprint, String> {
+    let metadata = fs::metadata(source)
+        .map_err(|e| format!("Failed to read {}: {}", source, e))?;
+    let mtime = metadata.modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or(0);
+
+    let unchanged = previous.filter(|previous| {
+        previous.path == source && previous.size == metadata.len() && previous.mtime == mtime && mtime != 0
+    });
+
+    let sha256 = match unchanged {
+        Some(previous) => previous.sha256.clone(),
+        None => storypack_zip::sha256_file(Path::new(source))
+            .map_err(|e| format!("Failed to hash {}: {}", source, e))?,
+    };
+
+    Ok(SourceFingerprint {
+        path: source.to_string(),
+        size: metadata.len(),
+        mtime,
+        sha256,
+    })
+}
+
+// Files that may belong to `key`, relative to the storypack folder. Only its
+// assets folder is read, and only paths with a part that is `<name>` or
+// starts with `<name>-` or `<name>.` are kept, e.g. `images/cover-960.webp`
+// or `video/hls/cover/720p/index.m3u8`, but not `images/cover2.webp`.
+fn list_outputs(project_path: &Path, key: &str) -> Result<HashSet<String>, String> {
+    let (folder, name) = key.split_once('/').unwrap_or(("", key));
+    let dir = project_path.join("assets").join(folder);
+
+    if !dir.is_dir() {
+        return Ok(HashSet::new());
+    }
+
+    let files = storypack_zip::list_files(&dir)
+        .map_err(|e| format!("Failed to list {}: {}", dir.display(), e))?;
+
+    Ok(files.into_iter()
+        .filter(|file| file.split('/').any(|part| is_output_of(part, name)))
+        .map(|file| format!("assets/{}/{}", folder, file))
+        .collect())
+}
+
+fn is_output_of(part: &str, name: &str) -> bool {
+    match part.strip_prefix(name) {
+        Some(rest) => rest.is_empty() || rest.starts_with('-') || rest.starts_with('.'),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn outputs_match_the_whole_name() {
+        assert!(is_output_of("chapter1", "chapter1"));
+        assert!(is_output_of("chapter1-960.webp", "chapter1"));
+        assert!(is_output_of("chapter1.png", "chapter1"));
+        assert!(!is_output_of("chapter10.png", "chapter1"));
+        assert!(!is_output_of("chapter11-960.webp", "chapter1"));
+    }
+}
@@ -0,0 +1,465 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::commands::media_tools;
+use crate::commands::storypack::{self, StoryspackConfig};
+use crate::commands::storypack_template::{self, PageContext, TemplateContext};
+use crate::commands::storypack_theme::{FontFaceContext, ThemeContext};
+use crate::commands::storypack_zip::{Compression, ZipWriter};
+
+const CONTAINER_XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+    <rootfiles>
+        <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+    </rootfiles>
+</container>
+"#;
+
+/// Fixed-layout EPUB 3 export, written next to the storypack folder.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct EpubOptions {
+    pub enabled: bool,
+    /// BCP 47 language tag
+    pub language: String,
+    pub author: Option<String>,
+    /// ISBN or other identifier; derived from the project name when empty
+    pub identifier: Option<String>,
+    /// Page size in CSS pixels
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Default for EpubOptions {
+    fn default() -> Self {
+        EpubOptions {
+            enabled: false,
+            language: "en".to_string(),
+            author: None,
+            identifier: None,
+            width: 1200,
+            height: 900,
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+struct EpubContext<'a> {
+    book: BookContext,
+    pages: Vec<EpubPage>,
+    manifest: Vec<ManifestItem>,
+    fonts: Vec<FontFaceContext>,
+    theme: &'a ThemeContext,
+}
+
+#[derive(serde::Serialize)]
+struct BookContext {
+    identifier: String,
+    title: String,
+    language: String,
+    author: Option<String>,
+    modified: String,
+    width: u32,
+    height: u32,
+    duration: Option<String>,
+    cover_id: Option<String>,
+    start_href: Option<String>,
+}
+
+#[derive(serde::Serialize, Clone)]
+struct EpubPage {
+    id: String,
+    kind: String,
+    title: String,
+    item_id: String,
+    href: String,
+    image: Option<EpubFile>,
+    text_xhtml: Option<String>,
+    audio: Vec<EpubClip>,
+    overlay: Option<OverlayContext>,
+}
+
+#[derive(serde::Serialize, Clone)]
+struct EpubFile {
+    id: String,
+    href: String,
+}
+
+#[derive(serde::Serialize, Clone)]
+struct EpubClip {
+    href: String,
+    duration: String,
+}
+
+#[derive(serde::Serialize, Clone)]
+struct OverlayContext {
+    id: String,
+    href: String,
+    /// Element the narration highlights
+    target: String,
+    duration: String,
+}
+
+#[derive(serde::Serialize)]
+struct ManifestItem {
+    id: String,
+    href: String,
+    media_type: String,
+    properties: Option<String>,
+    media_overlay: Option<String>,
+}
+
+/// Builds `<project>.epub` from the rendered storypack in `project_path`.
+/// Returns the EPUB path and warnings for the user.
+pub fn write_epub(
+    config: &StoryspackConfig,
+    context: &TemplateContext,
+    project_path: &Path,
+) -> Result<(PathBuf, Vec<String>), String> {
+    let options = &config.epub;
+    let mut warnings = Vec::new();
+    // (path inside OEBPS, file in the storypack folder)
+    let mut files: Vec<(String, PathBuf)> = Vec::new();
+    let mut manifest = Vec::new();
+    let mut pages = Vec::new();
+    let mut total_duration = 0.0;
+
+    for page in &context.pages {
+        let item_id = format!("page-{}", page.id);
+        let href = format!("pages/{}.xhtml", page.id);
+
+        let image = match &page.image {
+            Some(src) => {
+                let file = add_file(&mut files, &mut manifest, "images", &format!("img-{}", page.id), src, project_path)?;
+                Some(file)
+            }
+            None => None,
+        };
+
+        let mut audio = Vec::new();
+        let mut page_duration = 0.0;
+
+        for (idx, sources) in page.narration_sources.iter().enumerate() {
+            let source = sources.iter()
+                .find(|s| s.mime == "audio/mpeg")
+                .or_else(|| sources.iter().find(|s| s.mime == "audio/mp4"))
+                .or(sources.first());
+
+            let Some(source) = source else { continue };
+
+            if source.mime != "audio/mpeg" && source.mime != "audio/mp4" {
+                warnings.push(format!(
+                    "Narration for \"{}\" is {}, which not all e-readers play; transcode audio to include an MP3",
+                    page.title, source.mime
+                ));
+            }
+
+            let local = project_path.join(&source.src);
+            let duration = media_tools::probe_duration(&local.to_string_lossy())?;
+            page_duration += duration;
+
+            let file = add_file(&mut files, &mut manifest, "audio", &format!("audio-{}-{}", page.id, idx + 1), &source.src, project_path)?;
+            audio.push(EpubClip { href: file.href, duration: clock_value(duration) });
+        }
+
+        let text_xhtml = text_xhtml(page);
+
+        let overlay = if audio.is_empty() {
+            None
+        } else {
+            total_duration += page_duration;
+
+            let target = if text_xhtml.is_some() {
+                "text"
+            } else if image.is_some() {
+                "image"
+            } else {
+                "page"
+            };
+
+            Some(OverlayContext {
+                id: format!("overlay-{}", page.id),
+                href: format!("overlays/{}.smil", page.id),
+                target: target.to_string(),
+                duration: clock_value(page_duration),
+            })
+        };
+
+        if page.video.is_some() {
+            warnings.push(format!("The video on \"{}\" is not included in the EPUB", page.title));
+        }
+
+        pages.push(EpubPage {
+            id: page.id.clone(),
+            kind: page.kind.clone(),
+            title: page.title.clone(),
+            item_id,
+            href,
+            image,
+            text_xhtml,
+            audio,
+            overlay,
+        });
+    }
+
+    if context.video.is_some() {
+        warnings.push("The storypack video is not included in the EPUB".to_string());
+    }
+
+    // Cover image: the cover page's image, otherwise the first image
+    let cover_id = pages.iter()
+        .find(|page| page.kind == "cover" && page.image.is_some())
+        .or_else(|| pages.iter().find(|page| page.image.is_some()))
+        .and_then(|page| page.image.as_ref())
+        .map(|image| image.id.clone());
+
+    if let Some(cover_id) = &cover_id {
+        if let Some(item) = manifest.iter_mut().find(|item| &item.id == cover_id) {
+            item.properties = Some("cover-image".to_string());
+        }
+    }
+
+    let mut fonts = Vec::new();
+    for face in &context.theme.font_faces {
+        let file_name = face.src.rsplit('/').next().unwrap_or(&face.src);
+        let font_id = format!("font-{}", sanitize_id(file_name));
+        let file = add_file(&mut files, &mut manifest, "fonts", &font_id, &face.src, project_path)?;
+        fonts.push(FontFaceContext { src: file.href, ..face.clone() });
+    }
+
+    for page in &pages {
+        manifest.push(ManifestItem {
+            id: page.item_id.clone(),
+            href: page.href.clone(),
+            media_type: "application/xhtml+xml".to_string(),
+            properties: None,
+            media_overlay: page.overlay.as_ref().map(|overlay| overlay.id.clone()),
+        });
+
+        if let Some(overlay) = &page.overlay {
+            manifest.push(ManifestItem {
+                id: overlay.id.clone(),
+                href: overlay.href.clone(),
+                media_type: "application/smil+xml".to_string(),
+                properties: None,
+                media_overlay: None,
+            });
+        }
+    }
+
+    let start_href = pages.iter()
+        .find(|page| page.kind != "cover")
+        .map(|page| page.href.clone());
+
+    let book = BookContext {
+        identifier: options.identifier.clone()
+            .filter(|id| !id.trim().is_empty())
+            .unwrap_or_else(|| format!("urn:clipforge:storypack:{}", sanitize_id(&context.project.title))),
+        title: context.project.title.clone(),
+        language: options.language.clone(),
+        author: options.author.clone().filter(|author| !author.trim().is_empty()),
+        modified: utc_timestamp(SystemTime::now()),
+        width: options.width.max(1),
+        height: options.height.max(1),
+        duration: if total_duration > 0.0 { Some(clock_value(total_duration)) } else { None },
+        cover_id,
+        start_href,
+    };
+
+    let epub_context = EpubContext {
+        book,
+        pages,
+        manifest,
+        fonts,
+        theme: &context.theme,
+    };
+
+    let env = storypack_template::environment(config.template_dir.as_deref().map(PathBuf::from));
+    let epub_path = storypack::export_path(project_path, ".epub");
+    let write_error = |e: std::io::Error| format!("Failed to write EPUB: {}", e);
+
+    let file = File::create(&epub_path).map_err(write_error)?;
+    let mut zip = ZipWriter::new(BufWriter::new(file));
+
+    // The mimetype entry must come first and be stored uncompressed
+    zip.add("mimetype", b"application/epub+zip", Compression::Stored).map_err(write_error)?;
+    zip.add("META-INF/container.xml", CONTAINER_XML.as_bytes(), Compression::Deflated).map_err(write_error)?;
+
+    let opf = storypack_template::render(&env, "epub/content.opf", &epub_context)?;
+    zip.add("OEBPS/content.opf", opf.as_bytes(), Compression::Deflated).map_err(write_error)?;
+
+    let nav = storypack_template::render(&env, "epub/nav.xhtml", &epub_context)?;
+    zip.add("OEBPS/nav.xhtml", nav.as_bytes(), Compression::Deflated).map_err(write_error)?;
+
+    let style = storypack_template::render(&env, "epub/style.css", &epub_context)?;
+    zip.add("OEBPS/style.css", style.as_bytes(), Compression::Deflated).map_err(write_error)?;
+
+    for page in &epub_context.pages {
+        let page_context = minijinja::context! { book => &epub_context.book, page => page };
+
+        let xhtml = storypack_template::render(&env, "epub/page.xhtml", &page_context)?;
+        zip.add(&format!("OEBPS/{}", page.href), xhtml.as_bytes(), Compression::Deflated).map_err(write_error)?;
+
+        if let Some(overlay) = &page.overlay {
+            let smil = storypack_template::render(&env, "epub/overlay.smil", &page_context)?;
+            zip.add(&format!("OEBPS/{}", overlay.href), smil.as_bytes(), Compression::Deflated).map_err(write_error)?;
+        }
+    }
+
+    for (href, source) in &files {
+        zip.add_file(&format!("OEBPS/{}", href), source, Compression::for_path(href)).map_err(write_error)?;
+    }
+
+    zip.finish().map_err(write_error)?;
+
+    Ok((epub_path, warnings))
+}
+
+// Adds a storypack file to the manifest once, keyed by its path
+fn add_file(
+    files: &mut Vec<(String, PathBuf)>,
+    manifest: &mut Vec<ManifestItem>,
+    folder: &str,
+    id: &str,
+    src: &str,
+    project_path: &Path,
+) -> Result<EpubFile, String> {
+    let file_name = src.rsplit('/').next().unwrap_or(src);
+    let href = format!("{}/{}", folder, file_name);
+
+    if let Some(existing) = manifest.iter().find(|item| item.href == href) {
+        return Ok(EpubFile { id: existing.id.clone(), href });
+    }
+
+    let source = project_path.join(src);
+    if !source.is_file() {
+        return Err(format!("Missing file for EPUB: {}", src));
+    }
+
+    let media_type = media_type(file_name);
+    manifest.push(ManifestItem {
+        id: id.to_string(),
+        href: href.clone(),
+        media_type: media_type.to_string(),
+        properties: None,
+        media_overlay: None,
+    });
+    files.push((href.clone(), source));
+
+    Ok(EpubFile { id: id.to_string(), href })
+}
+
+// Page text as well-formed XHTML: <strong> headings survive only when balanced
+fn text_xhtml(page: &PageContext) -> Option<String> {
+    let html = page.text_html.as_ref()?;
+
+    let opens = html.matches("<strong>").count();
+    let closes = html.matches("</strong>").count();
+
+    if opens == closes && balanced_strong(html) {
+        Some(html.clone())
+    } else {
+        page.text.as_ref().map(|text| storypack_template::escape_markup(text))
+    }
+}
+
+fn balanced_strong(html: &str) -> bool {
+    let mut open = false;
+    let mut rest = html;
+
+    while let Some(pos) = rest.find("<strong>").into_iter().chain(rest.find("</strong>")).min() {
+        let closing = rest[pos..].starts_with("</strong>");
+        if closing != open {
+            return false;
+        }
+        open = !open;
+        rest = &rest[pos + if closing { 9 } else { 8 }..];
+    }
+
+    !open
+}
+
+fn media_type(file_name: &str) -> &'static str {
+    let extension = Path::new(file_name)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase())
+        .unwrap_or_default();
+
+    match extension.as_str() {
+        "jpg" | "jpeg" => "image/jpeg",
+        "png" => "image/png",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "svg" => "image/svg+xml",
+        "avif" => "image/avif",
+        "mp3" => "audio/mpeg",
+        "m4a" | "aac" => "audio/mp4",
+        "ogg" | "opus" => "audio/ogg",
+        "webm" => "audio/webm",
+        "wav" => "audio/wav",
+        "woff2" => "font/woff2",
+        "woff" => "font/woff",
+        "ttf" => "font/ttf",
+        "otf" => "font/otf",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Lowercase ASCII letters, digits and single dashes, for manifest ids and
+/// URNs; callers add a letter prefix where the id must be an XML name.
+pub fn sanitize_id(text: &str) -> String {
+    let cleaned: String = text
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+
+    let collapsed = cleaned
+        .split('-')
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-");
+
+    if collapsed.is_empty() {
+        "storypack".to_string()
+    } else {
+        collapsed
+    }
+}
+
+/// SMIL clock value, e.g. `0:01:02.500`.
+fn clock_value(seconds: f64) -> String {
+    let millis = (seconds.max(0.0) * 1000.0).round() as u64;
+    format!(
+        "{}:{:02}:{:02}.{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
+
+/// `2024-05-01T12:00:00Z` for `time`.
+pub fn utc_timestamp(time: SystemTime) -> String {
+    let seconds = time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let (days, rest) = (seconds / 86_400, seconds % 86_400);
+
+    // Civil-from-days (Howard Hinnant)
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year, month, day,
+        rest / 3600, rest / 60 % 60, rest % 60
+    )
+}
@@ -0,0 +1,331 @@
+use std::path::Path;
+use crate::commands::media_tools::{self, MediaTool};
+
+/// How page images are prepared for the web.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ImageOptions {
+    /// Resize and re-encode images; when false they are copied as-is
+    pub optimize: bool,
+    pub max_width: u32,
+    pub max_height: u32,
+    /// `srcset` widths; widths larger than the resized image are skipped
+    pub widths: Vec<u32>,
+    pub webp: bool,
+    pub avif: bool,
+    /// 1-100, applied to every format
+    pub quality: u32,
+}
+
+impl Default for ImageOptions {
+    fn default() -> Self {
+        ImageOptions {
+            optimize: true,
+            max_width: 1920,
+            max_height: 1920,
+            widths: vec![480, 960, 1920],
+            webp: true,
+            avif: true,
+            quality: 80,
+        }
+    }
+}
+
+/// An optimized image as the templates see it (`page.picture`).
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct ResponsiveImage {
+    /// Largest fallback image, for `<img src>`
+    pub src: String,
+    pub srcset: String,
+    pub mime: String,
+    pub width: u32,
+    pub height: u32,
+    /// Modern formats for `<source>`, best first
+    pub sources: Vec<ImageSource>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct ImageSource {
+    pub mime: String,
+    pub srcset: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ImageFormat {
+    Avif,
+    WebP,
+    Jpeg,
+    Png,
+}
+
+impl ImageFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            ImageFormat::Avif => "avif",
+            ImageFormat::WebP => "webp",
+            ImageFormat::Jpeg => "jpg",
+            ImageFormat::Png => "png",
+        }
+    }
+
+    fn mime(self) -> &'static str {
+        match self {
+            ImageFormat::Avif => "image/avif",
+            ImageFormat::WebP => "image/webp",
+            ImageFormat::Jpeg => "image/jpeg",
+            ImageFormat::Png => "image/png",
+        }
+    }
+
+    fn encoder(self) -> &'static str {
+        match self {
+            ImageFormat::Avif => "libaom-av1",
+            ImageFormat::WebP => "libwebp",
+            ImageFormat::Jpeg => "mjpeg",
+            ImageFormat::Png => "png",
+        }
+    }
+
+    fn codec_args(self, quality: u32) -> Vec<String> {
+        let quality = quality.clamp(1, 100);
+
+        match self {
+            ImageFormat::Avif => vec![
+                "-c:v".into(), "libaom-av1".into(),
+                "-still-picture".into(), "1".into(),
+                "-cpu-used".into(), "6".into(),
+                "-crf".into(), (15 + (100 - quality) * 48 / 100).to_string(),
+            ],
+            ImageFormat::WebP => vec![
+                "-c:v".into(), "libwebp".into(),
+                "-quality".into(), quality.to_string(),
+            ],
+            ImageFormat::Jpeg => vec![
+                "-c:v".into(), "mjpeg".into(),
+                "-pix_fmt".into(), "yuvj420p".into(),
+                "-q:v".into(), (2 + (100 - quality) * 15 / 100).to_string(),
+            ],
+            ImageFormat::Png => vec![
+                "-c:v".into(), "png".into(),
+                "-pix_fmt".into(), "rgba".into(),
+            ],
+        }
+    }
+}
+
+/// Resizes and re-encodes page images. Create one per storypack so the
+/// encoder check runs once.
+pub struct ImagePipeline {
+    options: ImageOptions,
+    modern_formats: Vec<ImageFormat>,
+}
+
+impl ImagePipeline {
+    /// Returns `None` when optimization is turned off.
+    pub fn new(options: &ImageOptions) -> Result<Option<Self>, String> {
+        if !options.optimize {
+            return Ok(None);
+        }
+
+        // Copying still produces a working pack, so a missing FFmpeg isn't fatal
+        if media_tools::locate(MediaTool::FFmpeg).is_none() {
+            println!("Warning: FFmpeg was not found, copying images without optimizing them");
+            return Ok(None);
+        }
+
+        let encoders = media_tools::available_encoders()?;
+        let mut modern_formats = Vec::new();
+
+        for (wanted, format) in [(options.avif, ImageFormat::Avif), (options.webp, ImageFormat::WebP)] {
+            if !wanted {
+                continue;
+            }
+
+            if encoders.iter().any(|e| e == format.encoder()) {
+                modern_formats.push(format);
+            } else {
+                println!(
+                    "Warning: FFmpeg has no {} encoder, skipping {} images",
+                    format.encoder(),
+                    format.extension()
+                );
+            }
+        }
+
+        Ok(Some(ImagePipeline {
+            options: options.clone(),
+            modern_formats,
+        }))
+    }
+
+    /// Whether `source` goes through the pipeline. Animated GIFs and SVGs
+    /// are copied unchanged.
+    pub fn handles(source: &str) -> bool {
+        let extension = Path::new(source)
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase())
+            .unwrap_or_default();
+
+        !matches!(extension.as_str(), "gif" | "svg")
+    }
+
+    /// Writes `<base_name>-<width>.<ext>` variants into `dest_folder`.
+    /// `url_prefix` is the folder's URL relative to index.html.
+    pub fn process(
+        &self,
+        source: &str,
+        dest_folder: &Path,
+        base_name: &str,
+        url_prefix: &str,
+    ) -> Result<ResponsiveImage, String> {
+        let info = probe_image(source)?;
+        let (width, height) = fit_within(info.width, info.height, self.options.max_width, self.options.max_height);
+
+        let mut widths: Vec<u32> = self.options.widths.iter()
+            .copied()
+            .filter(|w| *w > 0 && *w < width)
+            .collect();
+        widths.push(width);
+        widths.sort_unstable();
+        widths.dedup();
+
+        let sizes: Vec<(u32, u32)> = widths.iter()
+            .map(|&w| (w, ((w as u64 * height as u64 + width as u64 / 2) / width as u64).max(1) as u32))
+            .collect();
+
+        // JPEG has no transparency, and FFmpeg's AVIF encoder drops it
+        let fallback = if info.has_alpha { ImageFormat::Png } else { ImageFormat::Jpeg };
+        let modern: Vec<ImageFormat> = self.modern_formats.iter()
+            .copied()
+            .filter(|f| !(info.has_alpha && *f == ImageFormat::Avif))
+            .collect();
+
+        let mut sources = Vec::new();
+
+        for format in &modern {
+            let srcset = self.encode_variants(source, dest_folder, base_name, url_prefix, *format, &sizes)?;
+            sources.push(ImageSource { mime: format.mime().to_string(), srcset });
+        }
+
+        let srcset = self.encode_variants(source, dest_folder, base_name, url_prefix, fallback, &sizes)?;
+
+        Ok(ResponsiveImage {
+            src: format!("{}/{}-{}.{}", url_prefix, base_name, width, fallback.extension()),
+            srcset,
+            mime: fallback.mime().to_string(),
+            width,
+            height,
+            sources,
+        })
+    }
+
+    fn encode_variants(
+        &self,
+        source: &str,
+        dest_folder: &Path,
+        base_name: &str,
+        url_prefix: &str,
+        format: ImageFormat,
+        sizes: &[(u32, u32)],
+    ) -> Result<String, String> {
+        let mut entries = Vec::new();
+
+        for &(variant_width, variant_height) in sizes {
+            let file_name = format!("{}-{}.{}", base_name, variant_width, format.extension());
+
+            encode(
+                source,
+                &dest_folder.join(&file_name),
+                variant_width,
+                variant_height,
+                &format.codec_args(self.options.quality),
+            )?;
+
+            entries.push(format!("{}/{} {}w", url_prefix, file_name, variant_width));
+        }
+
+        Ok(entries.join(", "))
+    }
+}
+
+struct ImageInfo {
+    width: u32,
+    height: u32,
+    has_alpha: bool,
+}
+
+fn probe_image(source: &str) -> Result<ImageInfo, String> {
+    let output = media_tools::ffprobe()
+        .args([
+            "-v", "error",
+            "-select_streams", "v:0",
+            "-show_entries", "stream=width,height,pix_fmt",
+            "-of", "csv=p=0",
+            source,
+        ])
+        .output()
+        .map_err(|e| media_tools::spawn_error(MediaTool::FFprobe, e))?;
+
+    if !output.status.success() {
+        return Err(format!("Failed to read image {}", source));
+    }
+
+    // "3000,2000,rgba"
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut fields = stdout.trim().split(',');
+
+    let width = fields.next().and_then(|w| w.trim().parse().ok());
+    let height = fields.next().and_then(|h| h.trim().parse().ok());
+    let pix_fmt = fields.next().unwrap_or("").trim();
+
+    match (width, height) {
+        (Some(width), Some(height)) if width > 0 && height > 0 => Ok(ImageInfo {
+            width,
+            height,
+            has_alpha: has_alpha(pix_fmt),
+        }),
+        _ => Err(format!("Failed to read image size of {}", source)),
+    }
+}
+
+// Palette images may carry transparency, so they count as alpha
+fn has_alpha(pix_fmt: &str) -> bool {
+    const ALPHA_PREFIXES: &[&str] = &["rgba", "bgra", "argb", "abgr", "yuva", "gbrap", "ya"];
+
+    pix_fmt == "pal8" || ALPHA_PREFIXES.iter().any(|prefix| pix_fmt.starts_with(prefix))
+}
+
+// Never upscales
+fn fit_within(width: u32, height: u32, max_width: u32, max_height: u32) -> (u32, u32) {
+    let scale_w = if max_width > 0 { max_width as f64 / width as f64 } else { 1.0 };
+    let scale_h = if max_height > 0 { max_height as f64 / height as f64 } else { 1.0 };
+    let scale = scale_w.min(scale_h).min(1.0);
+
+    (
+        ((width as f64 * scale).round() as u32).max(1),
+        ((height as f64 * scale).round() as u32).max(1),
+    )
+}
+
+fn encode(source: &str, dest: &Path, width: u32, height: u32, codec_args: &[String]) -> Result<(), String> {
+    let output = media_tools::ffmpeg()
+        .args(["-y", "-v", "error", "-i", source])
+        .args(["-vf", &format!("scale={}:{}:flags=lanczos", width, height)])
+        // Drops EXIF (including GPS) and any other container metadata
+        .args(["-map_metadata", "-1", "-frames:v", "1"])
+        .args(codec_args)
+        .arg(dest)
+        .output()
+        .map_err(|e| media_tools::spawn_error(MediaTool::FFmpeg, e))?;
+
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(format!(
+            "Failed to convert {}: {}",
+            source,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+    }
+}
@@ -0,0 +1,359 @@
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use crate::commands::storypack::{self, Page, PageKind, StoryspackConfig, VideoSource};
+use crate::commands::storypack_manifest::{self, StorypackManifest};
+use crate::commands::storypack_output::ConflictPolicy;
+use crate::commands::storypack_theme::ThemeConfig;
+use crate::commands::storypack_validate;
+use crate::commands::transcript::Transcript;
+
+/// A generated storypack read back into a config that regenerates it.
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct ImportedStorypack {
+    pub config: StoryspackConfig,
+    /// Folder the storypack sits in; generate into it to replace the pack
+    pub output_folder: String,
+    /// `storypack.json`, or `index.html` for packs written before it existed
+    pub source: String,
+    pub warnings: Vec<String>,
+}
+
+/// Reads the storypack in `folder`. Asset paths in the config point at the
+/// files inside it, so images, narration and text can be swapped or fixed
+/// and the pack generated again in place. The assets are already web
+/// outputs, so image optimization and video transcoding are switched off.
+pub fn import(folder: &Path) -> Result<ImportedStorypack, String> {
+    if !folder.is_dir() {
+        return Err(format!("{} is not a folder", folder.display()));
+    }
+
+    let output_folder = folder.parent()
+        .ok_or_else(|| format!("{} has no parent folder", folder.display()))?;
+    let mut warnings = Vec::new();
+
+    let manifest_path = folder.join(storypack_manifest::MANIFEST_FILE);
+    let (mut config, source) = if manifest_path.is_file() {
+        let config = from_manifest(&manifest_path, folder, &mut warnings)?;
+        (config, storypack_manifest::MANIFEST_FILE)
+    } else {
+        let config = from_index_html(folder, &mut warnings)?;
+        (config, "index.html")
+    };
+
+    // Single-file packs keep their assets in index.html only
+    let index = fs::read_to_string(folder.join("index.html")).unwrap_or_default();
+    if index.contains("src=\"data:") {
+        warnings.push("Assets embedded in a single-file index.html can't be imported; select them again".to_string());
+    }
+
+    config.images.optimize = false;
+    config.video.transcode = false;
+    config.on_conflict = ConflictPolicy::OverwriteClean;
+
+    // Regenerating only replaces this folder if the name maps back to it
+    let folder_name = folder.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    match storypack::project_folder_name(&config.project_name) {
+        Ok(name) if name != folder_name => warnings.push(format!(
+            "The folder is named \"{}\" but the project name \"{}\" generates \"{}\"; rename one of them to regenerate in place",
+            folder_name, config.project_name, name
+        )),
+        Ok(_) => {}
+        Err(e) => warnings.push(e),
+    }
+
+    for warning in &warnings {
+        println!("Warning: {}", warning);
+    }
+
+    Ok(ImportedStorypack {
+        config,
+        output_folder: output_folder.to_string_lossy().to_string(),
+        source: source.to_string(),
+        warnings,
+    })
+}
+
+#[tauri::command]
+pub async fn import_storypack(folder: String) -> Result<ImportedStorypack, String> {
+    import(Path::new(&folder))
+}
+
+fn from_manifest(path: &Path, folder: &Path, warnings: &mut Vec<String>) -> Result<StoryspackConfig, String> {
+    let json = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let manifest: StorypackManifest = serde_json::from_str(&json)
+        .map_err(|e| format!("{} is not a valid storypack manifest: {}", path.display(), e))?;
+
+    if manifest.format != storypack_manifest::MANIFEST_FORMAT {
+        return Err(format!("{} is not a storypack manifest (format \"{}\")", path.display(), manifest.format));
+    }
+
+    if manifest.version > storypack_manifest::MANIFEST_VERSION {
+        return Err(format!(
+            "{} was written by a newer version ({}, manifest version {}); update ClipForge to import it",
+            path.display(), manifest.generator, manifest.version
+        ));
+    }
+
+    let mut files = PackFiles { folder, warnings };
+    let mut pages = Vec::new();
+    let mut transcriptions = Vec::new();
+
+    for page in manifest.pages {
+        if let Some(text) = page.text.filter(|text| !text.trim().is_empty()) {
+            transcriptions.push(Transcript { page_id: Some(page.id.clone()), text });
+        }
+
+        pages.push(Page {
+            image: page.image.and_then(|image| files.resolve(&image)),
+            narration: page.audio.iter().filter_map(|clip| files.resolve(clip)).collect(),
+            video: page.video.and_then(|video| files.video(video)),
+            id: page.id,
+            kind: page.kind,
+            title: Some(page.title),
+            text: None,
+        });
+    }
+
+    let theme = ThemeConfig {
+        page_font: manifest.theme.page_font.as_deref().map(|font| files.font(font)),
+        heading_font: manifest.theme.heading_font.as_deref().map(|font| files.font(font)),
+        ..manifest.theme
+    };
+
+    Ok(StoryspackConfig {
+        project_name: manifest.title,
+        pages,
+        theme_audio: manifest.theme_audio.and_then(|audio| files.resolve(&audio)),
+        video_source: manifest.video.and_then(|video| files.video(video)),
+        transcriptions,
+        theme,
+        ..StoryspackConfig::default()
+    })
+}
+
+// Older packs only have their markup. Both the original fixed layout and
+// the templated one mark pages with `<div class="page" data-page="...">`.
+fn from_index_html(folder: &Path, warnings: &mut Vec<String>) -> Result<StoryspackConfig, String> {
+    let path = folder.join("index.html");
+    let html = fs::read_to_string(&path)
+        .map_err(|_| format!("{} has no {} or index.html; is it a storypack folder?", folder.display(), storypack_manifest::MANIFEST_FILE))?;
+
+    let starts: Vec<usize> = html.match_indices("<div class=\"page\"").map(|(idx, _)| idx).collect();
+    if starts.is_empty() {
+        return Err(format!("No pages found in {}", path.display()));
+    }
+
+    let pages_end = html[starts[starts.len() - 1]..].find("<div class=\"navigation\"")
+        .map(|idx| starts[starts.len() - 1] + idx)
+        .unwrap_or(html.len());
+    let head = &html[..starts[0]];
+
+    let theme_audio_src = html.split_once("<audio id=\"theme-music\"")
+        .and_then(|(_, rest)| element_src(rest));
+    let video_src = head.split_once("<div class=\"video-container\">")
+        .map(|(_, rest)| rest);
+
+    let mut files = PackFiles { folder, warnings };
+    let mut pages = Vec::new();
+    let mut transcriptions = Vec::new();
+
+    for (idx, start) in starts.iter().enumerate() {
+        let end = starts.get(idx + 1).copied().unwrap_or(pages_end);
+        let markup = &html[*start..end];
+        let opening = &markup[..markup.find('>').unwrap_or(markup.len())];
+
+        let id = attribute(opening, "data-page").unwrap_or_default();
+        let kind = attribute(opening, "data-kind")
+            .map(|kind| kind_from_str(&kind))
+            .unwrap_or_else(|| kind_from_id(&id));
+
+        let image_tag = tag(markup, "img");
+        let text = markup.split_once("<p class=\"page-text\">")
+            .and_then(|(_, rest)| rest.split_once("</p>"))
+            .map(|(text, _)| unescape(text.trim()))
+            .filter(|text| !text.is_empty());
+
+        if let Some(text) = text {
+            transcriptions.push(Transcript { page_id: Some(id.clone()), text });
+        }
+
+        let narration = markup.split("<audio class=\"page-audio\"")
+            .skip(1)
+            .filter_map(element_src)
+            .filter_map(|src| files.resolve(&src))
+            .collect();
+
+        let video = markup.split_once("<div class=\"video-container\">")
+            .and_then(|(_, rest)| files.video_markup(rest));
+
+        pages.push(Page {
+            image: image_tag.and_then(|img| attribute(img, "src")).and_then(|src| files.resolve(&src)),
+            title: image_tag.and_then(|img| attribute(img, "alt")).filter(|alt| !alt.trim().is_empty()),
+            id,
+            kind,
+            narration,
+            text: None,
+            video,
+        });
+    }
+
+    let title = html.split_once("<title>")
+        .and_then(|(_, rest)| rest.split_once("</title>"))
+        .map(|(title, _)| unescape(title.trim()))
+        .unwrap_or_default();
+
+    let theme_audio = theme_audio_src.and_then(|src| files.resolve(&src));
+    let video_source = video_src.and_then(|markup| files.video_markup(markup));
+
+    files.warnings.push(format!(
+        "This pack has no {}, so its theme isn't known; the default theme is used",
+        storypack_manifest::MANIFEST_FILE
+    ));
+
+    Ok(StoryspackConfig {
+        project_name: title,
+        pages,
+        theme_audio,
+        video_source,
+        transcriptions,
+        ..StoryspackConfig::default()
+    })
+}
+
+// Turns paths from the pack into absolute paths, noting the ones that
+// can't be used
+struct PackFiles<'a> {
+    folder: &'a Path,
+    warnings: &'a mut Vec<String>,
+}
+
+impl PackFiles<'_> {
+    fn resolve(&mut self, relative: &str) -> Option<String> {
+        if relative.starts_with("data:") {
+            return None;
+        }
+
+        let path = Path::new(relative);
+        let inside = path.components().all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+
+        if !inside {
+            self.warnings.push(format!("Skipped {}, which points outside the storypack folder", relative));
+            return None;
+        }
+
+        let absolute: PathBuf = self.folder.join(path);
+        if !absolute.is_file() {
+            self.warnings.push(format!("{} is referenced but missing from the storypack", relative));
+            return None;
+        }
+
+        Some(absolute.to_string_lossy().to_string())
+    }
+
+    // Bundled fonts were written to `assets/fonts`; anything else is a
+    // font-family list and stays as it is
+    fn font(&mut self, font: &str) -> String {
+        if font.starts_with("assets/") {
+            self.resolve(font).unwrap_or_else(|| font.to_string())
+        } else {
+            font.to_string()
+        }
+    }
+
+    fn video(&mut self, video: VideoSource) -> Option<VideoSource> {
+        match video {
+            VideoSource::Local { path } => self.resolve(&path).map(|path| VideoSource::Local { path }),
+            other => Some(other),
+        }
+    }
+
+    // The markup after `<div class="video-container">`
+    fn video_markup(&mut self, markup: &str) -> Option<VideoSource> {
+        let markup = &markup[..markup.find("</div>").unwrap_or(markup.len())];
+
+        if let Some(iframe) = tag(markup, "iframe") {
+            let src = attribute(iframe, "src")?;
+            return match storypack_validate::youtube_id_from_url(&src) {
+                Some(video_id) => Some(VideoSource::YouTube { video_id }),
+                None => {
+                    self.warnings.push(format!("Skipped embedded video {}, which is not a YouTube link", src));
+                    None
+                }
+            };
+        }
+
+        let src = markup.split_once("<video").and_then(|(_, rest)| element_src(rest))?;
+
+        if src.starts_with("http://") || src.starts_with("https://") {
+            Some(VideoSource::Hosted { url: src })
+        } else {
+            self.resolve(&src).map(|path| VideoSource::Local { path })
+        }
+    }
+}
+
+// `src` of an `<audio>` or `<video>` whose opening tag starts `markup`:
+// either on the element itself or on its first `<source>`
+fn element_src(markup: &str) -> Option<String> {
+    let (opening, rest) = markup.split_once('>')?;
+
+    attribute(opening, "src").or_else(|| {
+        let end = rest.find("</audio>").or_else(|| rest.find("</video>")).unwrap_or(rest.len());
+        tag(&rest[..end], "source").and_then(|source| attribute(source, "src"))
+    })
+}
+
+// The first `<name ...>` tag in `markup`, without the brackets
+fn tag<'a>(markup: &'a str, name: &str) -> Option<&'a str> {
+    let open = format!("<{}", name);
+    let mut offset = 0;
+
+    while let Some(idx) = markup[offset..].find(&open) {
+        let start = offset + idx + open.len();
+        let rest = &markup[start..];
+
+        if rest.starts_with(|c: char| c.is_whitespace() || c == '>' || c == '/') {
+            return Some(&rest[..rest.find('>').unwrap_or(rest.len())]);
+        }
+        offset = start;
+    }
+
+    None
+}
+
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let needle = format!(" {}=\"", name);
+    let start = tag.find(&needle)? + needle.len();
+    let end = tag[start..].find('"')?;
+
+    Some(unescape(&tag[start..start + end]))
+}
+
+fn unescape(html: &str) -> String {
+    html.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&#x27;", "'")
+        .replace("&amp;", "&")
+}
+
+fn kind_from_str(kind: &str) -> PageKind {
+    match kind {
+        "cover" => PageKind::Cover,
+        "prologue" => PageKind::Prologue,
+        "chapter" => PageKind::Chapter,
+        "interlude" => PageKind::Interlude,
+        "epilogue" => PageKind::Epilogue,
+        "credits" => PageKind::Credits,
+        _ => PageKind::Custom,
+    }
+}
+
+// The original layout only wrote ids: cover, prologue, chapter1, ...
+fn kind_from_id(id: &str) -> PageKind {
+    kind_from_str(id.trim_end_matches(|c: char| c.is_ascii_digit()))
+}
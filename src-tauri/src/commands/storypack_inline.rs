@@ -0,0 +1,203 @@
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use std::collections::HashMap;
+use std::path::Path;
+use std::fs;
+use crate::commands::storypack_template::{TemplateContext, HLS_PLAYER_FILE, OUTPUT_FILES};
+use crate::commands::storypack_audio::AudioSource;
+
+/// Single-file export: everything inlined into one index.html.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct SingleFileOptions {
+    pub enabled: bool,
+    /// Also inline narration and theme music; otherwise they stay in
+    /// `assets/audio` next to index.html
+    pub inline_audio: bool,
+    /// Warn when index.html ends up larger than this
+    pub size_warning_mb: u64,
+}
+
+impl Default for SingleFileOptions {
+    fn default() -> Self {
+        SingleFileOptions {
+            enabled: false,
+            inline_audio: false,
+            size_warning_mb: 25,
+        }
+    }
+}
+
+/// Rewrites the `assets/...` URLs in `context` to data URIs, reading the
+/// files already written under `project_path`. Returns warnings about
+/// anything that stays external.
+pub fn inline_assets(
+    context: &mut TemplateContext,
+    project_path: &Path,
+    options: &SingleFileOptions,
+) -> Result<Vec<String>, String> {
+    let mut inliner = Inliner { root: project_path, cache: HashMap::new() };
+    let mut warnings = Vec::new();
+
+    for face in &mut context.theme.font_faces {
+        face.src = inliner.data_uri(&face.src)?;
+    }
+
+    for page in &mut context.pages {
+        // Responsive variants would multiply the file size, so keep only
+        // the largest fallback image
+        if let Some(picture) = page.picture.take() {
+            page.image = Some(picture.src);
+        }
+
+        if let Some(image) = &page.image {
+            page.image = Some(inliner.data_uri(image)?);
+        }
+
+        if options.inline_audio {
+            for sources in &mut page.narration_sources {
+                *sources = inliner.single_source(sources)?;
+            }
+            page.narration = page.narration_sources.iter()
+                .filter_map(|sources| sources.first().map(|source| source.src.clone()))
+                .collect();
+        }
+
+        if let Some(video) = &mut page.video {
+            if let Some(poster) = &video.poster {
+                video.poster = Some(inliner.data_uri(poster)?);
+            }
+        }
+    }
+
+    if options.inline_audio && !context.audio.theme_sources.is_empty() {
+        context.audio.theme_sources = inliner.single_source(&context.audio.theme_sources)?;
+        context.audio.theme = context.audio.theme_sources.first().map(|source| source.src.clone());
+    }
+
+    if let Some(video) = &mut context.video {
+        if let Some(poster) = &video.poster {
+            video.poster = Some(inliner.data_uri(poster)?);
+        }
+    }
+
+    let has_audio = context.audio.theme.is_some() || context.audio.has_narration;
+    if has_audio && !options.inline_audio {
+        warnings.push("Audio is not inlined; keep the assets/audio folder next to index.html".to_string());
+    }
+
+    let has_local_video = context.video.iter()
+        .chain(context.pages.iter().filter_map(|page| page.video.as_ref()))
+        .any(|video| video.kind == "local");
+    if has_local_video {
+        warnings.push("Local video is not inlined; keep the assets/video folder next to index.html".to_string());
+    }
+
+    Ok(warnings)
+}
+
+/// Deletes the files that were inlined, leaving index.html and any assets
+/// that are still referenced.
+pub fn remove_inlined_files(project_path: &Path, options: &SingleFileOptions) -> Result<(), String> {
+    for name in OUTPUT_FILES.iter().chain([&HLS_PLAYER_FILE]) {
+        let path = project_path.join(name);
+        if *name != "index.html" && path.is_file() {
+            fs::remove_file(&path)
+                .map_err(|e| format!("Failed to remove {}: {}", name, e))?;
+        }
+    }
+
+    let assets_path = project_path.join("assets");
+    let mut inlined = vec!["images", "fonts"];
+    if options.inline_audio {
+        inlined.push("audio");
+    }
+
+    for folder in inlined {
+        let path = assets_path.join(folder);
+        if path.is_dir() {
+            fs::remove_dir_all(&path)
+                .map_err(|e| format!("Failed to remove assets/{}: {}", folder, e))?;
+        }
+    }
+
+    // Drop folders that are now empty; a folder with files fails to remove
+    for folder in ["audio", "video"] {
+        let _ = fs::remove_dir(assets_path.join(folder));
+    }
+    let _ = fs::remove_dir(&assets_path);
+
+    Ok(())
+}
+
+/// Makes rendered CSS or JS safe to place inside a `<style>`/`<script>` element.
+pub fn escape_inline(code: &str, tag: &str) -> String {
+    code.replace(&format!("</{}", tag), &format!("<\\/{}", tag))
+}
+
+struct Inliner<'a> {
+    root: &'a Path,
+    cache: HashMap<String, String>,
+}
+
+impl Inliner<'_> {
+    // Only files the generator wrote are inlined; other URLs pass through
+    fn data_uri(&mut self, url: &str) -> Result<String, String> {
+        if !url.starts_with("assets/") {
+            return Ok(url.to_string());
+        }
+
+        if let Some(cached) = self.cache.get(url) {
+            return Ok(cached.clone());
+        }
+
+        let bytes = fs::read(self.root.join(url))
+            .map_err(|e| format!("Failed to read {} for inlining: {}", url, e))?;
+        let data_uri = format!("data:{};base64,{}", mime_type(url), STANDARD.encode(bytes));
+
+        self.cache.insert(url.to_string(), data_uri.clone());
+        Ok(data_uri)
+    }
+
+    // One format is enough inline; MP3 plays everywhere, so prefer it
+    fn single_source(&mut self, sources: &[AudioSource]) -> Result<Vec<AudioSource>, String> {
+        let chosen = sources.iter()
+            .find(|source| source.mime == "audio/mpeg")
+            .or(sources.first());
+
+        match chosen {
+            Some(source) => Ok(vec![AudioSource {
+                src: self.data_uri(&source.src)?,
+                mime: source.mime.clone(),
+            }]),
+            None => Ok(Vec::new()),
+        }
+    }
+}
+
+fn mime_type(url: &str) -> &'static str {
+    let extension = Path::new(url)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase())
+        .unwrap_or_default();
+
+    match extension.as_str() {
+        "jpg" | "jpeg" => "image/jpeg",
+        "png" => "image/png",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "avif" => "image/avif",
+        "svg" => "image/svg+xml",
+        "woff2" => "font/woff2",
+        "woff" => "font/woff",
+        "ttf" => "font/ttf",
+        "otf" => "font/otf",
+        "mp3" => "audio/mpeg",
+        "m4a" | "aac" => "audio/mp4",
+        "ogg" | "oga" | "opus" => "audio/ogg",
+        "webm" => "audio/webm",
+        "wav" => "audio/wav",
+        "flac" => "audio/flac",
+        _ => "application/octet-stream",
+    }
+}
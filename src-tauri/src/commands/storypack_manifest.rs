@@ -0,0 +1,128 @@
+use std::fs;
+use std::path::Path;
+use std::time::SystemTime;
+use crate::commands::media_tools;
+use crate::commands::storypack::{Page, PageKind, StoryspackConfig, VideoSource};
+use crate::commands::storypack_epub::utc_timestamp;
+use crate::commands::storypack_template::{TemplateContext, VideoContext};
+use crate::commands::storypack_theme::ThemeConfig;
+
+/// Machine-readable description of a storypack, written next to index.html.
+pub const MANIFEST_FILE: &str = "storypack.json";
+
+/// Identifies the file for other tools.
+pub const MANIFEST_FORMAT: &str = "clipforge-storypack";
+
+/// Bump when a field changes meaning or is removed. Readers should accept
+/// manifests with fields they don't know.
+pub const MANIFEST_VERSION: u32 = 1;
+
+/// Contents of `storypack.json`. Asset paths are relative to the storypack
+/// folder and use `/` separators.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct StorypackManifest {
+    pub format: String,
+    pub version: u32,
+    /// `ClipForge Storypack <version>`
+    pub generator: String,
+    /// UTC, e.g. `2024-05-01T12:00:00Z`
+    pub created: String,
+    pub title: String,
+    pub pages: Vec<ManifestPage>,
+    /// Preset and overrides; font files point at `assets/fonts`
+    pub theme: ThemeConfig,
+    #[serde(default)]
+    pub theme_audio: Option<String>,
+    /// Video shown above the pages; local videos point at their file in
+    /// `assets/video`
+    #[serde(default)]
+    pub video: Option<VideoSource>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct ManifestPage {
+    pub id: String,
+    pub kind: PageKind,
+    pub title: String,
+    #[serde(default)]
+    pub image: Option<String>,
+    /// Narration clips in playing order
+    #[serde(default)]
+    pub audio: Vec<String>,
+    /// Page text; headings are wrapped in `<strong>`
+    #[serde(default)]
+    pub text: Option<String>,
+    /// Total narration length in seconds, when FFprobe could read it
+    #[serde(default)]
+    pub duration: Option<f64>,
+    #[serde(default)]
+    pub video: Option<VideoSource>,
+}
+
+/// Describes the storypack in `project_path`. Build it while every asset is
+/// still on disk, since narration durations are read from the files.
+pub fn build_manifest(
+    config: &StoryspackConfig,
+    pages: &[Page],
+    context: &TemplateContext,
+    project_path: &Path,
+) -> StorypackManifest {
+    let manifest_pages = pages.iter()
+        .zip(&context.pages)
+        .map(|(page, page_context)| {
+            let audio = page_context.narration.clone();
+            let durations: Option<Vec<f64>> = audio.iter()
+                .map(|clip| media_tools::probe_duration(&project_path.join(clip).to_string_lossy()).ok())
+                .collect();
+
+            ManifestPage {
+                id: page.id.clone(),
+                kind: page.kind,
+                title: page.display_title().to_string(),
+                image: page_context.image.clone(),
+                duration: durations.filter(|d| !d.is_empty()).map(|d| d.iter().sum()),
+                audio,
+                text: page.text.clone(),
+                video: page.video.as_ref().zip(page_context.video.as_ref()).map(|(source, video)| packaged_video(source, video)),
+            }
+        })
+        .collect();
+
+    StorypackManifest {
+        format: MANIFEST_FORMAT.to_string(),
+        version: MANIFEST_VERSION,
+        generator: context.project.generator.clone(),
+        created: utc_timestamp(SystemTime::now()),
+        title: context.project.title.clone(),
+        pages: manifest_pages,
+        theme: config.theme.with_bundled_font_paths(),
+        theme_audio: context.audio.theme.clone(),
+        video: config.video_source.as_ref().zip(context.video.as_ref()).map(|(source, video)| packaged_video(source, video)),
+    }
+}
+
+/// Writes `storypack.json` into `project_path`. References to files that
+/// are gone by now (inlined into a single-file index.html) are dropped.
+pub fn write_manifest(mut manifest: StorypackManifest, project_path: &Path) -> Result<(), String> {
+    let exists = |path: &String| project_path.join(path).is_file();
+
+    for page in &mut manifest.pages {
+        page.image = page.image.take().filter(exists);
+        page.audio.retain(exists);
+    }
+    manifest.theme_audio = manifest.theme_audio.take().filter(exists);
+
+    let json = serde_json::to_string_pretty(&manifest)
+        .map_err(|e| format!("Failed to serialize {}: {}", MANIFEST_FILE, e))?;
+
+    fs::write(project_path.join(MANIFEST_FILE), json)
+        .map_err(|e| format!("Failed to write {}: {}", MANIFEST_FILE, e))
+}
+
+// Local videos are described by their copy in the storypack
+fn packaged_video(source: &VideoSource, video: &VideoContext) -> VideoSource {
+    match source {
+        VideoSource::Local { .. } => VideoSource::Local { path: video.src.clone() },
+        other => other.clone(),
+    }
+}
@@ -0,0 +1,196 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+/// What `generate_storypack` does when the storypack folder already exists.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictPolicy {
+    /// Stop without touching the existing folder
+    #[default]
+    Fail,
+    /// Replace the existing folder, so no stale files survive
+    OverwriteClean,
+    /// Write `<name>-v2`, `<name>-v3`, ... next to it
+    NewVersion,
+}
+
+/// Where the storypack for `folder_name` goes under `output_folder`, and the
+/// folder of the previous build if there is one: the folder being replaced,
+/// or the latest `-vN` when a new version is added.
+pub fn target_path(
+    output_folder: &Path,
+    folder_name: &str,
+    policy: ConflictPolicy,
+) -> Result<(PathBuf, Option<PathBuf>), String> {
+    let path = output_folder.join(folder_name);
+
+    if !path.exists() {
+        return Ok((path, None));
+    }
+
+    match policy {
+        ConflictPolicy::Fail => Err(format!(
+            "{} already exists; choose another project name or allow overwriting",
+            path.display()
+        )),
+        ConflictPolicy::OverwriteClean if !path.is_dir() => Err(format!(
+            "{} already exists and is not a folder",
+            path.display()
+        )),
+        ConflictPolicy::OverwriteClean => Ok((path.clone(), Some(path))),
+        ConflictPolicy::NewVersion => {
+            let mut version = 2;
+            let mut previous = path.clone();
+            let mut path = path;
+
+            while path.exists() {
+                previous = path;
+                path = output_folder.join(format!("{}-v{}", folder_name, version));
+                version += 1;
+            }
+
+            Ok((path, Some(previous)))
+        }
+    }
+}
+
+/// A hidden build folder next to the target. The storypack is generated in
+/// `project_path()`, exports such as `<name>.epub` next to it, and
+/// `commit` moves everything into place once the whole run succeeded.
+/// Dropping it without committing deletes it, so a failed run leaves the
+/// output folder as it was.
+pub struct StagingDir {
+    root: PathBuf,
+    project_path: PathBuf,
+}
+
+impl StagingDir {
+    /// Creates the build folder for `target`, in the same parent so the
+    /// final moves are renames rather than copies.
+    pub fn create(target: &Path) -> Result<Self, String> {
+        let (parent, name) = split_target(target)?;
+        let root = parent.join(format!(".{}.building-{}", name, process::id()));
+
+        if root.exists() {
+            fs::remove_dir_all(&root)
+                .map_err(|e| format!("Failed to clear {}: {}", root.display(), e))?;
+        }
+
+        let project_path = root.join(name);
+        fs::create_dir_all(&project_path)
+            .map_err(|e| format!("Failed to create project folder: {}", e))?;
+
+        Ok(StagingDir { root, project_path })
+    }
+
+    pub fn project_path(&self) -> &Path {
+        &self.project_path
+    }
+
+    /// Swaps the finished storypack in at `target` and moves the exports in
+    /// `exports` next to it. Returns the exports' final paths. The folder
+    /// and files it replaces are kept aside until everything has moved, and
+    /// put back if anything fails. `policy` is checked again, since `target`
+    /// may have been created while the storypack was being built.
+    pub fn commit(self, target: &Path, exports: &[String], policy: ConflictPolicy) -> Result<Vec<String>, String> {
+        let (parent, name) = split_target(target)?;
+        let mut replaced = Vec::new();
+
+        if target.exists() && policy != ConflictPolicy::OverwriteClean {
+            return Err(format!(
+                "{} was created while the storypack was being built; nothing was replaced",
+                target.display()
+            ));
+        }
+
+        if target.exists() {
+            let backup = parent.join(format!(".{}.old-{}", name, process::id()));
+            fs::rename(target, &backup)
+                .map_err(|e| format!("Failed to move the old {} aside: {}", target.display(), e))?;
+            replaced.push((target.to_path_buf(), backup));
+        }
+
+        // Moves already made, undone in reverse if a later one fails
+        let mut moves = Vec::new();
+
+        let result = fs::rename(&self.project_path, target)
+            .map_err(|e| format!("Failed to move the storypack to {}: {}", target.display(), e))
+            .and_then(|_| {
+                moves.push((self.project_path.clone(), target.to_path_buf()));
+
+                exports.iter()
+                    .map(|export| self.move_export(parent, export, &mut replaced, &mut moves))
+                    .collect::<Result<Vec<_>, _>>()
+            });
+
+        match result {
+            Ok(moved) => {
+                for (_, backup) in replaced {
+                    let removed = if backup.is_dir() { fs::remove_dir_all(&backup) } else { fs::remove_file(&backup) };
+                    if let Err(e) = removed {
+                        println!("Warning: failed to remove the old {}: {}", backup.display(), e);
+                    }
+                }
+
+                Ok(moved)
+            }
+            Err(e) => {
+                for (from, to) in moves.iter().rev() {
+                    let _ = fs::rename(to, from);
+                }
+                for (original, backup) in replaced.iter().rev() {
+                    let _ = fs::rename(backup, original);
+                }
+
+                Err(e)
+            }
+        }
+    }
+
+    // Moves one export next to the storypack, setting aside a file it replaces
+    fn move_export(
+        &self,
+        parent: &Path,
+        export: &str,
+        replaced: &mut Vec<(PathBuf, PathBuf)>,
+        moves: &mut Vec<(PathBuf, PathBuf)>,
+    ) -> Result<String, String> {
+        let file_name = Path::new(export).file_name()
+            .ok_or_else(|| format!("Export has no file name: {}", export))?;
+        let destination = parent.join(file_name);
+
+        if destination.exists() {
+            let backup = parent.join(format!(".{}.old-{}", file_name.to_string_lossy(), process::id()));
+            fs::rename(&destination, &backup)
+                .map_err(|e| format!("Failed to move the old {} aside: {}", destination.display(), e))?;
+            replaced.push((destination.clone(), backup));
+        }
+
+        fs::rename(export, &destination)
+            .map_err(|e| format!("Failed to move {} into place: {}", destination.display(), e))?;
+        moves.push((PathBuf::from(export), destination.clone()));
+
+        Ok(destination.to_string_lossy().to_string())
+    }
+}
+
+impl Drop for StagingDir {
+    fn drop(&mut self) {
+        if self.root.exists() {
+            if let Err(e) = fs::remove_dir_all(&self.root) {
+                println!("Warning: failed to remove build folder {}: {}", self.root.display(), e);
+            }
+        }
+    }
+}
+
+fn split_target(target: &Path) -> Result<(&Path, String), String> {
+    let parent = target.parent()
+        .ok_or_else(|| format!("{} has no parent folder", target.display()))?;
+    let name = target.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .ok_or_else(|| format!("{} has no folder name", target.display()))?;
+
+    Ok((parent, name))
+}
@@ -0,0 +1,1160 @@
+use flate2::write::ZlibEncoder;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::Write as _;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use crate::commands::storypack::{self, StoryspackConfig};
+use crate::commands::storypack_epub;
+use crate::commands::storypack_template::{PageContext, TemplateContext};
+use crate::commands::storypack_theme::ThemeConfig;
+
+// The theme fonts are bundled as WOFF2, which PDF can't embed, so print
+// uses DejaVu Serif unless the theme points at a local TrueType/OpenType file
+const SERIF_REGULAR: &[u8] = include_bytes!("../../fonts/dejavu-serif.ttf");
+const SERIF_BOLD: &[u8] = include_bytes!("../../fonts/dejavu-serif-bold.ttf");
+
+const POINTS_PER_MM: f32 = 72.0 / 25.4;
+const MARGIN_MM: f32 = 15.0;
+const GAP: f32 = 18.0;
+
+const TITLE_SIZE: f32 = 30.0;
+const HEADING_SIZE: f32 = 18.0;
+const BODY_SIZE: f32 = 13.0;
+const FOOTER_SIZE: f32 = 9.0;
+const LINE_HEIGHT: f32 = 1.4;
+
+/// Share of a page the text may take below an illustration; the rest
+/// continues on the following page
+const MAX_TEXT_SHARE: f32 = 0.4;
+
+type Color = (f32, f32, f32);
+
+const BODY_COLOR: Color = (0.13, 0.13, 0.13);
+const MUTED_COLOR: Color = (0.45, 0.45, 0.45);
+
+/// Printable picture-book PDF, written next to the storypack folder.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct PdfOptions {
+    pub enabled: bool,
+    pub page_size: PageSize,
+    /// Extra image area on every side that the print shop trims off; 3 mm
+    /// is common, 0 for home printers. With bleed, illustrations run out to
+    /// the bleed edge on every side that touches the margins, cropped to fill.
+    pub bleed_mm: f32,
+}
+
+impl Default for PdfOptions {
+    fn default() -> Self {
+        PdfOptions {
+            enabled: false,
+            page_size: PageSize::A4,
+            bleed_mm: 0.0,
+        }
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PageSize {
+    #[default]
+    A4,
+    Letter,
+    /// 8.5 × 8.5 in, the usual picture-book format
+    Square,
+}
+
+impl PageSize {
+    // Trim size in points
+    fn points(self) -> (f32, f32) {
+        match self {
+            PageSize::A4 => (595.28, 841.89),
+            PageSize::Letter => (612.0, 792.0),
+            PageSize::Square => (612.0, 612.0),
+        }
+    }
+}
+
+/// Builds `<project>.pdf` from the rendered storypack in `project_path`:
+/// a title page from the cover, one page per story page and a credits page.
+/// Returns the PDF path and warnings for the user.
+pub fn write_pdf(
+    config: &StoryspackConfig,
+    context: &TemplateContext,
+    project_path: &Path,
+) -> Result<(PathBuf, Vec<String>), String> {
+    let options = &config.pdf;
+    let mut warnings = Vec::new();
+    let fonts = load_fonts(&config.theme, &mut warnings)?;
+    let (width, height) = options.page_size.points();
+
+    let mut book = Book {
+        pdf: PdfWriter::default(),
+        fonts,
+        font_ids: [0, 0],
+        pages_id: 0,
+        page_ids: Vec::new(),
+        root: project_path,
+        width,
+        height,
+        bleed: options.bleed_mm.clamp(0.0, 10.0) * POINTS_PER_MM,
+        heading_color: hex_color(&context.theme.primary).unwrap_or(BODY_COLOR),
+        warnings,
+    };
+    book.pages_id = book.pdf.reserve();
+    book.font_ids = [book.pdf.reserve(), book.pdf.reserve()];
+
+    let cover = context.pages.iter().find(|page| page.kind == "cover");
+    book.title_page(&context.project.title, cover)?;
+
+    for page in context.pages.iter().filter(|page| page.kind != "cover" && page.kind != "credits") {
+        book.story_page(page, false, None)?;
+    }
+
+    // Credits close the book; packs without a credits page get a plain one
+    let footer = format!("Made with {}", context.project.generator);
+    let credits: Vec<&PageContext> = context.pages.iter()
+        .filter(|page| page.kind == "credits")
+        .collect();
+
+    if credits.is_empty() {
+        book.credits_page(&context.project.title, &footer);
+    }
+
+    for (idx, page) in credits.iter().enumerate() {
+        let footer = if idx + 1 == credits.len() { Some(footer.as_str()) } else { None };
+        book.story_page(page, true, footer)?;
+    }
+
+    let pdf_path = storypack::export_path(project_path, ".pdf");
+    let (bytes, warnings) = book.finish(&context.project.title, &context.project.generator);
+
+    fs::write(&pdf_path, bytes)
+        .map_err(|e| format!("Failed to write PDF: {}", e))?;
+
+    Ok((pdf_path, warnings))
+}
+
+struct Book<'a> {
+    pdf: PdfWriter,
+    /// Regular and bold
+    fonts: [Font; 2],
+    font_ids: [usize; 2],
+    pages_id: usize,
+    page_ids: Vec<usize>,
+    root: &'a Path,
+    /// Trim size; the media box adds `bleed` on every side
+    width: f32,
+    height: f32,
+    bleed: f32,
+    heading_color: Color,
+    warnings: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Rect {
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+}
+
+struct Line {
+    text: String,
+    bold: bool,
+    size: f32,
+    color: Color,
+    space_before: f32,
+}
+
+impl Line {
+    fn height(&self, first: bool) -> f32 {
+        self.size * LINE_HEIGHT + if first { 0.0 } else { self.space_before }
+    }
+}
+
+/// A page being drawn: its content stream plus the images it uses.
+#[derive(Default)]
+struct Canvas {
+    ops: String,
+    images: Vec<usize>,
+}
+
+impl Book<'_> {
+    // Area inside the margins, in media box coordinates
+    fn content(&self) -> Rect {
+        let margin = MARGIN_MM * POINTS_PER_MM;
+        Rect {
+            x: self.bleed + margin,
+            y: self.bleed + margin,
+            width: self.width - 2.0 * margin,
+            height: self.height - 2.0 * margin,
+        }
+    }
+
+    // Trim size plus bleed on every side
+    fn media_size(&self) -> (f32, f32) {
+        (self.width + 2.0 * self.bleed, self.height + 2.0 * self.bleed)
+    }
+
+    // With bleed, the sides of an illustration that sit on the margins move
+    // out to the media box edge, so the art is cut by the trim instead of
+    // framed by white paper
+    fn illustration_area(&self, area: Rect) -> Rect {
+        if self.bleed <= 0.0 {
+            return area;
+        }
+
+        let content = self.content();
+        let (media_width, media_height) = self.media_size();
+        let on_margin = |a: f32, b: f32| (a - b).abs() < 0.5;
+
+        let left = if on_margin(area.x, content.x) { 0.0 } else { area.x };
+        let bottom = if on_margin(area.y, content.y) { 0.0 } else { area.y };
+        let right = if on_margin(area.x + area.width, content.x + content.width) {
+            media_width
+        } else {
+            area.x + area.width
+        };
+        let top = if on_margin(area.y + area.height, content.y + content.height) {
+            media_height
+        } else {
+            area.y + area.height
+        };
+
+        Rect { x: left, y: bottom, width: right - left, height: top - bottom }
+    }
+
+    fn title_page(&mut self, title: &str, cover: Option<&PageContext>) -> Result<(), String> {
+        let area = self.content();
+        let mut lines = self.wrap(title, true, TITLE_SIZE, self.heading_color, area.width);
+
+        if let Some(text) = cover.and_then(|page| page.text.as_deref()) {
+            let mut subtitle = self.wrap(text, false, BODY_SIZE, BODY_COLOR, area.width);
+            if let Some(first) = subtitle.first_mut() {
+                first.space_before = GAP;
+            }
+            lines.extend(subtitle);
+        }
+
+        let text_height = lines_height(&lines);
+        let mut canvas = Canvas::default();
+
+        match cover.and_then(|page| self.page_image(page)) {
+            Some(src) => {
+                let image_area = Rect {
+                    y: area.y + text_height + GAP,
+                    height: area.height - text_height - GAP,
+                    ..area
+                };
+                self.draw_illustration(&mut canvas, &src, image_area)?;
+                self.draw_lines(&mut canvas, &lines, area.x, area.y + text_height, area.width, true);
+            }
+            None => {
+                // No cover art: center the title on the page
+                let top = area.y + (area.height + text_height) / 2.0;
+                self.draw_lines(&mut canvas, &lines, area.x, top, area.width, true);
+            }
+        }
+
+        self.add_page(canvas);
+        Ok(())
+    }
+
+    /// Illustration on top, text below. Text that doesn't fit continues on
+    /// text-only pages.
+    fn story_page(&mut self, page: &PageContext, heading: bool, footer: Option<&str>) -> Result<(), String> {
+        let area = self.content();
+        let mut lines = Vec::new();
+
+        if heading {
+            lines.extend(self.wrap(&page.title, true, HEADING_SIZE, self.heading_color, area.width));
+        }
+        lines.extend(self.text_lines(page, area.width));
+
+        let footer_lines = footer
+            .map(|text| self.wrap(text, false, FOOTER_SIZE, MUTED_COLOR, area.width))
+            .unwrap_or_default();
+        let footer_height = if footer_lines.is_empty() { 0.0 } else { lines_height(&footer_lines) + GAP };
+
+        let area = Rect {
+            y: area.y + footer_height,
+            height: area.height - footer_height,
+            ..area
+        };
+
+        let image = self.page_image(page);
+        let mut canvas = Canvas::default();
+
+        let text_limit = if image.is_some() { area.height * MAX_TEXT_SHARE } else { area.height };
+        let (first, mut rest) = split_lines(lines, text_limit);
+
+        if let Some(src) = image {
+            let text_height = lines_height(&first);
+            let image_area = Rect {
+                y: area.y + text_height + if first.is_empty() { 0.0 } else { GAP },
+                height: area.height - text_height - if first.is_empty() { 0.0 } else { GAP },
+                ..area
+            };
+            self.draw_illustration(&mut canvas, &src, image_area)?;
+            self.draw_lines(&mut canvas, &first, area.x, area.y + text_height, area.width, false);
+        } else {
+            self.draw_lines(&mut canvas, &first, area.x, area.y + area.height, area.width, false);
+        }
+
+        while !rest.is_empty() {
+            self.add_page(canvas);
+            canvas = Canvas::default();
+
+            let (page_lines, remaining) = split_lines(rest, area.height);
+            self.draw_lines(&mut canvas, &page_lines, area.x, area.y + area.height, area.width, false);
+            rest = remaining;
+        }
+
+        if !footer_lines.is_empty() {
+            let content = self.content();
+            self.draw_lines(&mut canvas, &footer_lines, content.x, content.y + lines_height(&footer_lines), content.width, true);
+        }
+
+        self.add_page(canvas);
+        Ok(())
+    }
+
+    fn credits_page(&mut self, title: &str, footer: &str) {
+        let area = self.content();
+        let mut lines = self.wrap("Credits", true, HEADING_SIZE, self.heading_color, area.width);
+        lines.extend(self.wrap(title, false, BODY_SIZE, BODY_COLOR, area.width));
+        let footer_lines = self.wrap(footer, false, FOOTER_SIZE, MUTED_COLOR, area.width);
+
+        let mut canvas = Canvas::default();
+        let top = area.y + (area.height + lines_height(&lines)) / 2.0;
+        self.draw_lines(&mut canvas, &lines, area.x, top, area.width, true);
+        self.draw_lines(&mut canvas, &footer_lines, area.x, area.y + lines_height(&footer_lines), area.width, true);
+        self.add_page(canvas);
+    }
+
+    // Page image, or the poster of a page video, which can't be printed
+    fn page_image(&mut self, page: &PageContext) -> Option<String> {
+        if let Some(image) = &page.image {
+            return Some(image.clone());
+        }
+
+        let video = page.video.as_ref()?;
+        if video.poster.is_none() {
+            self.warnings.push(format!("The video on \"{}\" has no poster image and is left out of the PDF", page.title));
+        }
+        video.poster.clone()
+    }
+
+    // Page text with <strong> lines as headings
+    fn text_lines(&self, page: &PageContext, width: f32) -> Vec<Line> {
+        let Some(html) = &page.text_html else { return Vec::new() };
+        let mut lines = Vec::new();
+
+        for (idx, segment) in html.split("<strong>").enumerate() {
+            let (heading, body) = match segment.split_once("</strong>") {
+                Some((heading, body)) if idx > 0 => (Some(heading), body),
+                _ => (None, segment),
+            };
+
+            if let Some(heading) = heading {
+                let mut heading_lines = self.wrap(&unescape(heading), true, HEADING_SIZE, self.heading_color, width);
+                if let Some(first) = heading_lines.first_mut() {
+                    first.space_before = GAP;
+                }
+                lines.extend(heading_lines);
+            }
+
+            lines.extend(self.wrap(&unescape(body), false, BODY_SIZE, BODY_COLOR, width));
+        }
+
+        lines
+    }
+
+    // Word-wraps `text`; blank lines become paragraph spacing
+    fn wrap(&self, text: &str, bold: bool, size: f32, color: Color, width: f32) -> Vec<Line> {
+        let font = &self.fonts[bold as usize];
+        let mut lines = Vec::new();
+        let mut space_before = 0.0;
+
+        for paragraph in text.split('\n') {
+            if paragraph.trim().is_empty() {
+                if !lines.is_empty() {
+                    space_before = size * 0.6;
+                }
+                continue;
+            }
+
+            let mut current = String::new();
+            for word in paragraph.split_whitespace() {
+                let candidate = if current.is_empty() { word.to_string() } else { format!("{} {}", current, word) };
+
+                if font.width(&candidate, size) <= width {
+                    current = candidate;
+                    continue;
+                }
+
+                if !current.is_empty() {
+                    lines.push(Line { text: std::mem::take(&mut current), bold, size, color, space_before });
+                    space_before = 0.0;
+                }
+
+                // Words wider than the page are broken anywhere
+                for c in word.chars() {
+                    if !current.is_empty() && font.width(&format!("{}{}", current, c), size) > width {
+                        lines.push(Line { text: std::mem::take(&mut current), bold, size, color, space_before });
+                        space_before = 0.0;
+                    }
+                    current.push(c);
+                }
+            }
+
+            if !current.is_empty() {
+                lines.push(Line { text: current, bold, size, color, space_before });
+                space_before = 0.0;
+            }
+        }
+
+        lines
+    }
+
+    // Draws lines downwards from `top`
+    fn draw_lines(&mut self, canvas: &mut Canvas, lines: &[Line], x: f32, top: f32, width: f32, center: bool) {
+        let mut y = top;
+
+        for (idx, line) in lines.iter().enumerate() {
+            y -= line.height(idx == 0);
+            let baseline = y + line.size * (LINE_HEIGHT - 1.0) / 2.0 + line.size * 0.2;
+
+            let font = &mut self.fonts[line.bold as usize];
+            let offset = if center { (width - font.width(&line.text, line.size)) / 2.0 } else { 0.0 };
+            let glyphs = font.encode(&line.text);
+            let (r, g, b) = line.color;
+
+            let _ = writeln!(
+                canvas.ops,
+                "BT /F{} {:.2} Tf {:.3} {:.3} {:.3} rg {:.2} {:.2} Td <{}> Tj ET",
+                line.bold as usize + 1, line.size, r, g, b, x + offset, baseline, glyphs
+            );
+        }
+    }
+
+    // Fits the image inside `area`, centered. With bleed it fills the
+    // widened area instead, clipped to it.
+    fn draw_illustration(&mut self, canvas: &mut Canvas, src: &str, area: Rect) -> Result<(), String> {
+        if area.height <= 0.0 {
+            return Ok(());
+        }
+
+        let Some((id, width, height)) = self.embed_image(src)? else { return Ok(()) };
+
+        let fill = self.bleed > 0.0;
+        let area = self.illustration_area(area);
+        let (x_scale, y_scale) = (area.width / width as f32, area.height / height as f32);
+        let scale = if fill { x_scale.max(y_scale) } else { x_scale.min(y_scale) };
+        let (w, h) = (width as f32 * scale, height as f32 * scale);
+        let x = area.x + (area.width - w) / 2.0;
+        let y = area.y + (area.height - h) / 2.0;
+
+        let clip = if fill {
+            format!("{:.2} {:.2} {:.2} {:.2} re W n ", area.x, area.y, area.width, area.height)
+        } else {
+            String::new()
+        };
+
+        let _ = writeln!(canvas.ops, "q {}{:.2} 0 0 {:.2} {:.2} {:.2} cm /Im{} Do Q", clip, w, h, x, y, id);
+        canvas.images.push(id);
+        Ok(())
+    }
+
+    // JPEGs are embedded as-is, PNGs decoded and recompressed. Returns None
+    // for formats PDF can't hold.
+    fn embed_image(&mut self, src: &str) -> Result<Option<(usize, u32, u32)>, String> {
+        let path = self.root.join(src);
+        let data = fs::read(&path)
+            .map_err(|e| format!("Failed to read {} for the PDF: {}", src, e))?;
+
+        if data.starts_with(&[0xFF, 0xD8]) {
+            let Some(info) = jpeg_info(&data) else {
+                self.warnings.push(format!("{} is not a JPEG the PDF can use and was left out", src));
+                return Ok(None);
+            };
+
+            let (color_space, decode) = match info.components {
+                1 => ("/DeviceGray", ""),
+                4 if info.adobe_inverted => ("/DeviceCMYK", " /Decode [1 0 1 0 1 0 1 0]"),
+                4 => ("/DeviceCMYK", ""),
+                _ => ("/DeviceRGB", ""),
+            };
+
+            let dict = format!(
+                "/Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace {} /BitsPerComponent 8{} /Filter /DCTDecode",
+                info.width, info.height, color_space, decode
+            );
+            let id = self.pdf.add_stream(&dict, &data, false);
+            return Ok(Some((id, info.width, info.height)));
+        }
+
+        if data.starts_with(b"\x89PNG") {
+            let image = decode_png(&data).map_err(|e| format!("Failed to decode {}: {}", src, e))?;
+
+            let smask = match &image.alpha {
+                Some(alpha) => {
+                    let dict = format!(
+                        "/Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace /DeviceGray /BitsPerComponent 8",
+                        image.width, image.height
+                    );
+                    format!(" /SMask {} 0 R", self.pdf.add_stream(&dict, alpha, true))
+                }
+                None => String::new(),
+            };
+
+            let dict = format!(
+                "/Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace {} /BitsPerComponent 8{}",
+                image.width, image.height, if image.gray { "/DeviceGray" } else { "/DeviceRGB" }, smask
+            );
+            let id = self.pdf.add_stream(&dict, &image.pixels, true);
+            return Ok(Some((id, image.width, image.height)));
+        }
+
+        self.warnings.push(format!(
+            "{} is not JPEG or PNG and was left out of the PDF; turn on image optimization to convert it",
+            src
+        ));
+        Ok(None)
+    }
+
+    fn add_page(&mut self, canvas: Canvas) {
+        let contents = self.pdf.add_stream("", canvas.ops.as_bytes(), true);
+
+        let mut images = String::new();
+        for id in canvas.images.iter().collect::<BTreeSet<_>>() {
+            let _ = write!(images, " /Im{} {} 0 R", id, id);
+        }
+
+        let (media_width, media_height) = self.media_size();
+        let page = format!(
+            "<< /Type /Page /Parent {} 0 R /MediaBox [0 0 {:.2} {:.2}] /BleedBox [0 0 {:.2} {:.2}] \
+             /TrimBox [{:.2} {:.2} {:.2} {:.2}] /Resources << /Font << /F1 {} 0 R /F2 {} 0 R >> \
+             /XObject <<{} >> >> /Contents {} 0 R >>",
+            self.pages_id, media_width, media_height, media_width, media_height,
+            self.bleed, self.bleed, self.bleed + self.width, self.bleed + self.height,
+            self.font_ids[0], self.font_ids[1], images, contents
+        );
+
+        let id = self.pdf.add(page);
+        self.page_ids.push(id);
+    }
+
+    // Writes the fonts (now that every glyph is known), page tree and catalog
+    fn finish(mut self, title: &str, generator: &str) -> (Vec<u8>, Vec<String>) {
+        for (idx, font) in self.fonts.iter().enumerate() {
+            font.write(&mut self.pdf, self.font_ids[idx], idx);
+
+            if !font.missing.is_empty() {
+                let missing: String = font.missing.iter().collect();
+                self.warnings.push(format!(
+                    "The PDF font {} has no glyphs for \"{}\"; set a .ttf/.otf theme font that covers them",
+                    font.postscript_name, missing
+                ));
+            }
+        }
+
+        let kids: Vec<String> = self.page_ids.iter().map(|id| format!("{} 0 R", id)).collect();
+        self.pdf.set(self.pages_id, format!(
+            "<< /Type /Pages /Kids [{}] /Count {} >>",
+            kids.join(" "), kids.len()
+        ));
+
+        let catalog = self.pdf.add(format!("<< /Type /Catalog /Pages {} 0 R >>", self.pages_id));
+
+        let created = storypack_epub::utc_timestamp(SystemTime::now()).replace(['-', ':', 'T'], "");
+        let info = self.pdf.add(format!(
+            "<< /Title <{}> /Producer <{}> /CreationDate (D:{}) >>",
+            utf16_hex(title), utf16_hex(generator), created
+        ));
+
+        (self.pdf.finish(catalog, info), self.warnings)
+    }
+}
+
+fn lines_height(lines: &[Line]) -> f32 {
+    lines.iter().enumerate().map(|(idx, line)| line.height(idx == 0)).sum()
+}
+
+// The lines that fit in `height`, and the rest
+fn split_lines(lines: Vec<Line>, height: f32) -> (Vec<Line>, Vec<Line>) {
+    let mut used = 0.0;
+    let mut count = 0;
+
+    for (idx, line) in lines.iter().enumerate() {
+        used += line.height(idx == 0);
+        if used > height {
+            break;
+        }
+        count += 1;
+    }
+
+    // Always place at least one line so an oversized line can't loop forever
+    if count == 0 && height >= BODY_SIZE * LINE_HEIGHT {
+        count = 1;
+    }
+
+    let mut lines = lines;
+    let rest = lines.split_off(count.min(lines.len()));
+    (lines, rest)
+}
+
+fn unescape(html: &str) -> String {
+    html.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&#x27;", "'")
+        .replace("&amp;", "&")
+}
+
+fn hex_color(value: &str) -> Option<Color> {
+    let hex = value.trim().strip_prefix('#')?;
+    let channel = |s: &str| u8::from_str_radix(s, 16).ok().map(|v| v as f32 / 255.0);
+
+    match hex.len() {
+        3 => {
+            let digits: Vec<String> = hex.chars().map(|c| format!("{}{}", c, c)).collect();
+            Some((channel(&digits[0])?, channel(&digits[1])?, channel(&digits[2])?))
+        }
+        6 => Some((channel(&hex[0..2])?, channel(&hex[2..4])?, channel(&hex[4..6])?)),
+        _ => None,
+    }
+}
+
+// PDF text string in UTF-16BE with a byte order mark
+fn utf16_hex(text: &str) -> String {
+    let mut hex = String::from("FEFF");
+    for unit in text.encode_utf16() {
+        let _ = write!(hex, "{:04X}", unit);
+    }
+    hex
+}
+
+/// Minimal PDF object store: objects are numbered from 1 in the order they
+/// are reserved and written out with a cross-reference table.
+#[derive(Default)]
+struct PdfWriter {
+    objects: Vec<Vec<u8>>,
+}
+
+impl PdfWriter {
+    fn reserve(&mut self) -> usize {
+        self.objects.push(Vec::new());
+        self.objects.len()
+    }
+
+    fn set(&mut self, id: usize, body: impl Into<Vec<u8>>) {
+        self.objects[id - 1] = body.into();
+    }
+
+    fn add(&mut self, body: impl Into<Vec<u8>>) -> usize {
+        let id = self.reserve();
+        self.set(id, body);
+        id
+    }
+
+    // `dict` is the stream dictionary without the << >> and /Length
+    fn add_stream(&mut self, dict: &str, data: &[u8], compress: bool) -> usize {
+        let compressed;
+        let (data, filter) = if compress {
+            compressed = zlib(data);
+            (compressed.as_slice(), " /Filter /FlateDecode")
+        } else {
+            (data, "")
+        };
+
+        let dict = format!("{}{}", dict, filter);
+        let mut body = format!("<< {} /Length {} >>\nstream\n", dict.trim(), data.len()).into_bytes();
+        body.extend_from_slice(data);
+        body.extend_from_slice(b"\nendstream");
+        self.add(body)
+    }
+
+    fn finish(self, root: usize, info: usize) -> Vec<u8> {
+        // The binary comment tells transfer tools the file isn't text
+        let mut out = b"%PDF-1.7\n%\xE2\xE3\xCF\xD3\n".to_vec();
+        let mut offsets = Vec::with_capacity(self.objects.len());
+
+        for (idx, body) in self.objects.iter().enumerate() {
+            offsets.push(out.len());
+            out.extend_from_slice(format!("{} 0 obj\n", idx + 1).as_bytes());
+            out.extend_from_slice(body);
+            out.extend_from_slice(b"\nendobj\n");
+        }
+
+        let xref = out.len();
+        let mut table = format!("xref\n0 {}\n0000000000 65535 f \n", self.objects.len() + 1);
+        for offset in offsets {
+            let _ = writeln!(table, "{:010} 00000 n ", offset);
+        }
+        let _ = write!(
+            table,
+            "trailer\n<< /Size {} /Root {} 0 R /Info {} 0 R >>\nstartxref\n{}\n%%EOF\n",
+            self.objects.len() + 1, root, info, xref
+        );
+
+        out.extend_from_slice(table.as_bytes());
+        out
+    }
+}
+
+fn zlib(data: &[u8]) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+    // Writing to a Vec can't fail
+    let _ = encoder.write_all(data);
+    encoder.finish().unwrap_or_default()
+}
+
+struct JpegInfo {
+    width: u32,
+    height: u32,
+    components: u8,
+    /// Photoshop writes CMYK JPEGs with inverted values
+    adobe_inverted: bool,
+}
+
+// Reads the frame header; progressive and baseline JPEGs both work in PDF
+fn jpeg_info(data: &[u8]) -> Option<JpegInfo> {
+    let mut pos = 2;
+    let mut adobe_inverted = false;
+
+    while pos + 4 <= data.len() {
+        if data[pos] != 0xFF {
+            return None;
+        }
+
+        let marker = data[pos + 1];
+        if marker == 0xFF {
+            pos += 1;
+            continue;
+        }
+
+        let length = u16::from_be_bytes([data[pos + 2], data[pos + 3]]) as usize;
+        let segment = data.get(pos + 4..pos + 2 + length)?;
+
+        if marker == 0xEE && segment.starts_with(b"Adobe") {
+            adobe_inverted = true;
+        }
+
+        // SOF0-SOF15, except DHT (C4), JPG (C8) and DAC (CC)
+        if (0xC0..=0xCF).contains(&marker) && ![0xC4, 0xC8, 0xCC].contains(&marker) {
+            if segment.len() < 6 {
+                return None;
+            }
+            return Some(JpegInfo {
+                height: u16::from_be_bytes([segment[1], segment[2]]) as u32,
+                width: u16::from_be_bytes([segment[3], segment[4]]) as u32,
+                components: segment[5],
+                adobe_inverted,
+            });
+        }
+
+        pos += 2 + length;
+    }
+
+    None
+}
+
+struct DecodedImage {
+    width: u32,
+    height: u32,
+    gray: bool,
+    pixels: Vec<u8>,
+    alpha: Option<Vec<u8>>,
+}
+
+fn decode_png(data: &[u8]) -> Result<DecodedImage, String> {
+    let mut decoder = png::Decoder::new(data);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().map_err(|e| e.to_string())?;
+
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let frame = reader.next_frame(&mut buffer).map_err(|e| e.to_string())?;
+    let buffer = &buffer[..frame.buffer_size()];
+
+    let (channels, gray, has_alpha) = match frame.color_type {
+        png::ColorType::Grayscale => (1, true, false),
+        png::ColorType::GrayscaleAlpha => (2, true, true),
+        png::ColorType::Rgba => (4, false, true),
+        _ => (3, false, false),
+    };
+
+    let color_channels = if gray { 1 } else { 3 };
+    let mut pixels = Vec::with_capacity(buffer.len());
+    let mut alpha = Vec::new();
+
+    for pixel in buffer.chunks_exact(channels) {
+        pixels.extend_from_slice(&pixel[..color_channels]);
+        if has_alpha {
+            alpha.push(pixel[channels - 1]);
+        }
+    }
+
+    // Fully opaque images don't need a mask
+    let alpha = if has_alpha && alpha.iter().any(|&a| a != 255) { Some(alpha) } else { None };
+
+    Ok(DecodedImage { width: frame.width, height: frame.height, gray, pixels, alpha })
+}
+
+fn load_fonts(theme: &ThemeConfig, warnings: &mut Vec<String>) -> Result<[Font; 2], String> {
+    let regular = match local_font(theme.page_font.as_deref(), warnings)? {
+        Some(data) => Font::load(data, "the page font")?,
+        None => Font::load(SERIF_REGULAR.to_vec(), "DejaVu Serif")?,
+    };
+
+    let bold = match local_font(theme.heading_font.as_deref(), warnings)? {
+        Some(data) => Font::load(data, "the heading font")?,
+        None => Font::load(SERIF_BOLD.to_vec(), "DejaVu Serif Bold")?,
+    };
+
+    Ok([regular, bold])
+}
+
+// Theme fonts given as a .ttf/.otf path can be embedded directly
+fn local_font(value: Option<&str>, warnings: &mut Vec<String>) -> Result<Option<Vec<u8>>, String> {
+    let Some(value) = value.map(str::trim).filter(|value| !value.is_empty()) else { return Ok(None) };
+    let path = Path::new(value);
+
+    let extension = path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase())
+        .unwrap_or_default();
+
+    match extension.as_str() {
+        "ttf" | "otf" if path.is_file() => fs::read(path)
+            .map(Some)
+            .map_err(|e| format!("Failed to read font {}: {}", value, e)),
+        "woff" | "woff2" => {
+            warnings.push(format!("{} can't be embedded in a PDF; the PDF uses DejaVu Serif instead", value));
+            Ok(None)
+        }
+        _ => Ok(None),
+    }
+}
+
+/// An embedded font, addressed by glyph id (Identity-H) so any script the
+/// font covers can be printed.
+struct Font {
+    data: Vec<u8>,
+    postscript_name: String,
+    units_per_em: f32,
+    ascender: i16,
+    descender: i16,
+    cap_height: i16,
+    bbox: [i16; 4],
+    italic_angle: f32,
+    /// CFF outlines go in as OpenType, TrueType outlines get subset
+    cff: bool,
+    /// Glyph id and advance width for every mapped character
+    glyphs: HashMap<char, (u16, u16)>,
+    used: BTreeMap<u16, char>,
+    missing: BTreeSet<char>,
+}
+
+impl Font {
+    fn load(data: Vec<u8>, what: &str) -> Result<Font, String> {
+        let face = ttf_parser::Face::parse(&data, 0)
+            .map_err(|e| format!("Failed to read {} for the PDF: {}", what, e))?;
+
+        let mut glyphs = HashMap::new();
+        if let Some(cmap) = face.tables().cmap {
+            for subtable in cmap.subtables.into_iter().filter(|subtable| subtable.is_unicode()) {
+                subtable.codepoints(|codepoint| {
+                    let Some(c) = char::from_u32(codepoint) else { return };
+                    if let Some(glyph) = subtable.glyph_index(codepoint) {
+                        let advance = face.glyph_hor_advance(glyph).unwrap_or(0);
+                        glyphs.entry(c).or_insert((glyph.0, advance));
+                    }
+                });
+            }
+        }
+
+        let postscript_name = face.names()
+            .into_iter()
+            .filter(|name| name.name_id == ttf_parser::name_id::POST_SCRIPT_NAME)
+            .find_map(|name| name.to_string())
+            .unwrap_or_else(|| "StorypackFont".to_string())
+            .chars()
+            .filter(|c| c.is_ascii_alphanumeric() || *c == '-')
+            .collect();
+
+        let bbox = face.global_bounding_box();
+
+        Ok(Font {
+            postscript_name,
+            units_per_em: face.units_per_em() as f32,
+            ascender: face.ascender(),
+            descender: face.descender(),
+            cap_height: face.capital_height().unwrap_or(face.ascender()),
+            bbox: [bbox.x_min, bbox.y_min, bbox.x_max, bbox.y_max],
+            italic_angle: face.italic_angle(),
+            cff: face.tables().cff.is_some(),
+            glyphs,
+            used: BTreeMap::new(),
+            missing: BTreeSet::new(),
+            data,
+        })
+    }
+
+    // Characters the font lacks print as its .notdef box
+    fn glyph(&self, c: char) -> (u16, u16) {
+        self.glyphs.get(&c).copied().unwrap_or((0, 0))
+    }
+
+    fn width(&self, text: &str, size: f32) -> f32 {
+        let units: f32 = text.chars().map(|c| self.glyph(c).1 as f32).sum();
+        units * size / self.units_per_em
+    }
+
+    // Hex glyph ids for a Tj operator; remembers the glyphs for subsetting
+    fn encode(&mut self, text: &str) -> String {
+        let mut hex = String::with_capacity(text.len() * 4);
+        for c in text.chars() {
+            let (glyph, _) = self.glyph(c);
+            if glyph == 0 {
+                self.missing.insert(c);
+            }
+            self.used.entry(glyph).or_insert(c);
+            let _ = write!(hex, "{:04X}", glyph);
+        }
+        hex
+    }
+
+    // 1000-unit glyph space used by PDF font metrics
+    fn scale(&self, value: f32) -> i32 {
+        (value * 1000.0 / self.units_per_em).round() as i32
+    }
+
+    fn write(&self, pdf: &mut PdfWriter, id: usize, index: usize) {
+        let subset = if self.cff { None } else { subset_truetype(&self.data, self.used.keys().copied()) };
+
+        // Subset fonts are tagged with six capital letters
+        let base_font = match subset {
+            Some(_) => format!("SPKF{}{}+{}", (b'A' + (index / 26) as u8) as char, (b'A' + (index % 26) as u8) as char, self.postscript_name),
+            None => self.postscript_name.clone(),
+        };
+
+        let font_file = match &subset {
+            Some(data) => {
+                let id = pdf.add_stream(&format!("/Length1 {}", data.len()), data, true);
+                format!("/FontFile2 {} 0 R", id)
+            }
+            None if self.cff => {
+                let id = pdf.add_stream("/Subtype /OpenType", &self.data, true);
+                format!("/FontFile3 {} 0 R", id)
+            }
+            None => {
+                let id = pdf.add_stream(&format!("/Length1 {}", self.data.len()), &self.data, true);
+                format!("/FontFile2 {} 0 R", id)
+            }
+        };
+
+        let descriptor = pdf.add(format!(
+            "<< /Type /FontDescriptor /FontName /{} /Flags 32 /FontBBox [{} {} {} {}] /ItalicAngle {} \
+             /Ascent {} /Descent {} /CapHeight {} /StemV 80 {} >>",
+            base_font,
+            self.scale(self.bbox[0] as f32), self.scale(self.bbox[1] as f32),
+            self.scale(self.bbox[2] as f32), self.scale(self.bbox[3] as f32),
+            self.italic_angle,
+            self.scale(self.ascender as f32), self.scale(self.descender as f32), self.scale(self.cap_height as f32),
+            font_file
+        ));
+
+        let mut widths = String::new();
+        let advances: HashMap<u16, u16> = self.glyphs.values().copied().collect();
+        for glyph in self.used.keys() {
+            let advance = advances.get(glyph).copied().unwrap_or(0);
+            let _ = write!(widths, "{} [{}] ", glyph, self.scale(advance as f32));
+        }
+
+        let (subtype, gid_map) = if self.cff { ("CIDFontType0", "") } else { ("CIDFontType2", " /CIDToGIDMap /Identity") };
+        let descendant = pdf.add(format!(
+            "<< /Type /Font /Subtype /{} /BaseFont /{} /CIDSystemInfo << /Registry (Adobe) /Ordering (Identity) /Supplement 0 >> \
+             /FontDescriptor {} 0 R /DW 0 /W [{}]{} >>",
+            subtype, base_font, descriptor, widths.trim_end(), gid_map
+        ));
+
+        let to_unicode = pdf.add_stream("", self.to_unicode().as_bytes(), true);
+
+        pdf.set(id, format!(
+            "<< /Type /Font /Subtype /Type0 /BaseFont /{} /Encoding /Identity-H /DescendantFonts [{} 0 R] /ToUnicode {} 0 R >>",
+            base_font, descendant, to_unicode
+        ));
+    }
+
+    // Maps glyph ids back to text so the PDF can be searched and copied
+    fn to_unicode(&self) -> String {
+        let mut cmap = String::from(
+            "/CIDInit /ProcSet findresource begin\n12 dict begin\nbegincmap\n\
+             /CIDSystemInfo << /Registry (Adobe) /Ordering (UCS) /Supplement 0 >> def\n\
+             /CMapName /Adobe-Identity-UCS def\n/CMapType 2 def\n\
+             1 begincodespacerange\n<0000> <FFFF>\nendcodespacerange\n"
+        );
+
+        let entries: Vec<(&u16, &char)> = self.used.iter().filter(|(glyph, _)| **glyph != 0).collect();
+        for chunk in entries.chunks(100) {
+            let _ = writeln!(cmap, "{} beginbfchar", chunk.len());
+            for (glyph, c) in chunk {
+                let mut utf16 = [0u16; 2];
+                let units: String = c.encode_utf16(&mut utf16).iter().map(|unit| format!("{:04X}", unit)).collect();
+                let _ = writeln!(cmap, "<{:04X}> <{}>", glyph, units);
+            }
+            cmap.push_str("endbfchar\n");
+        }
+
+        cmap.push_str("endcmap\nCMapName currentdict /CMap defineresource pop\nend\nend\n");
+        cmap
+    }
+}
+
+// Tables a PDF renderer needs from an embedded TrueType font
+const SUBSET_TABLES: [&[u8; 4]; 13] = [
+    b"OS/2", b"cmap", b"cvt ", b"fpgm", b"glyf", b"head", b"hhea",
+    b"hmtx", b"loca", b"maxp", b"name", b"post", b"prep",
+];
+
+/// Drops the outlines of unused glyphs. Glyph ids stay the same, so the
+/// text already encoded stays valid. Returns None if the font can't be
+/// parsed, in which case it is embedded whole.
+fn subset_truetype(data: &[u8], used: impl Iterator<Item = u16>) -> Option<Vec<u8>> {
+    let read16 = |offset: usize| data.get(offset..offset + 2).map(|b| u16::from_be_bytes([b[0], b[1]]));
+    let read32 = |offset: usize| data.get(offset..offset + 4).map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]));
+
+    let mut tables: BTreeMap<[u8; 4], &[u8]> = BTreeMap::new();
+    for idx in 0..read16(4)? as usize {
+        let record = 12 + 16 * idx;
+        let tag: [u8; 4] = data.get(record..record + 4)?.try_into().ok()?;
+        let offset = read32(record + 8)? as usize;
+        let length = read32(record + 12)? as usize;
+        tables.insert(tag, data.get(offset..offset + length)?);
+    }
+
+    let head = *tables.get(b"head")?;
+    let glyf = *tables.get(b"glyf")?;
+    let loca = *tables.get(b"loca")?;
+    let maxp = *tables.get(b"maxp")?;
+
+    let num_glyphs = u16::from_be_bytes([*maxp.get(4)?, *maxp.get(5)?]) as usize;
+    let long_offsets = head.get(50..52)? == [0, 1];
+
+    let offsets: Vec<usize> = (0..=num_glyphs)
+        .map(|idx| if long_offsets {
+            loca.get(idx * 4..idx * 4 + 4).map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]) as usize)
+        } else {
+            loca.get(idx * 2..idx * 2 + 2).map(|b| u16::from_be_bytes([b[0], b[1]]) as usize * 2)
+        })
+        .collect::<Option<_>>()?;
+
+    // Composite glyphs pull in their components
+    let mut keep: BTreeSet<u16> = used.filter(|&glyph| (glyph as usize) < num_glyphs).collect();
+    keep.insert(0);
+    let mut pending: Vec<u16> = keep.iter().copied().collect();
+
+    while let Some(glyph) = pending.pop() {
+        let outline = glyf.get(offsets[glyph as usize]..offsets[glyph as usize + 1])?;
+        if outline.len() < 10 || i16::from_be_bytes([outline[0], outline[1]]) >= 0 {
+            continue;
+        }
+
+        let mut pos = 10;
+        loop {
+            let flags = u16::from_be_bytes([*outline.get(pos)?, *outline.get(pos + 1)?]);
+            let component = u16::from_be_bytes([*outline.get(pos + 2)?, *outline.get(pos + 3)?]);
+            if (component as usize) < num_glyphs && keep.insert(component) {
+                pending.push(component);
+            }
+
+            pos += 4 + if flags & 0x0001 != 0 { 4 } else { 2 };
+            pos += if flags & 0x0008 != 0 { 2 } else if flags & 0x0040 != 0 { 4 } else if flags & 0x0080 != 0 { 8 } else { 0 };
+
+            if flags & 0x0020 == 0 {
+                break;
+            }
+        }
+    }
+
+    let mut new_glyf = Vec::new();
+    let mut new_loca = Vec::with_capacity((num_glyphs + 1) * 4);
+    for glyph in 0..num_glyphs {
+        new_loca.extend_from_slice(&(new_glyf.len() as u32).to_be_bytes());
+        if keep.contains(&(glyph as u16)) {
+            new_glyf.extend_from_slice(glyf.get(offsets[glyph]..offsets[glyph + 1])?);
+            while new_glyf.len() % 4 != 0 {
+                new_glyf.push(0);
+            }
+        }
+    }
+    new_loca.extend_from_slice(&(new_glyf.len() as u32).to_be_bytes());
+
+    // Long loca offsets; the checksum adjustment is filled in at the end
+    let mut new_head = head.to_vec();
+    new_head.get_mut(50..52)?.copy_from_slice(&[0, 1]);
+    new_head.get_mut(8..12)?.copy_from_slice(&[0; 4]);
+
+    let mut output_tables: BTreeMap<[u8; 4], Vec<u8>> = BTreeMap::new();
+    for tag in SUBSET_TABLES {
+        let table = match tag {
+            b"glyf" => new_glyf.clone(),
+            b"loca" => new_loca.clone(),
+            b"head" => new_head.clone(),
+            _ => match tables.get(tag) {
+                Some(table) => table.to_vec(),
+                None => continue,
+            },
+        };
+        output_tables.insert(*tag, table);
+    }
+
+    let count = output_tables.len() as u16;
+    let selector = 15 - count.leading_zeros() as u16;
+    let search_range = (1u16 << selector) * 16;
+
+    let mut font = Vec::new();
+    font.extend_from_slice(&0x0001_0000u32.to_be_bytes());
+    font.extend_from_slice(&count.to_be_bytes());
+    font.extend_from_slice(&search_range.to_be_bytes());
+    font.extend_from_slice(&selector.to_be_bytes());
+    font.extend_from_slice(&(count * 16 - search_range).to_be_bytes());
+
+    let mut offset = 12 + 16 * output_tables.len();
+    let mut head_offset = 0;
+    for (tag, table) in &output_tables {
+        if tag == b"head" {
+            head_offset = offset;
+        }
+        font.extend_from_slice(tag);
+        font.extend_from_slice(&checksum(table).to_be_bytes());
+        font.extend_from_slice(&(offset as u32).to_be_bytes());
+        font.extend_from_slice(&(table.len() as u32).to_be_bytes());
+        offset += (table.len() + 3) & !3;
+    }
+
+    for table in output_tables.values() {
+        font.extend_from_slice(table);
+        while font.len() % 4 != 0 {
+            font.push(0);
+        }
+    }
+
+    let adjustment = 0xB1B0_AFBAu32.wrapping_sub(checksum(&font));
+    font.get_mut(head_offset + 8..head_offset + 12)?.copy_from_slice(&adjustment.to_be_bytes());
+
+    Some(font)
+}
+
+fn checksum(data: &[u8]) -> u32 {
+    data.chunks(4).fold(0u32, |sum, chunk| {
+        let mut word = [0u8; 4];
+        word[..chunk.len()].copy_from_slice(chunk);
+        sum.wrapping_add(u32::from_be_bytes(word))
+    })
+}
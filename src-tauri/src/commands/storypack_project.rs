@@ -0,0 +1,528 @@
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
+use serde_json::{json, Value};
+use tauri::AppHandle;
+use crate::commands::storypack::{StoryspackConfig, VideoSource};
+use crate::commands::storypack_epub::utc_timestamp;
+use crate::commands::storypack_theme;
+
+pub const PROJECT_EXTENSION: &str = "storypack";
+
+/// Identifies the file for other tools.
+pub const PROJECT_FORMAT: &str = "clipforge-storypack-project";
+
+/// Bump when the layout changes, and add a step to `migrate` that upgrades
+/// files from the previous version.
+pub const PROJECT_VERSION: u32 = 1;
+
+// How deep relinking looks below each search folder
+const RELINK_DEPTH: usize = 3;
+
+// Keeps a search from crawling a whole drive
+const RELINK_MAX_FOLDERS: usize = 2000;
+
+/// Contents of a `.storypack` project file. Asset paths are relative to the
+/// folder holding the file and use `/` separators, so a project folder can
+/// be moved or shared as a whole.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct StorypackProject {
+    pub format: String,
+    pub version: u32,
+    /// `ClipForge Storypack <version>`
+    pub generator: String,
+    /// UTC, e.g. `2024-05-01T12:00:00Z`
+    pub saved: String,
+    /// Absolute folder the file was saved in; lets assets be found when only
+    /// the project file was moved
+    #[serde(default)]
+    pub saved_in: Option<String>,
+    pub config: StoryspackConfig,
+    /// Folder the storypack is generated into, relative like the assets
+    #[serde(default)]
+    pub output_folder: Option<String>,
+}
+
+/// A project read back with absolute paths.
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct OpenedProject {
+    pub path: String,
+    pub config: StoryspackConfig,
+    pub output_folder: Option<String>,
+    /// Assets that weren't at their saved path but were found nearby
+    pub relinked: Vec<RelinkedAsset>,
+    /// Assets that couldn't be found; their paths are kept as saved
+    pub missing: Vec<String>,
+    /// Version the file was written with, before migration
+    pub version: u32,
+}
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct RelinkedAsset {
+    pub saved: String,
+    pub found: String,
+}
+
+/// Writes `config` to `path`, adding the `.storypack` extension if it is
+/// missing. Returns the path written.
+pub fn save(path: &Path, config: &StoryspackConfig, output_folder: Option<&str>) -> Result<PathBuf, String> {
+    let path = if path.extension().is_some_and(|e| e.eq_ignore_ascii_case(PROJECT_EXTENSION)) {
+        path.to_path_buf()
+    } else {
+        let mut name = path.as_os_str().to_os_string();
+        name.push(format!(".{}", PROJECT_EXTENSION));
+        PathBuf::from(name)
+    };
+
+    let base = path.parent()
+        .filter(|parent| parent.is_absolute())
+        .ok_or_else(|| format!("{} is not an absolute path", path.display()))?;
+
+    let mut config = config.clone();
+    for_each_path(&mut config, |asset| *asset = relative_path(base, asset));
+
+    let project = StorypackProject {
+        format: PROJECT_FORMAT.to_string(),
+        version: PROJECT_VERSION,
+        generator: format!("ClipForge Storypack {}", env!("CARGO_PKG_VERSION")),
+        saved: utc_timestamp(SystemTime::now()),
+        saved_in: Some(base.to_string_lossy().to_string()),
+        config,
+        output_folder: output_folder.filter(|folder| !folder.is_empty()).map(|folder| relative_path(base, folder)),
+    };
+
+    let json = serde_json::to_string_pretty(&project)
+        .map_err(|e| format!("Failed to serialize project: {}", e))?;
+
+    // Write next to the file and rename, so a failed save never leaves a
+    // half-written project behind
+    let temp = path.with_extension(format!("{}.tmp", PROJECT_EXTENSION));
+    fs::write(&temp, json)
+        .map_err(|e| format!("Failed to write {}: {}", temp.display(), e))?;
+    fs::rename(&temp, &path).map_err(|e| {
+        let _ = fs::remove_file(&temp);
+        format!("Failed to save {}: {}", path.display(), e)
+    })?;
+
+    Ok(path)
+}
+
+/// Reads the project at `path`, upgrading older versions. Assets that have
+/// moved are looked for by name around the project file.
+pub fn open(path: &Path) -> Result<OpenedProject, String> {
+    let json = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let value: Value = serde_json::from_str(&json)
+        .map_err(|e| format!("{} is not a valid project file: {}", path.display(), e))?;
+
+    let (value, version) = migrate(value)
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut project: StorypackProject = serde_json::from_value(value)
+        .map_err(|e| format!("{} is not a valid project file: {}", path.display(), e))?;
+
+    let path = absolute(path);
+    let base = path.parent()
+        .ok_or_else(|| format!("{} has no parent folder", path.display()))?;
+    let mut relinker = Relinker::new(base, project.saved_in.as_deref().map(Path::new));
+
+    for_each_path(&mut project.config, |asset| *asset = relinker.resolve(asset));
+    let output_folder = project.output_folder.as_deref()
+        .map(|folder| lexical_join(base, folder).to_string_lossy().to_string());
+
+    for missing in &relinker.missing {
+        println!("Warning: project asset not found: {}", missing);
+    }
+
+    Ok(OpenedProject {
+        path: path.to_string_lossy().to_string(),
+        config: project.config,
+        output_folder,
+        relinked: relinker.relinked,
+        missing: relinker.missing,
+        version,
+    })
+}
+
+#[tauri::command]
+pub async fn save_storypack_project(
+    path: String,
+    config: StoryspackConfig,
+    output_folder: Option<String>,
+) -> Result<String, String> {
+    save(Path::new(&path), &config, output_folder.as_deref())
+        .map(|path| path.to_string_lossy().to_string())
+}
+
+#[tauri::command]
+pub async fn open_storypack_project(path: String) -> Result<OpenedProject, String> {
+    open(Path::new(&path))
+}
+
+#[tauri::command]
+pub async fn select_storypack_project(app: AppHandle) -> Result<Option<String>, String> {
+    use tauri_plugin_dialog::DialogExt;
+
+    let file = app.dialog()
+        .file()
+        .add_filter("Storypack Project", &[PROJECT_EXTENSION])
+        .set_title("Open Storypack Project")
+        .blocking_pick_file();
+
+    Ok(file.map(|p| p.to_string()))
+}
+
+#[tauri::command]
+pub async fn select_storypack_project_save_path(app: AppHandle, name: String) -> Result<Option<String>, String> {
+    use tauri_plugin_dialog::DialogExt;
+
+    let file = app.dialog()
+        .file()
+        .add_filter("Storypack Project", &[PROJECT_EXTENSION])
+        .set_title("Save Storypack Project")
+        .set_file_name(format!("{}.{}", name, PROJECT_EXTENSION))
+        .blocking_save_file();
+
+    Ok(file.map(|p| p.to_string()))
+}
+
+// Upgrades `project` one version at a time. Version 0 is a bare config, as
+// sent to `generate_storypack`, with absolute paths.
+fn migrate(mut project: Value) -> Result<(Value, u32), String> {
+    let saved_version = match project.get("format").and_then(Value::as_str) {
+        Some(PROJECT_FORMAT) => project.get("version")
+            .and_then(Value::as_u64)
+            .ok_or("the project file has no version")? as u32,
+        Some(other) => return Err(format!("not a storypack project (format \"{}\")", other)),
+        None if project.get("project_name").is_some() => 0,
+        None => return Err("not a storypack project".to_string()),
+    };
+
+    if saved_version > PROJECT_VERSION {
+        return Err(format!(
+            "saved by a newer version of ClipForge (project version {}); update ClipForge to open it",
+            saved_version
+        ));
+    }
+
+    for version in saved_version..PROJECT_VERSION {
+        project = match version {
+            0 => json!({
+                "format": PROJECT_FORMAT,
+                "version": 1,
+                "generator": "",
+                "saved": "",
+                "config": project,
+            }),
+            _ => unreachable!("no migration from project version {}", version),
+        };
+    }
+
+    Ok((project, saved_version))
+}
+
+// Calls `f` on every file and folder path in `config`
+fn for_each_path(config: &mut StoryspackConfig, mut f: impl FnMut(&mut String)) {
+    let mut visit = |path: &mut String| {
+        if !path.trim().is_empty() {
+            f(path);
+        }
+    };
+
+    for page in &mut config.pages {
+        page.image.iter_mut().for_each(&mut visit);
+        page.narration.iter_mut().for_each(&mut visit);
+        if let Some(VideoSource::Local { path }) = &mut page.video {
+            visit(path);
+        }
+    }
+
+    for path in [
+        &mut config.cover_image,
+        &mut config.prologue_image,
+        &mut config.epilogue_image,
+        &mut config.credits_image,
+        &mut config.theme_audio,
+        &mut config.template_dir,
+    ] {
+        path.iter_mut().for_each(&mut visit);
+    }
+
+    config.chapter_images.iter_mut().for_each(&mut visit);
+    config.narration_audio.iter_mut().for_each(&mut visit);
+    config.narration.iter_mut().for_each(|assignment| visit(&mut assignment.path));
+
+    if let Some(VideoSource::Local { path }) = &mut config.video_source {
+        visit(path);
+    }
+
+    for font in [&mut config.theme.page_font, &mut config.theme.heading_font] {
+        font.iter_mut()
+            .filter(|font| storypack_theme::is_font_file(font))
+            .for_each(&mut visit);
+    }
+}
+
+// `target` relative to `base` with `/` separators. Paths on another drive
+// or already relative are kept as they are.
+fn relative_path(base: &Path, target: &str) -> String {
+    let target_path = Path::new(target);
+    if !target_path.is_absolute() {
+        return target.to_string();
+    }
+
+    let base: Vec<Component> = base.components().collect();
+    let target_components: Vec<Component> = target_path.components().collect();
+
+    // Different drives or UNC shares have no relative path between them
+    if base.first() != target_components.first() {
+        return target.to_string();
+    }
+
+    let common = base.iter()
+        .zip(&target_components)
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let parts: Vec<String> = std::iter::repeat_n("..".to_string(), base.len() - common)
+        .chain(target_components[common..].iter().map(|c| c.as_os_str().to_string_lossy().to_string()))
+        .collect();
+
+    if parts.is_empty() {
+        ".".to_string()
+    } else {
+        parts.join("/")
+    }
+}
+
+// Joins and folds `..` without touching the file system, so paths to
+// missing files still come out clean
+fn lexical_join(base: &Path, path: &str) -> PathBuf {
+    let mut joined = PathBuf::new();
+
+    for component in base.join(path).components() {
+        match component {
+            Component::ParentDir => {
+                if !joined.pop() {
+                    joined.push(component);
+                }
+            }
+            Component::CurDir => {}
+            other => joined.push(other),
+        }
+    }
+
+    joined
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    if path.is_absolute() {
+        return path.to_path_buf();
+    }
+
+    std::env::current_dir()
+        .map(|dir| dir.join(path))
+        .unwrap_or_else(|_| path.to_path_buf())
+}
+
+// Finds assets that moved since the project was saved
+struct Relinker<'a> {
+    base: &'a Path,
+    saved_in: Option<&'a Path>,
+    /// Folders searched for moved files: the project folder, then the one
+    /// it was saved in. Never their parents, which may be a home folder or
+    /// a drive root.
+    roots: Vec<PathBuf>,
+    /// Saved folder -> folder its files turned up in; files that lived
+    /// together usually moved together
+    moved: Vec<(PathBuf, PathBuf)>,
+    /// Saved path -> resolved path, so shared files are looked up once
+    resolved: HashMap<String, String>,
+    relinked: Vec<RelinkedAsset>,
+    missing: Vec<String>,
+}
+
+impl<'a> Relinker<'a> {
+    fn new(base: &'a Path, saved_in: Option<&'a Path>) -> Self {
+        let mut roots = vec![base.to_path_buf()];
+        roots.extend(saved_in.filter(|saved_in| *saved_in != base).map(Path::to_path_buf));
+
+        Relinker {
+            base,
+            saved_in,
+            roots,
+            moved: Vec::new(),
+            resolved: HashMap::new(),
+            relinked: Vec::new(),
+            missing: Vec::new(),
+        }
+    }
+
+    fn resolve(&mut self, saved: &str) -> String {
+        if let Some(resolved) = self.resolved.get(saved) {
+            return resolved.clone();
+        }
+
+        let resolved = self.locate(saved);
+        self.resolved.insert(saved.to_string(), resolved.clone());
+        resolved
+    }
+
+    fn locate(&mut self, saved: &str) -> String {
+        let expected = lexical_join(self.base, saved);
+        if expected.exists() {
+            return expected.to_string_lossy().to_string();
+        }
+
+        match self.find(saved, &expected) {
+            Some(found) => {
+                if let (Some(from), Some(to)) = (expected.parent(), found.parent()) {
+                    self.moved.push((from.to_path_buf(), to.to_path_buf()));
+                }
+
+                let found = found.to_string_lossy().to_string();
+                self.relinked.push(RelinkedAsset { saved: saved.to_string(), found: found.clone() });
+                found
+            }
+            None => {
+                self.missing.push(saved.to_string());
+                expected.to_string_lossy().to_string()
+            }
+        }
+    }
+
+    fn find(&self, saved: &str, expected: &Path) -> Option<PathBuf> {
+        let name = expected.file_name()?;
+
+        // Only the project file moved
+        if let Some(saved_in) = self.saved_in.filter(|_| !Path::new(saved).is_absolute()) {
+            let original = lexical_join(saved_in, saved);
+            if original.is_file() {
+                return Some(original);
+            }
+        }
+
+        // A sibling already turned up somewhere else
+        let sibling = self.moved.iter()
+            .filter(|(from, _)| Some(from.as_path()) == expected.parent())
+            .map(|(_, to)| to.join(name))
+            .find(|candidate| candidate.is_file());
+        if sibling.is_some() {
+            return sibling;
+        }
+
+        // The same layout under a search folder, trying the longest tail
+        // of the saved path first: art/chapters/1.png, chapters/1.png, 1.png
+        let parts: Vec<_> = expected.components()
+            .filter_map(|component| match component {
+                Component::Normal(part) => Some(part),
+                _ => None,
+            })
+            .collect();
+
+        for skip in 0..parts.len() {
+            let tail: PathBuf = parts[skip..].iter().collect();
+            let found = self.roots.iter()
+                .map(|root| root.join(&tail))
+                .find(|candidate| candidate.is_file());
+            if found.is_some() {
+                return found;
+            }
+        }
+
+        // Anywhere a few levels below the search folders
+        self.roots.iter().find_map(|root| search(root, name))
+    }
+}
+
+// Breadth-first, so the shallowest match wins
+fn search(root: &Path, name: &std::ffi::OsStr) -> Option<PathBuf> {
+    let mut queue = VecDeque::from([(root.to_path_buf(), 0)]);
+    let mut visited = 0;
+
+    while let Some((folder, depth)) = queue.pop_front() {
+        visited += 1;
+        if visited > RELINK_MAX_FOLDERS {
+            return None;
+        }
+
+        let Ok(entries) = fs::read_dir(&folder) else { continue };
+        let mut subfolders = Vec::new();
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+
+            let Ok(file_type) = entry.file_type() else { continue };
+
+            // A folder with the same name isn't the asset
+            if file_type.is_file() && entry.file_name() == name {
+                return Some(path);
+            }
+
+            let hidden = entry.file_name().to_string_lossy().starts_with('.');
+            if depth < RELINK_DEPTH && !hidden && file_type.is_dir() {
+                subfolders.push(path);
+            }
+        }
+
+        subfolders.sort();
+        queue.extend(subfolders.into_iter().map(|path| (path, depth + 1)));
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn relative_path_walks_up_from_the_project_folder() {
+        let base = Path::new("/home/user/stories/bear");
+
+        assert_eq!(relative_path(base, "/home/user/stories/bear/art/cover.png"), "art/cover.png");
+        assert_eq!(relative_path(base, "/home/user/audio/theme.ogg"), "../../audio/theme.ogg");
+        assert_eq!(relative_path(base, "/home/user/stories/bear"), ".");
+        assert_eq!(relative_path(base, "art/cover.png"), "art/cover.png");
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn relative_path_keeps_other_drives_absolute() {
+        let base = Path::new(r"C:\Stories\Bear");
+
+        assert_eq!(relative_path(base, r"C:\Stories\Art\cover.png"), "../Art/cover.png");
+        assert_eq!(relative_path(base, r"D:\Audio\theme.ogg"), r"D:\Audio\theme.ogg");
+        assert_eq!(relative_path(base, r"\\server\share\cover.png"), r"\\server\share\cover.png");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn lexical_join_folds_parent_folders() {
+        let base = Path::new("/home/user/stories/bear");
+
+        assert_eq!(lexical_join(base, "../../audio/theme.ogg"), Path::new("/home/user/audio/theme.ogg"));
+        assert_eq!(lexical_join(base, "./art/./cover.png"), Path::new("/home/user/stories/bear/art/cover.png"));
+        assert_eq!(lexical_join(base, "/srv/fonts/My Font.ttf"), Path::new("/srv/fonts/My Font.ttf"));
+        // A relative base keeps the `..` it can't fold
+        assert_eq!(lexical_join(Path::new("stories"), "../../art/cover.png"), Path::new("../art/cover.png"));
+
+        for target in ["/home/user/audio/theme.ogg", "/home/user/stories/bear/art/cover.png", "/srv/x.png"] {
+            assert_eq!(lexical_join(base, &relative_path(base, target)), Path::new(target));
+        }
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn lexical_join_resolves_across_drives() {
+        let base = Path::new(r"C:\Stories\Bear");
+
+        assert_eq!(lexical_join(base, "../Art/cover.png"), Path::new(r"C:\Stories\Art\cover.png"));
+        assert_eq!(lexical_join(base, r"D:\Audio\theme.ogg"), Path::new(r"D:\Audio\theme.ogg"));
+
+        for target in [r"C:\Stories\Art\cover.png", r"D:\Audio\theme.ogg"] {
+            assert_eq!(lexical_join(base, &relative_path(base, target)), Path::new(target));
+        }
+    }
+}
@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use crate::commands::storypack::{self, StoryspackConfig};
+use crate::commands::storypack_epub::sanitize_id;
+use crate::commands::storypack_template::{self, TemplateContext};
+use crate::commands::storypack_zip::{self, Compression, ZipWriter};
+
+const TRACKING_FILE: &str = "tracking.js";
+
+/// SCORM 1.2 package for learning management systems, written as
+/// `<project>-scorm.zip` next to the storypack folder.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct LmsOptions {
+    pub enabled: bool,
+    /// Course identifier in imsmanifest.xml; derived from the project name
+    /// when empty
+    pub identifier: Option<String>,
+    pub xapi: XapiOptions,
+}
+
+/// Sends xAPI statements alongside (or instead of) SCORM reporting.
+/// Launch parameters from the LMS override these values.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct XapiOptions {
+    pub enabled: bool,
+    /// LRS endpoint, e.g. `http://localhost:8088/xapi`
+    pub endpoint: Option<String>,
+    /// Authorization header value, e.g. `Basic dXNlcjpwYXNz`. It is written
+    /// into `tracking.js` as plain text, so it's only meant for testing
+    /// against a local LRS; an LMS passes `auth` as a launch parameter.
+    pub auth: Option<String>,
+    /// IRI identifying the storypack; derived from the project name when empty
+    pub activity_id: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+struct PackageContext {
+    course: CourseContext,
+    files: Vec<String>,
+    /// Settings for tracking.js as a JavaScript object literal
+    config: String,
+}
+
+#[derive(serde::Serialize)]
+struct CourseContext {
+    identifier: String,
+    title: String,
+    activity_id: String,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TrackingConfig<'a> {
+    title: &'a str,
+    activity_id: &'a str,
+    page_titles: HashMap<&'a str, &'a str>,
+    xapi: &'a XapiOptions,
+}
+
+/// Zips the storypack in `project_path` with an imsmanifest.xml and an
+/// index.html that reports progress through tracking.js. Reads the
+/// multi-file output, so it must run before single-file inlining.
+pub fn write_scorm_package(
+    config: &StoryspackConfig,
+    context: &TemplateContext,
+    project_path: &Path,
+) -> Result<PathBuf, String> {
+    let options = &config.lms;
+    let slug = sanitize_id(&context.project.title);
+
+    let course = CourseContext {
+        identifier: options.identifier.as_deref()
+            .map(sanitize_id)
+            .filter(|id| !id.is_empty())
+            .map(|id| format!("storypack-{}", id))
+            .unwrap_or_else(|| format!("storypack-{}", slug)),
+        title: context.project.title.clone(),
+        activity_id: options.xapi.activity_id.clone()
+            .filter(|id| !id.trim().is_empty())
+            .unwrap_or_else(|| format!("urn:clipforge:storypack:{}", slug)),
+    };
+
+    let tracking = TrackingConfig {
+        title: &course.title,
+        activity_id: &course.activity_id,
+        page_titles: context.pages.iter()
+            .map(|page| (page.id.as_str(), page.title.as_str()))
+            .collect(),
+        xapi: &options.xapi,
+    };
+    let tracking_config = serde_json::to_string(&tracking)
+        .map_err(|e| format!("Failed to serialize tracking settings: {}", e))?;
+
+    let mut files = storypack_zip::list_files(project_path)
+        .map_err(|e| format!("Failed to list storypack files: {}", e))?;
+    if !files.iter().any(|file| file == TRACKING_FILE) {
+        files.push(TRACKING_FILE.to_string());
+        files.sort();
+    }
+
+    let package = PackageContext {
+        course,
+        files,
+        config: tracking_config,
+    };
+
+    let env = storypack_template::environment(config.template_dir.as_deref().map(PathBuf::from));
+    let mut launch_context = context.clone();
+    launch_context.tracking = true;
+    launch_context.refresh_csp();
+
+    let index_html = storypack_template::render(&env, "index.html", &launch_context)?;
+    let tracking_js = storypack_template::render(&env, "scorm/tracking.js", &package)?;
+    let manifest = storypack_template::render(&env, "scorm/imsmanifest.xml", &package)?;
+
+    let zip_path = storypack::export_path(project_path, "-scorm.zip");
+    let write_error = |e: std::io::Error| format!("Failed to write {}: {}", zip_path.display(), e);
+
+    let file = File::create(&zip_path).map_err(write_error)?;
+    let mut zip = ZipWriter::new(BufWriter::new(file));
+
+    // LMS importers look for the manifest at the package root
+    zip.add("imsmanifest.xml", manifest.as_bytes(), Compression::Deflated).map_err(write_error)?;
+
+    if options.xapi.enabled {
+        let tincan = storypack_template::render(&env, "scorm/tincan.xml", &package)?;
+        zip.add("tincan.xml", tincan.as_bytes(), Compression::Deflated).map_err(write_error)?;
+    }
+
+    for name in &package.files {
+        match name.as_str() {
+            "index.html" => zip.add(name, index_html.as_bytes(), Compression::Deflated),
+            TRACKING_FILE => zip.add(name, tracking_js.as_bytes(), Compression::Deflated),
+            _ => zip.add_file(name, &project_path.join(name), Compression::for_path(name)),
+        }
+        .map_err(write_error)?;
+    }
+
+    zip.finish().map_err(write_error)?;
+
+    Ok(zip_path)
+}
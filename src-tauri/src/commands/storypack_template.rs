@@ -0,0 +1,551 @@
+use minijinja::{AutoEscape, Environment, Error, ErrorKind};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::fs;
+use crate::commands::storypack::{Page, StoryspackConfig, VideoSource};
+use crate::commands::storypack_theme::ThemeContext;
+use crate::commands::storypack_images::ResponsiveImage;
+use crate::commands::storypack_audio::{self, AudioSource};
+use crate::commands::storypack_video::{self, VideoFile, WebVideo};
+use crate::commands::storypack_inline;
+use crate::commands::storypack_validate::is_youtube_id;
+
+// Built-in templates, overridable file by file from a user template folder
+const INDEX_HTML: &str = include_str!("../../templates/storypack/index.html");
+const STYLES_CSS: &str = include_str!("../../templates/storypack/styles.css");
+const PLAYER_JS: &str = include_str!("../../templates/storypack/player.js");
+const HLS_LITE_JS: &str = include_str!("../../templates/storypack/hls-lite.js");
+const EPUB_CONTENT_OPF: &str = include_str!("../../templates/epub/content.opf");
+const EPUB_NAV_XHTML: &str = include_str!("../../templates/epub/nav.xhtml");
+const EPUB_PAGE_XHTML: &str = include_str!("../../templates/epub/page.xhtml");
+const EPUB_OVERLAY_SMIL: &str = include_str!("../../templates/epub/overlay.smil");
+const EPUB_STYLE_CSS: &str = include_str!("../../templates/epub/style.css");
+const SCORM_MANIFEST_XML: &str = include_str!("../../templates/scorm/imsmanifest.xml");
+const SCORM_TINCAN_XML: &str = include_str!("../../templates/scorm/tincan.xml");
+const SCORM_TRACKING_JS: &str = include_str!("../../templates/scorm/tracking.js");
+
+/// Files rendered at the root of the storypack folder, in write order.
+pub const OUTPUT_FILES: &[&str] = &["index.html", "styles.css", "player.js"];
+
+/// Fallback HLS player, written only when a video has an HLS ladder.
+pub const HLS_PLAYER_FILE: &str = "hls-lite.js";
+
+const YOUTUBE_ORIGIN: &str = "https://www.youtube.com";
+
+/// Everything templates can see. Field names are documented in
+/// templates/storypack/README.md.
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct TemplateContext {
+    pub project: ProjectContext,
+    pub pages: Vec<PageContext>,
+    pub audio: AudioContext,
+    pub video: Option<VideoContext>,
+    pub has_hls: bool,
+    pub theme: ThemeContext,
+    /// Set for the single-file export
+    pub inline: Option<InlineContext>,
+    /// Set for the LMS package: index.html loads tracking.js
+    pub tracking: bool,
+    /// Content-Security-Policy for index.html; call `refresh_csp` after
+    /// changing `inline` or `tracking`
+    pub csp: String,
+}
+
+/// Rendered CSS and scripts to embed in index.html.
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct InlineContext {
+    pub styles: String,
+    pub scripts: Vec<String>,
+}
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct ProjectContext {
+    pub title: String,
+    pub generator: String,
+}
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct PageContext {
+    pub id: String,
+    pub kind: String,
+    pub title: String,
+    pub image: Option<String>,
+    pub picture: Option<ResponsiveImage>,
+    pub text: Option<String>,
+    pub text_html: Option<String>,
+    pub narration: Vec<String>,
+    pub narration_sources: Vec<Vec<AudioSource>>,
+    pub video: Option<VideoContext>,
+}
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct AudioContext {
+    pub theme: Option<String>,
+    pub theme_sources: Vec<AudioSource>,
+    pub has_narration: bool,
+}
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct VideoContext {
+    pub kind: String,
+    pub src: String,
+    pub mime: String,
+    pub sources: Vec<VideoFile>,
+    pub poster: Option<String>,
+    pub hls: Option<String>,
+}
+
+/// Outputs of the media pipelines. Anything missing here was copied as-is
+/// under its usual name.
+#[derive(Debug, Clone, Default)]
+pub struct GeneratedAssets {
+    /// Optimized images by page id
+    pub pictures: HashMap<String, ResponsiveImage>,
+    /// Transcoded audio by output base name (`theme`, `chapter1-narration1`)
+    pub audio: HashMap<String, Vec<AudioSource>>,
+    /// Transcoded local videos by output base name (`video` or a page id)
+    pub videos: HashMap<String, WebVideo>,
+}
+
+/// Renders every file in `OUTPUT_FILES` into `project_path`.
+pub fn write_storypack_files(
+    config: &StoryspackConfig,
+    pages: &[Page],
+    theme: &ThemeContext,
+    assets: &GeneratedAssets,
+    project_path: &Path,
+) -> Result<(), String> {
+    let context = build_context(config, pages, theme.clone(), assets);
+    let env = environment(config.template_dir.as_deref().map(PathBuf::from));
+
+    let mut names = OUTPUT_FILES.to_vec();
+    if context.has_hls {
+        names.push(HLS_PLAYER_FILE);
+    }
+
+    for name in names {
+        let rendered = render(&env, name, &context)?;
+
+        fs::write(project_path.join(name), rendered)
+            .map_err(|e| format!("Failed to write {}: {}", name, e))?;
+    }
+
+    Ok(())
+}
+
+/// Renders a self-contained index.html into `project_path`, inlining the
+/// assets already written there, then removes what was inlined. Returns
+/// warnings for the user.
+pub fn write_single_file(
+    config: &StoryspackConfig,
+    pages: &[Page],
+    theme: &ThemeContext,
+    assets: &GeneratedAssets,
+    project_path: &Path,
+) -> Result<Vec<String>, String> {
+    let options = &config.single_file;
+    let mut context = build_context(config, pages, theme.clone(), assets);
+    let mut warnings = storypack_inline::inline_assets(&mut context, project_path, options)?;
+    let env = environment(config.template_dir.as_deref().map(PathBuf::from));
+
+    let styles = render(&env, "styles.css", &context)?;
+    let mut scripts = vec![render(&env, "player.js", &context)?];
+    if context.has_hls {
+        scripts.push(render(&env, HLS_PLAYER_FILE, &context)?);
+    }
+
+    context.inline = Some(InlineContext {
+        styles: storypack_inline::escape_inline(&styles, "style"),
+        scripts: scripts.iter()
+            .map(|script| storypack_inline::escape_inline(script, "script"))
+            .collect(),
+    });
+    context.refresh_csp();
+
+    let index_path = project_path.join("index.html");
+    fs::write(&index_path, render(&env, "index.html", &context)?)
+        .map_err(|e| format!("Failed to write index.html: {}", e))?;
+
+    storypack_inline::remove_inlined_files(project_path, options)?;
+
+    let size = fs::metadata(&index_path)
+        .map(|metadata| metadata.len())
+        .unwrap_or(0);
+    if size > options.size_warning_mb * 1024 * 1024 {
+        warnings.push(format!(
+            "index.html is {:.1} MB, over the {} MB warning size; email and LMS uploads may reject it",
+            size as f64 / (1024.0 * 1024.0),
+            options.size_warning_mb
+        ));
+    }
+
+    Ok(warnings)
+}
+
+/// Renders the template `name`, naming it in any error.
+pub fn render<S: serde::Serialize>(env: &Environment, name: &str, context: &S) -> Result<String, String> {
+    env.get_template(name)
+        .and_then(|template| template.render(context))
+        .map_err(|e| format!("Failed to render {}: {}", name, e))
+}
+
+pub fn build_context(
+    config: &StoryspackConfig,
+    pages: &[Page],
+    theme: ThemeContext,
+    assets: &GeneratedAssets,
+) -> TemplateContext {
+    let pages: Vec<PageContext> = pages.iter()
+        .map(|page| page_context(page, assets))
+        .collect();
+    let has_narration = pages.iter().any(|p| !p.narration.is_empty());
+    let theme_sources = config.theme_audio.as_ref()
+        .map(|theme| audio_sources(assets, "theme", theme))
+        .unwrap_or_default();
+
+    let video = config.video_source.as_ref().map(|source| video_context(source, "video", assets));
+    let has_hls = video.iter()
+        .chain(pages.iter().filter_map(|page| page.video.as_ref()))
+        .any(|video| video.hls.is_some());
+
+    let mut context = TemplateContext {
+        project: ProjectContext {
+            title: config.project_name.trim().to_string(),
+            generator: format!("ClipForge Storypack {}", env!("CARGO_PKG_VERSION")),
+        },
+        pages,
+        audio: AudioContext {
+            theme: theme_sources.first().map(|source| source.src.clone()),
+            theme_sources,
+            has_narration,
+        },
+        video,
+        has_hls,
+        theme,
+        inline: None,
+        tracking: false,
+        csp: String::new(),
+    };
+    context.refresh_csp();
+    context
+}
+
+impl TemplateContext {
+    /// Rebuilds `csp` from what index.html will load: its own files, data:
+    /// URIs and inline code for the single-file export, blob: media for the
+    /// HLS player, YouTube frames, hosted video origins, and any xAPI
+    /// endpoint for the LMS package.
+    pub fn refresh_csp(&mut self) {
+        let inline = self.inline.is_some();
+        let videos: Vec<&VideoContext> = self.video.iter()
+            .chain(self.pages.iter().filter_map(|page| page.video.as_ref()))
+            .collect();
+
+        let mut code = vec!["'self'"];
+        let mut assets = vec!["'self'"];
+        if inline {
+            code.push("'unsafe-inline'");
+            assets.push("data:");
+        }
+
+        let mut media: Vec<String> = assets.iter().map(|source| source.to_string()).collect();
+        if self.has_hls {
+            media.push("blob:".to_string());
+        }
+        for video in videos.iter().filter(|video| video.kind == "hosted") {
+            if let Some(origin) = url_origin(&video.src) {
+                if !media.contains(&origin) {
+                    media.push(origin);
+                }
+            }
+        }
+
+        let mut connect = vec!["'self'"];
+        if self.tracking {
+            // The LMS can hand tracking.js any LRS endpoint at launch
+            connect.extend(["https:", "http:"]);
+        }
+
+        let mut directives = vec![
+            "default-src 'none'".to_string(),
+            format!("script-src {}", code.join(" ")),
+            format!("style-src {}", code.join(" ")),
+            format!("img-src {}", assets.join(" ")),
+            format!("font-src {}", assets.join(" ")),
+            format!("media-src {}", media.join(" ")),
+            format!("connect-src {}", connect.join(" ")),
+        ];
+
+        if videos.iter().any(|video| video.kind == "youtube") {
+            directives.push(format!("frame-src {}", YOUTUBE_ORIGIN));
+        }
+
+        directives.extend(["object-src 'none'", "base-uri 'none'", "form-action 'none'"].map(String::from));
+
+        self.csp = directives.join("; ");
+    }
+}
+
+fn page_context(page: &Page, assets: &GeneratedAssets) -> PageContext {
+    let picture = assets.pictures.get(&page.id);
+    let image = match picture {
+        Some(picture) => Some(picture.src.clone()),
+        None => page.image.as_ref()
+            .map(|image| format!("assets/images/{}.{}", page.id, get_extension(image))),
+    };
+
+    let narration_sources: Vec<Vec<AudioSource>> = page.narration.iter()
+        .enumerate()
+        .map(|(idx, clip)| audio_sources(assets, &narration_name(&page.id, idx), clip))
+        .collect();
+
+    PageContext {
+        id: page.id.clone(),
+        kind: page.kind.as_str().to_string(),
+        title: page.display_title().to_string(),
+        image,
+        picture: picture.cloned(),
+        text: page.text.as_ref().map(|text| strip_strong(text)),
+        text_html: page.text.as_ref().map(|text| escape_html(text)),
+        narration: narration_sources.iter()
+            .filter_map(|sources| sources.first().map(|source| source.src.clone()))
+            .collect(),
+        narration_sources,
+        video: page.video.as_ref().map(|source| video_context(source, &page.id, assets)),
+    }
+}
+
+// Transcoded sources when the audio pipeline ran, otherwise the copied file
+fn audio_sources(assets: &GeneratedAssets, name: &str, original: &str) -> Vec<AudioSource> {
+    match assets.audio.get(name) {
+        Some(sources) => sources.clone(),
+        None => vec![AudioSource {
+            src: format!("assets/audio/{}.{}", name, get_extension(original)),
+            mime: storypack_audio::audio_mime(original).to_string(),
+        }],
+    }
+}
+
+// `local_name` is the file name (without extension) a local video was written to
+fn video_context(source: &VideoSource, local_name: &str, assets: &GeneratedAssets) -> VideoContext {
+    let (kind, sources, poster, hls) = match source {
+        VideoSource::YouTube { video_id } => {
+            // Validation rejects bad ids; an empty src keeps one out of the page
+            let video_id = video_id.trim();
+            return VideoContext {
+                kind: "youtube".to_string(),
+                src: if is_youtube_id(video_id) {
+                    format!("{}/embed/{}", YOUTUBE_ORIGIN, video_id)
+                } else {
+                    String::new()
+                },
+                mime: String::new(),
+                sources: Vec::new(),
+                poster: None,
+                hls: None,
+            };
+        }
+        VideoSource::Hosted { url } => {
+            let files = external_url(url)
+                .map(|src| VideoFile {
+                    src,
+                    mime: storypack_video::video_mime(url).to_string(),
+                })
+                .into_iter()
+                .collect();
+            ("hosted", files, None, None)
+        }
+        VideoSource::Local { path } => match assets.videos.get(local_name) {
+            Some(video) => ("local", video.sources.clone(), video.poster.clone(), video.hls.clone()),
+            None => {
+                let file = VideoFile {
+                    src: format!("assets/video/{}.{}", local_name, get_extension(path)),
+                    mime: storypack_video::video_mime(path).to_string(),
+                };
+                ("local", vec![file], None, None)
+            }
+        },
+    };
+
+    // `src`/`mime` name the MP4 (or only) source, for templates that use one
+    let primary = sources.iter()
+        .find(|file| file.mime == "video/mp4")
+        .or(sources.first())
+        .cloned()
+        .unwrap_or(VideoFile { src: String::new(), mime: String::new() });
+
+    VideoContext {
+        kind: kind.to_string(),
+        src: primary.src,
+        mime: primary.mime,
+        sources,
+        poster,
+        hls,
+    }
+}
+
+/// Template environment with the built-in templates, overridden by files in
+/// `user_dir`.
+pub fn environment(user_dir: Option<PathBuf>) -> Environment<'static> {
+    let mut env = Environment::new();
+    env.set_trim_blocks(true);
+    env.set_lstrip_blocks(true);
+    env.set_keep_trailing_newline(true);
+
+    // EPUB files are XML too
+    env.set_auto_escape_callback(|name| {
+        match name.rsplit('.').next() {
+            Some("html" | "htm" | "xml" | "xhtml" | "opf" | "smil") => AutoEscape::Html,
+            _ => AutoEscape::None,
+        }
+    });
+
+    // The default HTML escaping also escapes `/`, which bloats inlined
+    // data URIs; only the characters that can break markup need it
+    env.set_formatter(|out, state, value| {
+        match value.as_str() {
+            Some(text) if state.auto_escape() == AutoEscape::Html && !value.is_safe() => {
+                out.write_str(&escape_markup(text)).map_err(Error::from)
+            }
+            _ => minijinja::escape_formatter(out, state, value),
+        }
+    });
+
+    env.set_loader(move |name| {
+        if name.split(['/', '\\']).any(|part| part == "..") {
+            return Err(Error::new(ErrorKind::InvalidOperation, "template paths may not contain .."));
+        }
+
+        if let Some(dir) = &user_dir {
+            let path = dir.join(name);
+            if path.is_file() {
+                return fs::read_to_string(&path)
+                    .map(Some)
+                    .map_err(|e| Error::new(
+                        ErrorKind::InvalidOperation,
+                        format!("failed to read {}: {}", path.display(), e),
+                    ));
+            }
+        }
+
+        Ok(builtin_template(name).map(|source| source.to_string()))
+    });
+
+    env
+}
+
+fn builtin_template(name: &str) -> Option<&'static str> {
+    match name {
+        "index.html" => Some(INDEX_HTML),
+        "styles.css" => Some(STYLES_CSS),
+        "player.js" => Some(PLAYER_JS),
+        "hls-lite.js" => Some(HLS_LITE_JS),
+        "epub/content.opf" => Some(EPUB_CONTENT_OPF),
+        "epub/nav.xhtml" => Some(EPUB_NAV_XHTML),
+        "epub/page.xhtml" => Some(EPUB_PAGE_XHTML),
+        "epub/overlay.smil" => Some(EPUB_OVERLAY_SMIL),
+        "epub/style.css" => Some(EPUB_STYLE_CSS),
+        "scorm/imsmanifest.xml" => Some(SCORM_MANIFEST_XML),
+        "scorm/tincan.xml" => Some(SCORM_TINCAN_XML),
+        "scorm/tracking.js" => Some(SCORM_TRACKING_JS),
+        _ => None,
+    }
+}
+
+pub fn narration_name(page_id: &str, idx: usize) -> String {
+    format!("{}-narration{}", page_id, idx + 1)
+}
+
+pub fn get_extension(path: &str) -> String {
+    safe_extension(path).unwrap_or_else(|| "jpg".to_string())
+}
+
+/// Extension of `path` with anything but ASCII letters and digits removed,
+/// so asset names never need escaping in URLs.
+pub fn safe_extension(path: &str) -> Option<String> {
+    let extension: String = Path::new(path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("")
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .collect();
+
+    if extension.is_empty() {
+        None
+    } else {
+        Some(extension)
+    }
+}
+
+/// `url` if it is an http(s) URL, with characters that aren't allowed in
+/// URLs percent-encoded. Other schemes (`javascript:`, `data:`, ...) give
+/// `None`.
+pub fn external_url(url: &str) -> Option<String> {
+    let url = url.trim();
+    let scheme = url.split_once("://")?.0.to_lowercase();
+
+    if scheme != "http" && scheme != "https" {
+        return None;
+    }
+
+    let mut encoded = String::with_capacity(url.len());
+    for c in url.chars() {
+        if c.is_ascii_graphic() && !matches!(c, '"' | '\'' | '<' | '>' | '\\' | '^' | '`' | '{' | '|' | '}') {
+            encoded.push(c);
+        } else {
+            let mut buffer = [0u8; 4];
+            for byte in c.encode_utf8(&mut buffer).bytes() {
+                encoded.push_str(&format!("%{:02X}", byte));
+            }
+        }
+    }
+
+    Some(encoded)
+}
+
+/// `scheme://host[:port]` of an absolute URL, as used in CSP source lists.
+/// Hosts with anything but letters, digits, dots and dashes give `None`, so
+/// a crafted URL can't add its own sources to the policy.
+pub fn url_origin(url: &str) -> Option<String> {
+    let (scheme, rest) = url.split_once("://")?;
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let host = authority.rsplit('@').next()?;
+
+    let (name, port) = match host.split_once(':') {
+        Some((name, port)) => (name, Some(port)),
+        None => (host, None),
+    };
+
+    let valid_name = !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-');
+    let valid_port = port.is_none_or(|port| !port.is_empty() && port.chars().all(|c| c.is_ascii_digit()));
+
+    if valid_name && valid_port {
+        Some(format!("{}://{}", scheme.to_lowercase(), host.to_lowercase()))
+    } else {
+        None
+    }
+}
+
+pub fn escape_markup(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+// Helper to escape HTML but preserve <strong> tags
+pub fn escape_html(text: &str) -> String {
+    // First escape everything
+    let escaped = escape_markup(text);
+
+    // Then unescape <strong> and </strong> tags
+    escaped
+        .replace("&lt;strong&gt;", "<strong>")
+        .replace("&lt;/strong&gt;", "</strong>")
+}
+
+fn strip_strong(text: &str) -> String {
+    text.replace("<strong>", "").replace("</strong>", "")
+}
\ No newline at end of file
@@ -0,0 +1,447 @@
+use std::path::Path;
+use std::fs;
+
+/// Theme choice in the storypack config: a built-in preset plus optional
+/// overrides. Fonts can be a bundled font name ("Source Serif 4"), a path to
+/// a .woff2/.woff/.ttf/.otf file, or a CSS font-family list.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Default)]
+pub struct ThemeConfig {
+    #[serde(default)]
+    pub preset: ThemePreset,
+    #[serde(default)]
+    pub primary_color: Option<String>,
+    #[serde(default)]
+    pub accent_color: Option<String>,
+    #[serde(default)]
+    pub background: Option<String>,
+    #[serde(default)]
+    pub page_font: Option<String>,
+    #[serde(default)]
+    pub heading_font: Option<String>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ThemePreset {
+    #[default]
+    ClassicBook,
+    Dark,
+    HighContrast,
+    KidsBright,
+    Minimal,
+}
+
+/// Resolved theme values handed to the templates as `theme`.
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct ThemeContext {
+    pub name: String,
+    pub primary: String,
+    pub primary_dark: String,
+    pub accent: String,
+    pub accent_dark: String,
+    pub background: String,
+    pub surface: String,
+    pub page_background: String,
+    pub text_color: String,
+    pub muted_color: String,
+    pub border_color: String,
+    pub button_text: String,
+    pub ui_font: String,
+    pub page_font: String,
+    pub heading_font: String,
+    pub font_faces: Vec<FontFaceContext>,
+}
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct FontFaceContext {
+    pub family: String,
+    pub src: String,
+    pub format: String,
+    pub weight: u16,
+}
+
+/// A resolved theme plus the font files it needs in `assets/fonts`.
+#[derive(Debug, Clone)]
+pub struct Theme {
+    pub context: ThemeContext,
+    font_files: Vec<FontFile>,
+}
+
+#[derive(Debug, Clone)]
+enum FontFile {
+    Bundled { file_name: &'static str, data: &'static [u8] },
+    Local { file_name: String, source: String },
+    License { file_name: &'static str, data: &'static [u8] },
+}
+
+struct BundledFont {
+    family: &'static str,
+    fallback: &'static str,
+    faces: &'static [(u16, &'static str, &'static [u8])],
+    license: (&'static str, &'static [u8]),
+}
+
+const SOURCE_SERIF: BundledFont = BundledFont {
+    family: "Source Serif 4",
+    fallback: "Georgia, 'Times New Roman', serif",
+    faces: &[
+        (400, "source-serif-4-regular.woff2", include_bytes!("../../fonts/source-serif-4-regular.woff2")),
+        (700, "source-serif-4-bold.woff2", include_bytes!("../../fonts/source-serif-4-bold.woff2")),
+    ],
+    license: ("SourceSerif4-LICENSE.md", include_bytes!("../../fonts/SourceSerif4-LICENSE.md")),
+};
+
+const FIRA_SANS: BundledFont = BundledFont {
+    family: "Fira Sans",
+    fallback: "-apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, sans-serif",
+    faces: &[
+        (400, "fira-sans-regular.woff2", include_bytes!("../../fonts/fira-sans-regular.woff2")),
+        (500, "fira-sans-medium.woff2", include_bytes!("../../fonts/fira-sans-medium.woff2")),
+    ],
+    license: ("FiraSans-LICENSE.txt", include_bytes!("../../fonts/FiraSans-LICENSE.txt")),
+};
+
+const OPEN_SANS: BundledFont = BundledFont {
+    family: "Open Sans",
+    fallback: "'Helvetica Neue', Arial, sans-serif",
+    faces: &[
+        (400, "open-sans-regular.woff2", include_bytes!("../../fonts/open-sans-regular.woff2")),
+        (700, "open-sans-bold.woff2", include_bytes!("../../fonts/open-sans-bold.woff2")),
+    ],
+    license: ("OpenSans-LICENSE.txt", include_bytes!("../../fonts/OpenSans-LICENSE.txt")),
+};
+
+const BUNDLED_FONTS: [&BundledFont; 3] = [&SOURCE_SERIF, &FIRA_SANS, &OPEN_SANS];
+
+struct Preset {
+    name: &'static str,
+    primary: &'static str,
+    primary_dark: &'static str,
+    accent: &'static str,
+    accent_dark: &'static str,
+    background: &'static str,
+    surface: &'static str,
+    page_background: &'static str,
+    text_color: &'static str,
+    muted_color: &'static str,
+    border_color: &'static str,
+    button_text: &'static str,
+    page_font: &'static BundledFont,
+    heading_font: &'static BundledFont,
+}
+
+impl ThemePreset {
+    fn preset(self) -> Preset {
+        match self {
+            ThemePreset::ClassicBook => Preset {
+                name: "classic_book",
+                primary: "#667eea",
+                primary_dark: "#5568d3",
+                accent: "#764ba2",
+                accent_dark: "#5e3882",
+                background: "linear-gradient(135deg, #667eea 0%, #764ba2 100%)",
+                surface: "#ffffff",
+                page_background: "#f5f5f5",
+                text_color: "#333333",
+                muted_color: "#666666",
+                border_color: "#e0e0e0",
+                button_text: "#ffffff",
+                page_font: &SOURCE_SERIF,
+                heading_font: &FIRA_SANS,
+            },
+            ThemePreset::Dark => Preset {
+                name: "dark",
+                primary: "#8ab4f8",
+                primary_dark: "#6c9be8",
+                accent: "#bb86fc",
+                accent_dark: "#9a67ea",
+                background: "#121212",
+                surface: "#1e1e1e",
+                page_background: "#181818",
+                text_color: "#e8e8e8",
+                muted_color: "#a0a0a0",
+                border_color: "#333333",
+                button_text: "#121212",
+                page_font: &SOURCE_SERIF,
+                heading_font: &FIRA_SANS,
+            },
+            ThemePreset::HighContrast => Preset {
+                name: "high_contrast",
+                primary: "#000000",
+                primary_dark: "#333333",
+                accent: "#0000cc",
+                accent_dark: "#000099",
+                background: "#ffffff",
+                surface: "#ffffff",
+                page_background: "#ffffff",
+                text_color: "#000000",
+                muted_color: "#000000",
+                border_color: "#000000",
+                button_text: "#ffffff",
+                page_font: &FIRA_SANS,
+                heading_font: &FIRA_SANS,
+            },
+            ThemePreset::KidsBright => Preset {
+                name: "kids_bright",
+                primary: "#ff6f3c",
+                primary_dark: "#e85a28",
+                accent: "#00b8a9",
+                accent_dark: "#009688",
+                background: "linear-gradient(135deg, #ffde7d 0%, #f6416c 100%)",
+                surface: "#fffdf5",
+                page_background: "#fff3d6",
+                text_color: "#2d2d2d",
+                muted_color: "#555555",
+                border_color: "#ffd59e",
+                button_text: "#ffffff",
+                page_font: &OPEN_SANS,
+                heading_font: &FIRA_SANS,
+            },
+            ThemePreset::Minimal => Preset {
+                name: "minimal",
+                primary: "#222222",
+                primary_dark: "#000000",
+                accent: "#555555",
+                accent_dark: "#333333",
+                background: "#fafafa",
+                surface: "#ffffff",
+                page_background: "#ffffff",
+                text_color: "#222222",
+                muted_color: "#777777",
+                border_color: "#eeeeee",
+                button_text: "#ffffff",
+                page_font: &OPEN_SANS,
+                heading_font: &OPEN_SANS,
+            },
+        }
+    }
+}
+
+impl ThemeConfig {
+    /// The same theme with font files pointing at their copies in the
+    /// storypack (`assets/fonts/...`) instead of the original paths.
+    pub fn with_bundled_font_paths(&self) -> ThemeConfig {
+        let relink = |font: &Option<String>| font.as_ref().map(|choice| match local_font(choice.trim()) {
+            Some(local) => format!("assets/fonts/{}", local.file_name),
+            None => choice.clone(),
+        });
+
+        ThemeConfig {
+            page_font: relink(&self.page_font),
+            heading_font: relink(&self.heading_font),
+            ..self.clone()
+        }
+    }
+}
+
+/// Applies the overrides in `config` on top of its preset.
+pub fn resolve_theme(config: &ThemeConfig) -> Result<Theme, String> {
+    let preset = config.preset.preset();
+    let mut font_files = Vec::new();
+    let mut font_faces = Vec::new();
+
+    let mut font_stack = |choice: &Option<String>, default: &'static BundledFont| -> Result<String, String> {
+        match choice.as_deref().map(str::trim).filter(|c| !c.is_empty()) {
+            None => Ok(use_bundled(default, &mut font_files, &mut font_faces)),
+            Some(choice) => resolve_font(choice, &mut font_files, &mut font_faces),
+        }
+    };
+
+    let page_font = font_stack(&config.page_font, preset.page_font)?;
+    let heading_font = font_stack(&config.heading_font, preset.heading_font)?;
+
+    let (primary, primary_dark) = match &config.primary_color {
+        Some(color) if !color.trim().is_empty() => {
+            let color = css_value(color, "primary color")?;
+            (color.clone(), darker(&color))
+        }
+        _ => (preset.primary.to_string(), preset.primary_dark.to_string()),
+    };
+
+    let (accent, accent_dark) = match &config.accent_color {
+        Some(color) if !color.trim().is_empty() => {
+            let color = css_value(color, "accent color")?;
+            (color.clone(), darker(&color))
+        }
+        _ => (preset.accent.to_string(), preset.accent_dark.to_string()),
+    };
+
+    let background = match &config.background {
+        Some(background) if !background.trim().is_empty() => css_value(background, "background")?,
+        _ => preset.background.to_string(),
+    };
+
+    Ok(Theme {
+        context: ThemeContext {
+            name: preset.name.to_string(),
+            primary,
+            primary_dark,
+            accent,
+            accent_dark,
+            background,
+            surface: preset.surface.to_string(),
+            page_background: preset.page_background.to_string(),
+            text_color: preset.text_color.to_string(),
+            muted_color: preset.muted_color.to_string(),
+            border_color: preset.border_color.to_string(),
+            button_text: preset.button_text.to_string(),
+            ui_font: heading_font.clone(),
+            page_font,
+            heading_font,
+            font_faces,
+        },
+        font_files,
+    })
+}
+
+impl Theme {
+    /// Copies the theme's fonts (and their licenses) into `fonts_path`.
+    pub fn write_fonts(&self, fonts_path: &Path) -> Result<(), String> {
+        if self.font_files.is_empty() {
+            return Ok(());
+        }
+
+        fs::create_dir_all(fonts_path)
+            .map_err(|e| format!("Failed to create fonts folder: {}", e))?;
+
+        for font in &self.font_files {
+            match font {
+                FontFile::Bundled { file_name, data } | FontFile::License { file_name, data } => {
+                    fs::write(fonts_path.join(file_name), data)
+                        .map_err(|e| format!("Failed to write font {}: {}", file_name, e))?;
+                }
+                FontFile::Local { file_name, source } => {
+                    fs::copy(source, fonts_path.join(file_name))
+                        .map_err(|e| format!("Failed to copy font {}: {}", source, e))?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn resolve_font(
+    choice: &str,
+    font_files: &mut Vec<FontFile>,
+    font_faces: &mut Vec<FontFaceContext>,
+) -> Result<String, String> {
+    let bundled = BUNDLED_FONTS.iter().find(|font| {
+        font.family.eq_ignore_ascii_case(choice)
+            || font.family.replace(' ', "-").eq_ignore_ascii_case(choice)
+    });
+
+    if let Some(font) = bundled {
+        return Ok(use_bundled(font, font_files, font_faces));
+    }
+
+    if let Some(LocalFont { family, file_name, format }) = local_font(choice) {
+        if !Path::new(choice).is_file() {
+            return Err(format!("Font file not found: {}", choice));
+        }
+
+        font_faces.push(FontFaceContext {
+            family: family.clone(),
+            src: format!("assets/fonts/{}", file_name),
+            format: format.to_string(),
+            weight: 400,
+        });
+        font_files.push(FontFile::Local { file_name, source: choice.to_string() });
+
+        return Ok(format!("'{}', serif", family));
+    }
+
+    // Anything else is a CSS font-family list of system fonts
+    let allowed = |c: char| c.is_alphanumeric() || matches!(c, ' ' | ',' | '-' | '\'' | '"');
+    if choice.chars().all(allowed) {
+        Ok(choice.to_string())
+    } else {
+        Err(format!("Invalid font: {}", choice))
+    }
+}
+
+/// Whether a font choice names a font file rather than a bundled font or a
+/// CSS font-family list.
+pub fn is_font_file(choice: &str) -> bool {
+    local_font(choice.trim()).is_some()
+}
+
+struct LocalFont {
+    family: String,
+    /// Name of the copy in `assets/fonts`
+    file_name: String,
+    format: &'static str,
+}
+
+// Font choices with a font file extension are paths; the family is named
+// after the file
+fn local_font(choice: &str) -> Option<LocalFont> {
+    let path = Path::new(choice);
+    let extension = path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase())?;
+    let format = font_format(&extension)?;
+
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("font");
+    let family: String = stem
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { ' ' })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    let file_name = format!("{}.{}", family.replace(' ', "-").to_lowercase(), extension);
+
+    Some(LocalFont { family, file_name, format })
+}
+
+fn use_bundled(
+    font: &'static BundledFont,
+    font_files: &mut Vec<FontFile>,
+    font_faces: &mut Vec<FontFaceContext>,
+) -> String {
+    let already_added = font_faces.iter().any(|face| face.family == font.family);
+
+    if !already_added {
+        for (weight, file_name, data) in font.faces {
+            font_faces.push(FontFaceContext {
+                family: font.family.to_string(),
+                src: format!("assets/fonts/{}", file_name),
+                format: "woff2".to_string(),
+                weight: *weight,
+            });
+            font_files.push(FontFile::Bundled { file_name, data });
+        }
+
+        let (file_name, data) = font.license;
+        font_files.push(FontFile::License { file_name, data });
+    }
+
+    format!("'{}', {}", font.family, font.fallback)
+}
+
+fn font_format(extension: &str) -> Option<&'static str> {
+    match extension {
+        "woff2" => Some("woff2"),
+        "woff" => Some("woff"),
+        "ttf" => Some("truetype"),
+        "otf" => Some("opentype"),
+        _ => None,
+    }
+}
+
+// Values end up inside styles.css, so only plain color/gradient syntax is allowed
+fn css_value(value: &str, field: &str) -> Result<String, String> {
+    let value = value.trim();
+    let allowed = |c: char| c.is_ascii_alphanumeric() || matches!(c, '#' | '(' | ')' | ',' | '.' | '%' | ' ' | '-');
+
+    if value.chars().all(allowed) {
+        Ok(value.to_string())
+    } else {
+        Err(format!("Invalid {}: {}", field, value))
+    }
+}
+
+fn darker(color: &str) -> String {
+    format!("color-mix(in srgb, {} 85%, black)", color)
+}
\ No newline at end of file
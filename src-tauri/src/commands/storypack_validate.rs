@@ -0,0 +1,439 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::Path;
+use crate::commands::media_tools::{self, MediaTool};
+use crate::commands::storypack::{self, Page, StoryspackConfig, VideoSource};
+use crate::commands::storypack_template;
+use crate::commands::storypack_theme;
+use crate::commands::transcript;
+
+// Formats browsers play as-is, and formats that only work once FFmpeg has
+// converted them
+pub const IMAGE_FORMATS: &[&str] = &["png", "jpg", "jpeg", "gif", "webp", "avif", "svg"];
+pub const IMAGE_CONVERTIBLE: &[&str] = &["bmp", "tif", "tiff"];
+pub const AUDIO_FORMATS: &[&str] = &["mp3", "m4a", "aac", "ogg", "oga", "opus", "wav", "webm", "flac"];
+pub const AUDIO_CONVERTIBLE: &[&str] = &["aif", "aiff", "wma", "caf", "amr"];
+pub const VIDEO_FORMATS: &[&str] = &["mp4", "m4v", "webm", "ogv", "mov"];
+pub const VIDEO_CONVERTIBLE: &[&str] = &["mkv", "avi", "wmv", "flv", "mts", "m2ts", "3gp", "mpg", "mpeg"];
+
+/// One problem with a storypack config. `field` is the path of the setting
+/// it refers to, such as `pages[2].image` or `video_source`.
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct ValidationIssue {
+    pub field: String,
+    pub message: String,
+}
+
+/// Everything `validate_storypack` found. Errors stop generation, warnings
+/// only affect the result.
+#[derive(serde::Serialize, Debug, Clone, Default)]
+pub struct ValidationReport {
+    pub errors: Vec<ValidationIssue>,
+    pub warnings: Vec<ValidationIssue>,
+}
+
+#[derive(Clone, Copy)]
+enum MediaKind {
+    Image,
+    Audio,
+    Video,
+}
+
+impl MediaKind {
+    fn label(self) -> &'static str {
+        match self {
+            MediaKind::Image => "image",
+            MediaKind::Audio => "audio",
+            MediaKind::Video => "video",
+        }
+    }
+
+    fn formats(self) -> (&'static [&'static str], &'static [&'static str]) {
+        match self {
+            MediaKind::Image => (IMAGE_FORMATS, IMAGE_CONVERTIBLE),
+            MediaKind::Audio => (AUDIO_FORMATS, AUDIO_CONVERTIBLE),
+            MediaKind::Video => (VIDEO_FORMATS, VIDEO_CONVERTIBLE),
+        }
+    }
+
+    // The option that converts other formats for this kind
+    fn conversion_option(self) -> &'static str {
+        match self {
+            MediaKind::Image => "image optimization with FFmpeg",
+            MediaKind::Audio => "audio transcoding",
+            MediaKind::Video => "video transcoding",
+        }
+    }
+}
+
+impl ValidationReport {
+    pub fn is_valid(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    pub fn describe(&self) -> String {
+        let mut message = String::from("The storypack settings have problems:\n");
+
+        for issue in &self.errors {
+            message.push_str(&format!("  ✗ {}: {}\n", issue.field, issue.message));
+        }
+
+        message
+    }
+
+    fn error(&mut self, field: impl Into<String>, message: impl Into<String>) {
+        self.errors.push(ValidationIssue { field: field.into(), message: message.into() });
+    }
+
+    fn warning(&mut self, field: impl Into<String>, message: impl Into<String>) {
+        self.warnings.push(ValidationIssue { field: field.into(), message: message.into() });
+    }
+}
+
+/// Checks the whole config before anything is written: input files, formats,
+/// narration against pages, page text, the project name, video sources and
+/// the media tools the enabled options need.
+pub fn validate(config: &StoryspackConfig) -> ValidationReport {
+    let mut report = ValidationReport::default();
+
+    check_project_name(&mut report, &config.project_name);
+
+    let (pages, transcript_warnings) = config.resolve();
+    let page_fields = page_fields(config);
+
+    if pages.is_empty() {
+        report.error("pages", "The storypack has no pages");
+    }
+
+    // Images. Without FFmpeg, optimization only copies them, and browsers
+    // can't show a copied BMP or TIFF.
+    let converts_images = config.images.optimize && media_tools::locate(MediaTool::FFmpeg).is_some();
+    if config.pages.is_empty() {
+        for (page, field) in pages.iter().zip(&page_fields) {
+            if let Some(image) = &page.image {
+                check_media(&mut report, field, image, MediaKind::Image, converts_images);
+            }
+        }
+    } else {
+        for (idx, page) in config.pages.iter().enumerate() {
+            if let Some(image) = &page.image {
+                check_media(&mut report, format!("pages[{}].image", idx), image, MediaKind::Image, converts_images);
+            }
+        }
+    }
+
+    check_narration(&mut report, config, &pages);
+
+    if let Some(theme) = &config.theme_audio {
+        check_media(&mut report, "theme_audio", theme, MediaKind::Audio, config.audio.transcode);
+    }
+
+    // Videos
+    for (idx, page) in config.pages.iter().enumerate() {
+        if let Some(video) = &page.video {
+            check_video(&mut report, &format!("pages[{}].video", idx), video, config.video.transcode);
+        }
+    }
+
+    if let Some(video) = &config.video_source {
+        check_video(&mut report, "video_source", video, config.video.transcode);
+    }
+
+    check_media_tools(&mut report, config);
+
+    // Page text
+    for warning in transcript_warnings {
+        report.warning("transcriptions", warning);
+    }
+
+    for idx in transcript::pages_without_text(&pages) {
+        let field = if config.pages.is_empty() {
+            "transcriptions".to_string()
+        } else {
+            format!("pages[{}].text", idx)
+        };
+        report.warning(field, format!("Page \"{}\" has no text", pages[idx].id));
+    }
+
+    if let Some(dir) = &config.template_dir {
+        if !Path::new(dir).is_dir() {
+            report.error("template_dir", format!("Template folder not found: {}", dir));
+        }
+    }
+
+    if let Err(e) = storypack_theme::resolve_theme(&config.theme) {
+        report.error("theme", e);
+    }
+
+    if config.lms.xapi.enabled {
+        if let Some(endpoint) = config.lms.xapi.endpoint.as_deref().filter(|e| !e.trim().is_empty()) {
+            check_url(&mut report, "lms.xapi.endpoint", endpoint);
+        }
+
+        if config.lms.xapi.auth.as_deref().is_some_and(|auth| !auth.trim().is_empty()) {
+            report.warning(
+                "lms.xapi.auth",
+                "The LRS credentials are readable by anyone with the package; only use them for local testing",
+            );
+        }
+    }
+
+    report
+}
+
+fn check_project_name(report: &mut ValidationReport, name: &str) {
+    let field = "project_name";
+
+    if name.trim().is_empty() {
+        report.error(field, "Enter a project name");
+        return;
+    }
+
+    match storypack::project_folder_name(name) {
+        Ok(folder) if folder != name.trim() => {
+            report.warning(field, format!("The storypack folder will be named \"{}\"", folder));
+        }
+        Ok(_) => {}
+        Err(e) => report.error(field, e),
+    }
+}
+
+// Image optimization falls back to copying without FFmpeg, but audio and
+// video transcoding can't run at all
+fn check_media_tools(report: &mut ValidationReport, config: &StoryspackConfig) {
+    let ffmpeg = media_tools::locate(MediaTool::FFmpeg).is_some();
+    let ffprobe = media_tools::locate(MediaTool::FFprobe).is_some();
+    let fix = "Set its location on the Settings tab or turn the option off";
+
+    if config.images.optimize && !ffmpeg {
+        report.warning("images.optimize", "FFmpeg was not found, so images will be copied without optimizing them");
+    }
+
+    if config.audio.transcode && !ffmpeg {
+        report.error("audio.transcode", format!("Audio transcoding needs FFmpeg, which was not found. {}", fix));
+    }
+
+    let local_video = config.pages.iter()
+        .filter_map(|page| page.video.as_ref())
+        .chain(config.video_source.as_ref())
+        .any(|video| matches!(video, VideoSource::Local { .. }));
+
+    if config.video.transcode && local_video {
+        if !ffmpeg {
+            report.error("video.transcode", format!("Video transcoding needs FFmpeg, which was not found. {}", fix));
+        } else if !ffprobe {
+            report.error("video.transcode", format!("Video transcoding needs ffprobe, which was not found. {}", fix));
+        }
+    }
+}
+
+// Config field of each resolved page's image, for configs using the fixed
+// cover/prologue/chapters/epilogue/credits fields
+fn page_fields(config: &StoryspackConfig) -> Vec<String> {
+    let mut fields = Vec::new();
+
+    if config.cover_image.is_some() {
+        fields.push("cover_image".to_string());
+    }
+
+    if config.prologue_image.is_some() {
+        fields.push("prologue_image".to_string());
+    }
+
+    for idx in 0..config.chapter_images.len() {
+        fields.push(format!("chapter_images[{}]", idx));
+    }
+
+    if config.epilogue_image.is_some() {
+        fields.push("epilogue_image".to_string());
+    }
+
+    if config.credits_image.is_some() {
+        fields.push("credits_image".to_string());
+    }
+
+    fields
+}
+
+fn check_narration(report: &mut ValidationReport, config: &StoryspackConfig, pages: &[Page]) {
+    // Every clip that ends up on a page, with the field it came from
+    let mut clips: Vec<(String, &str)> = Vec::new();
+
+    for (idx, page) in config.pages.iter().enumerate() {
+        for (clip_idx, clip) in page.narration.iter().enumerate() {
+            clips.push((format!("pages[{}].narration[{}]", idx, clip_idx), clip));
+        }
+    }
+
+    // The old layout hands out one file per page, in order
+    if config.pages.is_empty() && config.narration.is_empty() {
+        for (idx, clip) in config.narration_audio.iter().enumerate() {
+            let field = format!("narration_audio[{}]", idx);
+
+            if idx < pages.len() {
+                clips.push((field, clip));
+            } else {
+                report.error(field, format!("More narration files than pages; {} would not be played", clip));
+            }
+        }
+
+        let narrated = config.narration_audio.len();
+        if narrated > 0 && narrated < pages.len() {
+            let silent: Vec<String> = pages[narrated..].iter().map(|p| format!("\"{}\"", p.id)).collect();
+            report.warning("narration_audio", format!(
+                "{} narration files for {} pages; {} will have no narration",
+                narrated, pages.len(), silent.join(", ")
+            ));
+        }
+    }
+
+    for (idx, assignment) in config.narration.iter().enumerate() {
+        if pages.iter().any(|p| p.id == assignment.page_id) {
+            clips.push((format!("narration[{}].path", idx), &assignment.path));
+        } else {
+            report.error(format!("narration[{}].page_id", idx), format!(
+                "No page \"{}\" for {}",
+                assignment.page_id, assignment.path
+            ));
+        }
+    }
+
+    let mut first_use: HashMap<&str, &str> = HashMap::new();
+
+    for (field, clip) in &clips {
+        match first_use.get(clip) {
+            Some(first) => report.error(field.clone(), format!("{} is already used by {}", clip, first)),
+            None => {
+                first_use.insert(clip, field);
+                check_media(report, field.clone(), clip, MediaKind::Audio, config.audio.transcode);
+            }
+        }
+    }
+}
+
+fn check_video(report: &mut ValidationReport, field: &str, video: &VideoSource, transcode: bool) {
+    match video {
+        VideoSource::YouTube { video_id } => {
+            if !is_youtube_id(video_id.trim()) {
+                let message = match youtube_id_from_url(video_id) {
+                    Some(id) => format!("Enter just the YouTube video id ({}), not the link", id),
+                    None => format!("\"{}\" is not a YouTube video id (11 letters, digits, - or _)", video_id),
+                };
+                report.error(format!("{}.video_id", field), message);
+            }
+        }
+        VideoSource::Hosted { url } => {
+            let field = format!("{}.url", field);
+            check_url(report, &field, url);
+
+            // index.html's Content-Security-Policy only lists plain host names
+            let allowed = storypack_template::external_url(url)
+                .and_then(|url| storypack_template::url_origin(&url))
+                .is_some();
+            if !allowed && report.errors.iter().all(|issue| issue.field != field) {
+                report.warning(field, format!("{} has an unusual host name, so browsers will block the video", url.trim()));
+            }
+        }
+        VideoSource::Local { path } => {
+            check_media(report, format!("{}.path", field), path, MediaKind::Video, transcode);
+        }
+    }
+}
+
+fn check_media(report: &mut ValidationReport, field: impl Into<String>, path: &str, kind: MediaKind, converts: bool) {
+    let field = field.into();
+
+    if path.trim().is_empty() {
+        report.error(field, format!("No {} file selected", kind.label()));
+        return;
+    }
+
+    let file = Path::new(path);
+
+    if !file.exists() {
+        report.error(field, format!("File not found: {}", path));
+        return;
+    }
+
+    if file.is_dir() {
+        report.error(field, format!("Expected a file but found a folder: {}", path));
+        return;
+    }
+
+    if let Err(e) = File::open(file) {
+        report.error(field, format!("Can't read {}: {}", path, e));
+        return;
+    }
+
+    let extension = file.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase())
+        .unwrap_or_default();
+    let (playable, convertible) = kind.formats();
+
+    if playable.contains(&extension.as_str()) {
+        return;
+    }
+
+    if convertible.contains(&extension.as_str()) {
+        if !converts {
+            report.error(field, format!(
+                "Browsers can't play .{} {} files; turn on {} or convert {} first",
+                extension, kind.label(), kind.conversion_option(), path
+            ));
+        }
+        return;
+    }
+
+    let format = if extension.is_empty() { "files without an extension".to_string() } else { format!(".{} files", extension) };
+    report.error(field, format!("Unsupported {} format ({}): {}", kind.label(), format, path));
+}
+
+fn check_url(report: &mut ValidationReport, field: &str, url: &str) {
+    let url = url.trim();
+    let lower = url.to_lowercase();
+
+    let rest = if let Some(rest) = lower.strip_prefix("https://") {
+        rest
+    } else if let Some(rest) = lower.strip_prefix("http://") {
+        report.warning(field, format!("{} uses plain HTTP; browsers block it on pages served over HTTPS", url));
+        rest
+    } else {
+        report.error(field, format!("\"{}\" is not a web address (it should start with https://)", url));
+        return;
+    };
+
+    let host = rest.split(['/', '?', '#']).next().unwrap_or("");
+    let host = host.rsplit('@').next().unwrap_or(host);
+
+    if host.is_empty() || host.starts_with(':') {
+        report.error(field, format!("{} has no host name", url));
+    } else if let Some(c) = url.chars().find(|c| c.is_whitespace() || matches!(c, '"' | '<' | '>' | '\\' | '`')) {
+        report.error(field, format!("{} contains {:?}; encode it or remove it", url, c));
+    }
+}
+
+/// YouTube ids are 11 characters from the URL-safe base64 alphabet.
+pub fn is_youtube_id(id: &str) -> bool {
+    id.len() == 11 && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Pulls the id out of a watch, share, embed or shorts link.
+pub fn youtube_id_from_url(url: &str) -> Option<String> {
+    let url = url.trim();
+
+    if !url.contains("youtu") {
+        return None;
+    }
+
+    let candidate = url.split(['?', '&'])
+        .find_map(|part| part.strip_prefix("v="))
+        .or_else(|| {
+            ["youtu.be/", "/embed/", "/shorts/", "/live/"]
+                .iter()
+                .find_map(|marker| url.split_once(marker).map(|(_, rest)| rest))
+        })?;
+
+    let id: String = candidate.chars().take_while(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_').collect();
+    is_youtube_id(&id).then_some(id)
+}
@@ -0,0 +1,419 @@
+use std::path::Path;
+use std::fs;
+use crate::commands::media_tools::{self, MediaTool};
+
+/// How local videos are prepared for the web.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct VideoOptions {
+    /// Convert to H.264/AAC MP4; when false files are copied as-is
+    pub transcode: bool,
+    /// x264 CRF, lower is better quality
+    pub crf: u32,
+    pub audio_bitrate_kbps: u32,
+    /// Also write a VP9/Opus WebM
+    pub webm: bool,
+    /// Grab a frame for the `poster` attribute
+    pub poster: bool,
+    /// Also package an HLS ladder under `assets/video/hls/`
+    pub hls: bool,
+    /// Rendition heights; ones taller than the source are skipped
+    pub hls_renditions: Vec<u32>,
+}
+
+impl Default for VideoOptions {
+    fn default() -> Self {
+        VideoOptions {
+            transcode: true,
+            crf: 23,
+            audio_bitrate_kbps: 128,
+            webm: false,
+            poster: true,
+            hls: false,
+            hls_renditions: vec![360, 720, 1080],
+        }
+    }
+}
+
+/// A prepared local video as the templates see it.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct WebVideo {
+    /// `<source>` list, preferred first
+    pub sources: Vec<VideoFile>,
+    pub poster: Option<String>,
+    /// HLS master playlist URL
+    pub hls: Option<String>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct VideoFile {
+    pub src: String,
+    pub mime: String,
+}
+
+struct VideoInfo {
+    video_codec: Option<String>,
+    width: Option<u32>,
+    height: Option<u32>,
+    pix_fmt: Option<String>,
+    frame_rate: Option<f64>,
+    audio_codec: Option<String>,
+    duration: Option<f64>,
+}
+
+/// Transcodes local videos. Create one per storypack so the encoder check
+/// runs once.
+pub struct VideoPipeline {
+    options: VideoOptions,
+    webm: bool,
+}
+
+impl VideoPipeline {
+    /// Returns `None` when transcoding is turned off.
+    pub fn new(options: &VideoOptions) -> Result<Option<Self>, String> {
+        if !options.transcode {
+            return Ok(None);
+        }
+
+        let mut webm = options.webm;
+
+        if webm {
+            let encoders = media_tools::available_encoders()?;
+            let missing: Vec<&str> = ["libvpx-vp9", "libopus"].into_iter()
+                .filter(|encoder| !encoders.iter().any(|e| e == encoder))
+                .collect();
+
+            if !missing.is_empty() {
+                println!("Warning: FFmpeg has no {} encoder, skipping WebM video", missing.join(" or "));
+                webm = false;
+            }
+        }
+
+        Ok(Some(VideoPipeline {
+            options: options.clone(),
+            webm,
+        }))
+    }
+
+    /// Writes `<base_name>.mp4` (plus `.webm`, `-poster.jpg` and
+    /// `hls/<base_name>/` when enabled) into `dest_folder`.
+    pub fn process(
+        &self,
+        source: &str,
+        dest_folder: &Path,
+        base_name: &str,
+        url_prefix: &str,
+    ) -> Result<WebVideo, String> {
+        let info = probe_video(source)?;
+        let mut sources = Vec::new();
+
+        if self.webm {
+            let file_name = format!("{}.webm", base_name);
+            println!("Encoding WebM: {}", file_name);
+
+            run_ffmpeg(source, &[
+                "-c:v", "libvpx-vp9", "-crf", "32", "-b:v", "0", "-row-mt", "1",
+                "-c:a", "libopus", "-b:a", &format!("{}k", self.options.audio_bitrate_kbps),
+                "-f", "webm",
+            ], &dest_folder.join(&file_name))?;
+
+            sources.push(VideoFile {
+                src: format!("{}/{}", url_prefix, file_name),
+                mime: "video/webm".to_string(),
+            });
+        }
+
+        let file_name = format!("{}.mp4", base_name);
+        println!("Preparing MP4: {}", file_name);
+        run_ffmpeg(source, &self.mp4_args(&info), &dest_folder.join(&file_name))?;
+
+        sources.push(VideoFile {
+            src: format!("{}/{}", url_prefix, file_name),
+            mime: "video/mp4".to_string(),
+        });
+
+        let poster = if self.options.poster && info.video_codec.is_some() {
+            let poster_name = format!("{}-poster.jpg", base_name);
+            write_poster(source, info.duration, &dest_folder.join(&poster_name))?;
+            Some(format!("{}/{}", url_prefix, poster_name))
+        } else {
+            None
+        };
+
+        let hls = if self.options.hls {
+            let hls_folder = dest_folder.join("hls").join(base_name);
+            self.package_hls(source, &info, &hls_folder)?;
+            Some(format!("{}/hls/{}/master.m3u8", url_prefix, base_name))
+        } else {
+            None
+        };
+
+        Ok(WebVideo { sources, poster, hls })
+    }
+
+    // One fMP4 media playlist per rendition plus a master playlist. Keyframes
+    // every 2 seconds keep segments aligned so players can switch between them.
+    fn package_hls(&self, source: &str, info: &VideoInfo, hls_folder: &Path) -> Result<(), String> {
+        let (source_width, source_height) = match (info.width, info.height) {
+            (Some(w), Some(h)) if w > 0 && h > 0 => (w, h),
+            _ => return Err(format!("Cannot package {} for HLS: no video stream", source)),
+        };
+
+        let mut heights: Vec<u32> = self.options.hls_renditions.iter()
+            .copied()
+            .filter(|h| *h > 0 && *h <= source_height)
+            .collect();
+        if heights.is_empty() {
+            heights.push(source_height);
+        }
+        heights.sort_unstable();
+        heights.dedup();
+
+        let mut master = String::from("#EXTM3U\n#EXT-X-VERSION:7\n#EXT-X-INDEPENDENT-SEGMENTS\n");
+
+        for height in heights {
+            let height = height / 2 * 2;
+            let width = ((height as f64 * source_width as f64 / source_height as f64) / 2.0).round() as u32 * 2;
+            let (video_kbps, audio_kbps) = ladder_bitrates(height);
+            let level = h264_level(width, height, info.frame_rate.unwrap_or(30.0));
+
+            let rendition = format!("{}p", height);
+            let rendition_folder = hls_folder.join(&rendition);
+            fs::create_dir_all(&rendition_folder)
+                .map_err(|e| format!("Failed to create HLS folder: {}", e))?;
+
+            println!("Packaging HLS rendition: {}", rendition);
+
+            let mut args: Vec<String> = vec![
+                "-map".into(), "0:v:0".into(),
+                "-vf".into(), format!("scale={}:{}", width, height),
+                "-c:v".into(), "libx264".into(),
+                "-preset".into(), "medium".into(),
+                "-profile:v".into(), "main".into(),
+                "-level".into(), format!("{}.{}", level / 10, level % 10),
+                "-pix_fmt".into(), "yuv420p".into(),
+                "-b:v".into(), format!("{}k", video_kbps),
+                "-maxrate".into(), format!("{}k", video_kbps * 107 / 100),
+                "-bufsize".into(), format!("{}k", video_kbps * 3 / 2),
+                "-force_key_frames".into(), "expr:gte(t,n_forced*2)".into(),
+                "-sc_threshold".into(), "0".into(),
+            ];
+
+            if info.audio_codec.is_some() {
+                args.extend([
+                    "-map".into(), "0:a:0".into(),
+                    "-c:a".into(), "aac".into(),
+                    "-b:a".into(), format!("{}k", audio_kbps),
+                    "-ac".into(), "2".into(),
+                    "-ar".into(), "48000".into(),
+                ]);
+            }
+
+            args.extend([
+                "-f".into(), "hls".into(),
+                "-hls_time".into(), "6".into(),
+                "-hls_playlist_type".into(), "vod".into(),
+                "-hls_segment_type".into(), "fmp4".into(),
+                "-hls_fmp4_init_filename".into(), "init.mp4".into(),
+                "-hls_segment_filename".into(),
+                rendition_folder.join("segment_%03d.m4s").to_string_lossy().to_string(),
+            ]);
+
+            run_ffmpeg(source, &args, &rendition_folder.join("index.m3u8"))?;
+
+            let bandwidth = (video_kbps * 107 / 100 + if info.audio_codec.is_some() { audio_kbps } else { 0 }) * 1000;
+            // Main profile with constraint_set1, as x264 writes it
+            let mut codecs = format!("avc1.4d40{:02x}", level);
+            if info.audio_codec.is_some() {
+                codecs.push_str(",mp4a.40.2");
+            }
+
+            master.push_str(&format!(
+                "#EXT-X-STREAM-INF:BANDWIDTH={},RESOLUTION={}x{},CODECS=\"{}\"\n{}/index.m3u8\n",
+                bandwidth, width, height, codecs, rendition
+            ));
+        }
+
+        fs::write(hls_folder.join("master.m3u8"), master)
+            .map_err(|e| format!("Failed to write HLS master playlist: {}", e))
+    }
+
+    // Copies streams browsers already play and re-encodes the rest
+    fn mp4_args(&self, info: &VideoInfo) -> Vec<String> {
+        let copy_video = info.video_codec.as_deref() == Some("h264")
+            && info.pix_fmt.as_deref() == Some("yuv420p");
+        let copy_audio = info.audio_codec.is_none() || info.audio_codec.as_deref() == Some("aac");
+
+        let mut args: Vec<String> = Vec::new();
+
+        if copy_video {
+            args.extend(["-c:v".into(), "copy".into()]);
+        } else {
+            args.extend([
+                "-c:v".into(), "libx264".into(),
+                "-preset".into(), "medium".into(),
+                "-crf".into(), self.options.crf.to_string(),
+                "-pix_fmt".into(), "yuv420p".into(),
+                // x264 needs even dimensions
+                "-vf".into(), "scale=trunc(iw/2)*2:trunc(ih/2)*2".into(),
+            ]);
+        }
+
+        if copy_audio {
+            args.extend(["-c:a".into(), "copy".into()]);
+        } else {
+            args.extend([
+                "-c:a".into(), "aac".into(),
+                "-b:a".into(), format!("{}k", self.options.audio_bitrate_kbps),
+            ]);
+        }
+
+        args.extend(["-movflags".into(), "+faststart".into(), "-f".into(), "mp4".into()]);
+        args
+    }
+}
+
+// H.264 levels as (level_idc, max macroblocks per second, max macroblocks
+// per frame). The ladder bitrates stay under every level's cap.
+const H264_LEVELS: &[(u32, f64, u32)] = &[
+    (30, 40_500.0, 1_620),
+    (31, 108_000.0, 3_600),
+    (32, 216_000.0, 5_120),
+    (40, 245_760.0, 8_192),
+    (42, 522_240.0, 8_704),
+    (50, 589_824.0, 22_080),
+    (51, 983_040.0, 36_864),
+    (52, 2_073_600.0, 36_864),
+];
+
+// level_idc of the lowest level that fits a rendition, e.g. 40 for 1080p30
+// and 42 for 1080p60
+fn h264_level(width: u32, height: u32, fps: f64) -> u32 {
+    let frame_size = width.div_ceil(16) * height.div_ceil(16);
+    let per_second = frame_size as f64 * fps;
+
+    H264_LEVELS.iter()
+        .find(|(_, max_per_second, max_frame_size)| frame_size <= *max_frame_size && per_second <= *max_per_second)
+        .map(|(level, ..)| *level)
+        .unwrap_or(52)
+}
+
+// (video, audio) kbps for a rendition height
+fn ladder_bitrates(height: u32) -> (u32, u32) {
+    match height {
+        0..=360 => (800, 96),
+        361..=480 => (1400, 128),
+        481..=720 => (2800, 128),
+        _ => (5000, 192),
+    }
+}
+
+/// MIME type for a video file or URL served without transcoding.
+pub fn video_mime(path: &str) -> &'static str {
+    let path = path.split(['?', '#']).next().unwrap_or(path);
+    let extension = Path::new(path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase())
+        .unwrap_or_default();
+
+    match extension.as_str() {
+        "webm" => "video/webm",
+        "ogv" | "ogg" => "video/ogg",
+        "mov" => "video/quicktime",
+        "mkv" => "video/x-matroska",
+        "avi" => "video/x-msvideo",
+        "m3u8" => "application/vnd.apple.mpegurl",
+        _ => "video/mp4",
+    }
+}
+
+fn probe_video(source: &str) -> Result<VideoInfo, String> {
+    let output = media_tools::ffprobe()
+        .args([
+            "-v", "error",
+            "-show_entries", "stream=codec_type,codec_name,pix_fmt,width,height,avg_frame_rate,r_frame_rate:format=duration",
+            "-of", "json",
+            source,
+        ])
+        .output()
+        .map_err(|e| media_tools::spawn_error(MediaTool::FFprobe, e))?;
+
+    if !output.status.success() {
+        return Err(format!("Failed to read video {}", source));
+    }
+
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout)
+        .map_err(|e| format!("Failed to parse ffprobe output: {}", e))?;
+
+    let streams = json["streams"].as_array().cloned().unwrap_or_default();
+    let first_of = |kind: &str| streams.iter().find(|s| s["codec_type"] == kind);
+
+    let video = first_of("video");
+    let audio = first_of("audio");
+
+    Ok(VideoInfo {
+        video_codec: video.and_then(|s| s["codec_name"].as_str()).map(String::from),
+        width: video.and_then(|s| s["width"].as_u64()).map(|w| w as u32),
+        height: video.and_then(|s| s["height"].as_u64()).map(|h| h as u32),
+        pix_fmt: video.and_then(|s| s["pix_fmt"].as_str()).map(String::from),
+        frame_rate: video.and_then(|s| parse_rate(&s["avg_frame_rate"]).or_else(|| parse_rate(&s["r_frame_rate"]))),
+        audio_codec: audio.and_then(|s| s["codec_name"].as_str()).map(String::from),
+        duration: json["format"]["duration"].as_str().and_then(|d| d.parse().ok()),
+    })
+}
+
+// ffprobe rates are fractions such as "30000/1001"; "0/0" means unknown
+fn parse_rate(value: &serde_json::Value) -> Option<f64> {
+    let (num, den) = value.as_str()?.split_once('/')?;
+    let (num, den): (f64, f64) = (num.parse().ok()?, den.parse().ok()?);
+
+    if num > 0.0 && den > 0.0 {
+        Some(num / den)
+    } else {
+        None
+    }
+}
+
+// A frame a little way in, since the first frame is often black
+fn write_poster(source: &str, duration: Option<f64>, dest: &Path) -> Result<(), String> {
+    let seek = duration.map(|d| (d * 0.1).min(5.0)).unwrap_or(0.0);
+
+    let output = media_tools::ffmpeg()
+        .args(["-y", "-v", "error", "-ss", &format!("{:.2}", seek), "-i", source])
+        .args(["-frames:v", "1", "-map_metadata", "-1", "-q:v", "3"])
+        .arg(dest)
+        .output()
+        .map_err(|e| media_tools::spawn_error(MediaTool::FFmpeg, e))?;
+
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(format!(
+            "Failed to create poster for {}: {}",
+            source,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+    }
+}
+
+fn run_ffmpeg<S: AsRef<std::ffi::OsStr>>(source: &str, args: &[S], dest: &Path) -> Result<(), String> {
+    let output = media_tools::ffmpeg()
+        .args(["-y", "-v", "error", "-i", source])
+        // Subtitle and data tracks rarely fit the target container
+        .args(["-sn", "-dn", "-map_metadata", "-1"])
+        .args(args)
+        .arg(dest)
+        .output()
+        .map_err(|e| media_tools::spawn_error(MediaTool::FFmpeg, e))?;
+
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(format!(
+            "Failed to convert {}: {}",
+            source,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+    }
+}
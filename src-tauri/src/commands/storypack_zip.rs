@@ -0,0 +1,400 @@
+use flate2::write::DeflateEncoder;
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use crate::commands::storypack;
+
+// DOS date for 1980-01-01 00:00, the earliest a ZIP can express. Every entry
+// gets it so the same input always produces the same archive.
+const FIXED_DOS_TIME: u16 = 0;
+const FIXED_DOS_DATE: u16 = (1 << 5) | 1;
+
+// Names are UTF-8
+const FLAG_UTF8: u16 = 1 << 11;
+
+/// Name of the checksum manifest inside the archive, in `sha256sum` format
+/// so `sha256sum -c SHA256SUMS` verifies an unpacked storypack.
+pub const CHECKSUM_MANIFEST: &str = "SHA256SUMS";
+
+/// Zips the finished storypack folder into `<project>.zip` next to it.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct ArchiveOptions {
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Compression {
+    Stored,
+    Deflated,
+}
+
+impl Compression {
+    fn method(self) -> u16 {
+        match self {
+            Compression::Stored => 0,
+            Compression::Deflated => 8,
+        }
+    }
+
+    /// Already-compressed media is stored, everything else deflated.
+    pub fn for_path(path: &str) -> Compression {
+        let extension = Path::new(path)
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase())
+            .unwrap_or_default();
+
+        match extension.as_str() {
+            "jpg" | "jpeg" | "png" | "gif" | "webp" | "avif"
+            | "mp3" | "m4a" | "aac" | "ogg" | "opus" | "webm" | "flac"
+            | "mp4" | "m4v" | "mov" | "mkv" | "m4s" | "ts"
+            | "woff" | "woff2" | "zip" | "epub" => Compression::Stored,
+            _ => Compression::Deflated,
+        }
+    }
+}
+
+struct CentralEntry {
+    name: String,
+    compression: Compression,
+    crc: u32,
+    compressed_size: u32,
+    size: u32,
+    offset: u32,
+}
+
+/// A small ZIP writer with byte-for-byte reproducible output: entries are
+/// written in the order they are added, all with the same timestamp. No
+/// ZIP64, so entries and the archive must stay under 4 GB.
+pub struct ZipWriter<W: Write> {
+    out: W,
+    offset: u64,
+    entries: Vec<CentralEntry>,
+}
+
+impl<W: Write> ZipWriter<W> {
+    pub fn new(out: W) -> Self {
+        ZipWriter { out, offset: 0, entries: Vec::new() }
+    }
+
+    /// Adds an entry from memory.
+    pub fn add(&mut self, name: &str, data: &[u8], compression: Compression) -> io::Result<()> {
+        let crc = crc32fast::hash(data);
+
+        match compression {
+            Compression::Stored => self.write_entry(name, compression, crc, data.len() as u64, data),
+            Compression::Deflated => {
+                let compressed = deflate(data)?;
+                self.write_entry(name, compression, crc, data.len() as u64, &compressed)
+            }
+        }
+    }
+
+    /// Adds an entry from a file. Stored files are streamed rather than
+    /// read into memory, since they are usually large media.
+    pub fn add_file(&mut self, name: &str, path: &Path, compression: Compression) -> io::Result<()> {
+        if compression == Compression::Deflated {
+            let data = std::fs::read(path)?;
+            return self.add(name, &data, compression);
+        }
+
+        let mut file = BufReader::new(File::open(path)?);
+        let mut hasher = crc32fast::Hasher::new();
+        let mut buffer = [0u8; 64 * 1024];
+        let mut size = 0u64;
+
+        loop {
+            let read = file.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            hasher.update(&buffer[..read]);
+            size += read as u64;
+        }
+
+        file.rewind()?;
+
+        self.write_header(name, compression, hasher.finalize(), size, size)?;
+        let copied = io::copy(&mut file, &mut self.out)?;
+        self.offset += copied;
+
+        Ok(())
+    }
+
+    /// Writes the central directory and returns the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        let directory_start = self.offset;
+        let mut directory = Vec::new();
+
+        for entry in &self.entries {
+            directory.extend_from_slice(&0x0201_4b50u32.to_le_bytes());
+            directory.extend_from_slice(&20u16.to_le_bytes()); // version made by
+            directory.extend_from_slice(&20u16.to_le_bytes()); // version needed
+            directory.extend_from_slice(&FLAG_UTF8.to_le_bytes());
+            directory.extend_from_slice(&entry.compression.method().to_le_bytes());
+            directory.extend_from_slice(&FIXED_DOS_TIME.to_le_bytes());
+            directory.extend_from_slice(&FIXED_DOS_DATE.to_le_bytes());
+            directory.extend_from_slice(&entry.crc.to_le_bytes());
+            directory.extend_from_slice(&entry.compressed_size.to_le_bytes());
+            directory.extend_from_slice(&entry.size.to_le_bytes());
+            directory.extend_from_slice(&(entry.name.len() as u16).to_le_bytes());
+            directory.extend_from_slice(&0u16.to_le_bytes()); // extra field length
+            directory.extend_from_slice(&0u16.to_le_bytes()); // comment length
+            directory.extend_from_slice(&0u16.to_le_bytes()); // disk number
+            directory.extend_from_slice(&0u16.to_le_bytes()); // internal attributes
+            directory.extend_from_slice(&0u32.to_le_bytes()); // external attributes
+            directory.extend_from_slice(&entry.offset.to_le_bytes());
+            directory.extend_from_slice(entry.name.as_bytes());
+        }
+
+        let count = u16::try_from(self.entries.len()).map_err(|_| too_large("entry count"))?;
+        let directory_size = u32::try_from(directory.len()).map_err(|_| too_large("central directory"))?;
+        let directory_start = u32::try_from(directory_start).map_err(|_| too_large("archive"))?;
+
+        directory.extend_from_slice(&0x0605_4b50u32.to_le_bytes());
+        directory.extend_from_slice(&0u16.to_le_bytes()); // this disk
+        directory.extend_from_slice(&0u16.to_le_bytes()); // disk with directory
+        directory.extend_from_slice(&count.to_le_bytes());
+        directory.extend_from_slice(&count.to_le_bytes());
+        directory.extend_from_slice(&directory_size.to_le_bytes());
+        directory.extend_from_slice(&directory_start.to_le_bytes());
+        directory.extend_from_slice(&0u16.to_le_bytes()); // comment length
+
+        self.out.write_all(&directory)?;
+        self.out.flush()?;
+
+        Ok(self.out)
+    }
+
+    fn write_entry(&mut self, name: &str, compression: Compression, crc: u32, size: u64, data: &[u8]) -> io::Result<()> {
+        self.write_header(name, compression, crc, data.len() as u64, size)?;
+        self.out.write_all(data)?;
+        self.offset += data.len() as u64;
+        Ok(())
+    }
+
+    fn write_header(&mut self, name: &str, compression: Compression, crc: u32, compressed_size: u64, size: u64) -> io::Result<()> {
+        let offset = u32::try_from(self.offset).map_err(|_| too_large("archive"))?;
+        let compressed_size = u32::try_from(compressed_size).map_err(|_| too_large(name))?;
+        let size = u32::try_from(size).map_err(|_| too_large(name))?;
+
+        let mut header = Vec::with_capacity(30 + name.len());
+        header.extend_from_slice(&0x0403_4b50u32.to_le_bytes());
+        header.extend_from_slice(&20u16.to_le_bytes()); // version needed
+        header.extend_from_slice(&FLAG_UTF8.to_le_bytes());
+        header.extend_from_slice(&compression.method().to_le_bytes());
+        header.extend_from_slice(&FIXED_DOS_TIME.to_le_bytes());
+        header.extend_from_slice(&FIXED_DOS_DATE.to_le_bytes());
+        header.extend_from_slice(&crc.to_le_bytes());
+        header.extend_from_slice(&compressed_size.to_le_bytes());
+        header.extend_from_slice(&size.to_le_bytes());
+        header.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        header.extend_from_slice(&0u16.to_le_bytes()); // extra field length
+        header.extend_from_slice(name.as_bytes());
+
+        self.out.write_all(&header)?;
+        self.offset += header.len() as u64;
+
+        self.entries.push(CentralEntry {
+            name: name.to_string(),
+            compression,
+            crc,
+            compressed_size,
+            size,
+            offset,
+        });
+
+        Ok(())
+    }
+}
+
+/// Writes `<project>.zip` next to `project_path`. Entries sit under a
+/// `<project>/` folder in sorted path order, followed by a checksum manifest,
+/// so the same folder always zips to the same bytes.
+pub fn write_archive(project_path: &Path) -> Result<PathBuf, String> {
+    let project_name = project_path.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .ok_or("Storypack folder has no name")?;
+
+    let files: Vec<String> = list_files(project_path)
+        .map_err(|e| format!("Failed to list storypack files: {}", e))?
+        .into_iter()
+        .filter(|relative| relative != CHECKSUM_MANIFEST)
+        .collect();
+
+    let zip_path = storypack::export_path(project_path, ".zip");
+    let write_error = |e: io::Error| format!("Failed to write {}: {}", zip_path.display(), e);
+
+    let file = File::create(&zip_path).map_err(write_error)?;
+    let mut zip = ZipWriter::new(BufWriter::new(file));
+    let mut manifest = String::new();
+
+    for relative in &files {
+        let source = project_path.join(relative);
+        let name = format!("{}/{}", project_name, relative);
+
+        zip.add_file(&name, &source, Compression::for_path(relative)).map_err(write_error)?;
+
+        let hash = sha256_file(&source).map_err(|e| format!("Failed to hash {}: {}", relative, e))?;
+        manifest.push_str(&format!("{}  {}\n", hash, relative));
+    }
+
+    zip.add(&format!("{}/{}", project_name, CHECKSUM_MANIFEST), manifest.as_bytes(), Compression::Deflated)
+        .map_err(write_error)?;
+    zip.finish().map_err(write_error)?;
+
+    Ok(zip_path)
+}
+
+/// Every file under `root` as a relative path with `/` separators, sorted
+/// so archives list them in the same order on every platform.
+pub fn list_files(root: &Path) -> io::Result<Vec<String>> {
+    let mut files = Vec::new();
+    collect_files(root, "", &mut files)?;
+    files.sort();
+    Ok(files)
+}
+
+fn collect_files(dir: &Path, prefix: &str, files: &mut Vec<String>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        let relative = if prefix.is_empty() { name } else { format!("{}/{}", prefix, name) };
+
+        if entry.file_type()?.is_dir() {
+            collect_files(&entry.path(), &relative, files)?;
+        } else {
+            files.push(relative);
+        }
+    }
+
+    Ok(())
+}
+
+/// Hex SHA-256 of a file's contents.
+pub fn sha256_file(path: &Path) -> io::Result<String> {
+    let mut file = BufReader::new(File::open(path)?);
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)?;
+
+    Ok(hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+fn deflate(data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut encoder = DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(data)?;
+    encoder.finish()
+}
+
+fn too_large(what: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, format!("{} is too large for a ZIP file (4 GB limit)", what))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::DeflateDecoder;
+    use std::io::Cursor;
+
+    fn u16_at(bytes: &[u8], at: usize) -> u16 {
+        u16::from_le_bytes([bytes[at], bytes[at + 1]])
+    }
+
+    fn u32_at(bytes: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+    }
+
+    fn write_zip(add: impl FnOnce(&mut ZipWriter<Cursor<Vec<u8>>>)) -> Vec<u8> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        add(&mut zip);
+        zip.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn local_header_has_the_crc() {
+        let bytes = write_zip(|zip| zip.add("hello.txt", b"hello world", Compression::Stored).unwrap());
+
+        assert_eq!(u32_at(&bytes, 0), 0x0403_4b50);
+        assert_eq!(u16_at(&bytes, 8), 0);
+        // CRC-32 of "hello world"
+        assert_eq!(u32_at(&bytes, 14), 0x0d4a_1185);
+        assert_eq!(u32_at(&bytes, 18), 11);
+        assert_eq!(u32_at(&bytes, 22), 11);
+        assert_eq!(&bytes[30..39], b"hello.txt");
+        assert_eq!(&bytes[39..50], b"hello world");
+    }
+
+    #[test]
+    fn central_directory_points_at_every_entry() {
+        let page = "<p>Once upon a time</p>\n".repeat(50);
+        let media = std::env::temp_dir().join(format!("storypack-zip-{}.mp3", std::process::id()));
+        fs::write(&media, [7u8; 1000]).unwrap();
+
+        let entries: [(&str, Vec<u8>, u16); 3] = [
+            ("mimetype", b"application/epub+zip".to_vec(), 0),
+            ("OEBPS/page.xhtml", page.clone().into_bytes(), 8),
+            ("OEBPS/audio/n\u{e4}rration.mp3", vec![7u8; 1000], 0),
+        ];
+
+        let bytes = write_zip(|zip| {
+            zip.add(entries[0].0, &entries[0].1, Compression::Stored).unwrap();
+            zip.add(entries[1].0, page.as_bytes(), Compression::Deflated).unwrap();
+            zip.add_file(entries[2].0, &media, Compression::Stored).unwrap();
+        });
+        let _ = fs::remove_file(&media);
+
+        // End of central directory record, without a comment
+        let end = bytes.len() - 22;
+        assert_eq!(u32_at(&bytes, end), 0x0605_4b50);
+        assert_eq!(u16_at(&bytes, end + 8), 3);
+        assert_eq!(u16_at(&bytes, end + 10), 3);
+        let directory_size = u32_at(&bytes, end + 12) as usize;
+        let directory_start = u32_at(&bytes, end + 16) as usize;
+        assert_eq!(directory_start + directory_size, end);
+
+        let mut at = directory_start;
+        for (name, data, method) in &entries {
+            assert_eq!(u32_at(&bytes, at), 0x0201_4b50);
+            assert_eq!(u16_at(&bytes, at + 8), FLAG_UTF8);
+            assert_eq!(u16_at(&bytes, at + 10), *method);
+            assert_eq!(u16_at(&bytes, at + 12), FIXED_DOS_TIME);
+            assert_eq!(u16_at(&bytes, at + 14), FIXED_DOS_DATE);
+            let crc = u32_at(&bytes, at + 16);
+            let compressed_size = u32_at(&bytes, at + 20) as usize;
+            assert_eq!(crc, crc32fast::hash(data));
+            assert_eq!(u32_at(&bytes, at + 24) as usize, data.len());
+            let name_len = u16_at(&bytes, at + 28) as usize;
+            let offset = u32_at(&bytes, at + 42) as usize;
+            assert_eq!(&bytes[at + 46..at + 46 + name_len], name.as_bytes());
+
+            // The local header it points at agrees, and the data unpacks
+            assert_eq!(u32_at(&bytes, offset), 0x0403_4b50);
+            assert_eq!(u32_at(&bytes, offset + 14), crc);
+            assert_eq!(&bytes[offset + 30..offset + 30 + name_len], name.as_bytes());
+            let stored = &bytes[offset + 30 + name_len..][..compressed_size];
+            let unpacked = if *method == 8 {
+                let mut out = Vec::new();
+                DeflateDecoder::new(stored).read_to_end(&mut out).unwrap();
+                out
+            } else {
+                stored.to_vec()
+            };
+            assert_eq!(&unpacked, data);
+
+            at += 46 + name_len;
+        }
+        assert_eq!(at, end);
+    }
+
+    #[test]
+    fn same_entries_give_identical_archives() {
+        let build = || write_zip(|zip| {
+            zip.add("a.txt", b"one", Compression::Deflated).unwrap();
+            zip.add("b.png", b"two", Compression::Stored).unwrap();
+        });
+
+        assert_eq!(build(), build());
+    }
+}
@@ -0,0 +1,188 @@
+use std::collections::HashMap;
+use crate::commands::storypack::Page;
+
+/// Narration text for one page. `page_id` is set when the text came from
+/// transcribing that page's own narration; otherwise the page is taken from
+/// the text's heading ("Chapter 3: ...").
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+#[serde(from = "TranscriptInput")]
+pub struct Transcript {
+    pub page_id: Option<String>,
+    pub text: String,
+}
+
+// Older configs send plain strings
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum TranscriptInput {
+    Text(String),
+    Tagged {
+        #[serde(default)]
+        page_id: Option<String>,
+        text: String,
+    },
+}
+
+impl From<TranscriptInput> for Transcript {
+    fn from(input: TranscriptInput) -> Self {
+        match input {
+            TranscriptInput::Text(text) => Transcript { page_id: None, text },
+            TranscriptInput::Tagged { page_id, text } => Transcript {
+                page_id: page_id.filter(|id| !id.trim().is_empty()),
+                text,
+            },
+        }
+    }
+}
+
+/// Fills in `text` on pages that don't have their own, and returns warnings
+/// for text that couldn't be placed and text claimed by two pages.
+pub fn assign_transcripts(pages: &mut [Page], transcripts: &[Transcript]) -> Vec<String> {
+    let mut warnings = Vec::new();
+    let mut texts: HashMap<String, Vec<String>> = HashMap::new();
+
+    for (idx, transcript) in transcripts.iter().enumerate() {
+        let heading_id = heading_page_id(&transcript.text);
+
+        let page_id = match (&transcript.page_id, heading_id) {
+            (Some(tagged), Some(heading)) if *tagged != heading => {
+                warnings.push(format!(
+                    "Transcript {} is tagged for page \"{}\" but its heading points to \"{}\"; using \"{}\"",
+                    idx + 1, tagged, heading, tagged
+                ));
+                tagged.clone()
+            }
+            (Some(tagged), _) => tagged.clone(),
+            (None, Some(heading)) => heading,
+            (None, None) => {
+                warnings.push(format!(
+                    "Transcript {} could not be matched to a page (starts with \"{}\")",
+                    idx + 1,
+                    preview(&transcript.text)
+                ));
+                continue;
+            }
+        };
+
+        if !pages.iter().any(|p| p.id == page_id) {
+            warnings.push(format!(
+                "Transcript {} is for page \"{}\", which is not in this storypack",
+                idx + 1, page_id
+            ));
+            continue;
+        }
+
+        texts.entry(page_id).or_default().push(transcript.text.clone());
+    }
+
+    for page in pages.iter_mut() {
+        let Some(page_texts) = texts.remove(&page.id) else {
+            continue;
+        };
+
+        if page.text.is_some() {
+            warnings.push(format!(
+                "Page \"{}\" has its own text and a transcript; keeping the page text",
+                page.id
+            ));
+            continue;
+        }
+
+        if page_texts.len() > 1 {
+            warnings.push(format!(
+                "Page \"{}\" matched {} transcripts; they were joined in order",
+                page.id,
+                page_texts.len()
+            ));
+        }
+
+        page.text = Some(page_texts.join("\n\n"));
+    }
+
+    warnings
+}
+
+/// Indexes of pages without text. Only worth mentioning when the story has
+/// text at all, so a picture book without any text reports none.
+pub fn pages_without_text(pages: &[Page]) -> Vec<usize> {
+    if !pages.iter().any(|p| p.text.is_some()) {
+        return Vec::new();
+    }
+
+    pages.iter()
+        .enumerate()
+        .filter(|(_, page)| page.text.is_none())
+        .map(|(idx, _)| idx)
+        .collect()
+}
+
+/// Page id named by the heading at the start of `text`, either a leading
+/// `<strong>...</strong>` or the first line. The heading must be exactly a
+/// page name, optionally followed by a separator and a title:
+/// "Prologue", "Chapter 10: The Storm", "Interlude two - Night".
+pub fn heading_page_id(text: &str) -> Option<String> {
+    let text = text.trim_start();
+
+    let heading = match text.strip_prefix("<strong>") {
+        Some(rest) => rest.split("</strong>").next()?,
+        None => text.lines().next()?,
+    };
+
+    parse_heading(heading)
+}
+
+fn parse_heading(heading: &str) -> Option<String> {
+    let lower = heading.trim().to_lowercase();
+    let (kind, rest) = split_word(&lower);
+
+    let (page_id, rest) = match kind {
+        "cover" | "prologue" | "epilogue" | "credits" => (kind.to_string(), rest),
+        "chapter" | "interlude" => {
+            let (number, rest) = split_word(rest.trim_start());
+            (format!("{}{}", kind, parse_number(number)?), rest)
+        }
+        _ => return None,
+    };
+
+    // "Cover your eyes..." is narration, not a heading
+    let rest = rest.trim_start();
+    if rest.is_empty() || rest.starts_with([':', '.', '-', '–', '—']) {
+        Some(page_id)
+    } else {
+        None
+    }
+}
+
+fn split_word(text: &str) -> (&str, &str) {
+    let end = text
+        .find(|c: char| !c.is_alphanumeric())
+        .unwrap_or(text.len());
+
+    text.split_at(end)
+}
+
+fn parse_number(word: &str) -> Option<u32> {
+    const WORDS: [&str; 20] = [
+        "one", "two", "three", "four", "five", "six", "seven", "eight", "nine", "ten",
+        "eleven", "twelve", "thirteen", "fourteen", "fifteen", "sixteen", "seventeen",
+        "eighteen", "nineteen", "twenty",
+    ];
+
+    word.parse().ok().or_else(|| {
+        WORDS
+            .iter()
+            .position(|w| *w == word)
+            .map(|idx| idx as u32 + 1)
+    })
+}
+
+fn preview(text: &str) -> String {
+    let first_line = text.trim().lines().next().unwrap_or("");
+    let mut preview: String = first_line.chars().take(40).collect();
+
+    if first_line.chars().count() > 40 {
+        preview.push('…');
+    }
+
+    preview
+}
\ No newline at end of file
@@ -3,7 +3,10 @@
 
 mod commands;
 
-use commands::cloudflare::transcribe_audio;
+use commands::cloudflare::{
+    transcribe_audio,
+    transcribe_page
+};
 
 use commands::publish::{
     select_storypack_folder,
@@ -17,12 +20,28 @@ use commands::clipforge::{
     concat_videos, 
     get_file_size, 
     confirm_dialog,
-    get_video_duration
+    get_video_duration,
+    preview_concat_videos
+};
+
+use commands::media_tools::{
+    check_media_tools,
+    set_media_tool_paths,
+    get_media_tool_paths,
+    select_media_tool
+};
+
+use commands::proxy::{
+    generate_proxies,
+    get_proxy_path
 };
 
 use commands::storypack::{
     select_output_folder,
     generate_storypack,
+    check_narration,
+    check_transcripts,
+    validate_storypack,
     select_image_file,
     select_image_files,
     select_audio_file,
@@ -30,10 +49,30 @@ use commands::storypack::{
     open_folder
 };
 
+use commands::storypack_import::{
+    import_storypack
+};
+
+use commands::storypack_project::{
+    save_storypack_project,
+    open_storypack_project,
+    select_storypack_project,
+    select_storypack_project_save_path
+};
+
+use commands::storypack_autofill::{
+    autofill_storypack_from_folder,
+    select_asset_folder
+};
+
 fn main() {
     tauri::Builder::default()
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_fs::init())
+        .setup(|app| {
+            commands::media_tools::load_settings(app.handle());
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
 			select_video_files,
 			select_output_path,
@@ -41,8 +80,25 @@ fn main() {
 			get_file_size,
 			confirm_dialog,
 			get_video_duration,
+			preview_concat_videos,
+			generate_proxies,
+			get_proxy_path,
+			check_media_tools,
+			set_media_tool_paths,
+			get_media_tool_paths,
+			select_media_tool,
 			select_output_folder,
 			generate_storypack,
+			check_narration,
+			check_transcripts,
+			validate_storypack,
+			import_storypack,
+			save_storypack_project,
+			open_storypack_project,
+			select_storypack_project,
+			select_storypack_project_save_path,
+			autofill_storypack_from_folder,
+			select_asset_folder,
 			select_image_file,
 			select_image_files,
 			select_audio_file,
@@ -51,7 +107,8 @@ fn main() {
 			select_storypack_folder,
 			test_ftp_connection,
 			upload_to_ftp,
-			transcribe_audio
+			transcribe_audio,
+			transcribe_page
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -0,0 +1,235 @@
+// Reports reading progress to the LMS. SCORM 1.2 is used when the LMS
+// provides its API; xAPI statements are sent when an LRS endpoint is set,
+// either below or through the usual launch parameters (endpoint, auth,
+// actor, registration, activity_id).
+(function () {
+    const CONFIG = {{ config }};
+    const VERBS = {
+        launched: 'http://adlnet.gov/expapi/verbs/launched',
+        experienced: 'http://adlnet.gov/expapi/verbs/experienced',
+        progressed: 'http://adlnet.gov/expapi/verbs/progressed',
+        completed: 'http://adlnet.gov/expapi/verbs/completed',
+        terminated: 'http://adlnet.gov/expapi/verbs/terminated'
+    };
+    const PROGRESS_EXTENSION = 'https://w3id.org/xapi/cmi5/result/extensions/progress';
+
+    const player = window.storypackPlayer;
+    if (!player) return;
+
+    const total = player.pageIds.length;
+    const viewed = new Set();
+    const startedAt = Date.now();
+    let completed = false;
+    let finished = false;
+
+    // SCORM 1.2 ------------------------------------------------------------
+
+    // The API object lives on a parent frame or the window that opened us
+    function findScormApi() {
+        let win = window;
+        for (let depth = 0; depth < 10; depth++) {
+            if (win.API) return win.API;
+            if (win.parent === win) break;
+            win = win.parent;
+        }
+        return window.opener && window.opener.API ? window.opener.API : null;
+    }
+
+    let scorm = null;
+    try {
+        scorm = findScormApi();
+    } catch (error) {
+        // A cross-origin parent throws on access
+        console.warn('SCORM API lookup failed:', error);
+    }
+
+    if (scorm && String(scorm.LMSInitialize('')) !== 'true') {
+        console.warn('LMSInitialize failed:', scorm.LMSGetLastError());
+        scorm = null;
+    }
+
+    function scormSet(name, value) {
+        if (scorm) scorm.LMSSetValue(name, String(value));
+    }
+
+    // SCORM 1.2 CMITimespan: HHHH:MM:SS.SS
+    function scormTime(ms) {
+        const seconds = ms / 1000;
+        const hours = Math.floor(seconds / 3600);
+        const minutes = Math.floor((seconds % 3600) / 60);
+        const rest = (seconds % 60).toFixed(2).padStart(5, '0');
+        return `${String(hours).padStart(4, '0')}:${String(minutes).padStart(2, '0')}:${rest}`;
+    }
+
+    // xAPI -----------------------------------------------------------------
+
+    const params = new URLSearchParams(window.location.search);
+    const xapi = CONFIG.xapi.enabled ? {
+        endpoint: params.get('endpoint') || CONFIG.xapi.endpoint,
+        auth: params.get('auth') || CONFIG.xapi.auth,
+        actor: parseActor(params.get('actor')),
+        registration: params.get('registration'),
+        activityId: params.get('activity_id') || CONFIG.activityId
+    } : null;
+
+    function parseActor(value) {
+        if (value) {
+            try {
+                const actor = JSON.parse(value);
+                // Some launchers send name/mbox as one-element arrays
+                Object.keys(actor).forEach(key => {
+                    if (Array.isArray(actor[key])) actor[key] = actor[key][0];
+                });
+                return actor;
+            } catch (error) {
+                console.warn('Ignoring malformed xAPI actor:', error);
+            }
+        }
+        return {
+            objectType: 'Agent',
+            name: 'Anonymous reader',
+            account: {
+                homePage: /^https?:/.test(window.location.origin) ? window.location.origin : 'http://localhost',
+                name: 'anonymous'
+            }
+        };
+    }
+
+    function uuid() {
+        if (window.crypto && crypto.randomUUID) return crypto.randomUUID();
+        return 'xxxxxxxx-xxxx-4xxx-yxxx-xxxxxxxxxxxx'.replace(/[xy]/g, c => {
+            const r = Math.random() * 16 | 0;
+            return (c === 'x' ? r : (r & 0x3) | 0x8).toString(16);
+        });
+    }
+
+    // ISO 8601 duration, e.g. PT1M5.2S
+    function isoDuration(ms) {
+        const seconds = ms / 1000;
+        const minutes = Math.floor(seconds / 60);
+        return `PT${minutes > 0 ? `${minutes}M` : ''}${(seconds % 60).toFixed(1)}S`;
+    }
+
+    function course() {
+        return {
+            objectType: 'Activity',
+            id: xapi.activityId,
+            definition: {
+                type: 'http://adlnet.gov/expapi/activities/course',
+                name: { 'en-US': CONFIG.title }
+            }
+        };
+    }
+
+    function sendStatement(verb, object, result) {
+        if (!xapi || !xapi.endpoint) return;
+
+        const statement = {
+            id: uuid(),
+            actor: xapi.actor,
+            verb: { id: VERBS[verb], display: { 'en-US': verb } },
+            object,
+            timestamp: new Date().toISOString()
+        };
+
+        const context = {};
+        // Page statements point back at the storypack they belong to
+        if (object.id !== xapi.activityId) context.contextActivities = { parent: [course()] };
+        if (xapi.registration) context.registration = xapi.registration;
+        if (Object.keys(context).length > 0) statement.context = context;
+        if (result) statement.result = result;
+
+        const headers = {
+            'Content-Type': 'application/json',
+            'X-Experience-API-Version': '1.0.3'
+        };
+        if (xapi.auth) headers.Authorization = xapi.auth;
+
+        fetch(`${xapi.endpoint.replace(/\/$/, '')}/statements`, {
+            method: 'POST',
+            headers,
+            body: JSON.stringify(statement),
+            // Lets the final statements go out while the page unloads
+            keepalive: true
+        }).then(response => {
+            if (!response.ok) console.warn(`LRS rejected ${verb} statement: HTTP ${response.status}`);
+        }).catch(error => console.warn('Could not reach the LRS:', error));
+    }
+
+    // Reporting ------------------------------------------------------------
+
+    function progress() {
+        return total === 0 ? 100 : Math.round((viewed.size / total) * 100);
+    }
+
+    function pageViewed(index) {
+        const id = player.pageIds[index];
+        const firstView = !viewed.has(id);
+        viewed.add(id);
+
+        // Nothing is graded, so score.* stays unset; LMSs would show progress as a mark
+        scormSet('cmi.core.lesson_location', index);
+        scormSet('cmi.suspend_data', Array.from(viewed).join(','));
+
+        if (firstView && xapi) {
+            sendStatement('experienced', {
+                objectType: 'Activity',
+                id: `${xapi.activityId}/pages/${encodeURIComponent(id)}`,
+                definition: {
+                    type: 'http://adlnet.gov/expapi/activities/module',
+                    name: { 'en-US': CONFIG.pageTitles[id] || id }
+                }
+            });
+            sendStatement('progressed', course(), { extensions: { [PROGRESS_EXTENSION]: progress() } });
+        }
+
+        if (!completed && index === total - 1) {
+            completed = true;
+            scormSet('cmi.core.lesson_status', 'completed');
+            sendStatement('completed', course(), {
+                completion: true,
+                duration: isoDuration(Date.now() - startedAt)
+            });
+        }
+
+        if (scorm) scorm.LMSCommit('');
+    }
+
+    function finish() {
+        if (finished) return;
+        finished = true;
+
+        if (scorm) {
+            scormSet('cmi.core.session_time', scormTime(Date.now() - startedAt));
+            // Incomplete readers pick up where they left off next time
+            scormSet('cmi.core.exit', completed ? '' : 'suspend');
+            scorm.LMSCommit('');
+            scorm.LMSFinish('');
+        }
+
+        sendStatement('terminated', course(), { duration: isoDuration(Date.now() - startedAt) });
+    }
+
+    // Resume a SCORM attempt
+    if (scorm) {
+        const status = scorm.LMSGetValue('cmi.core.lesson_status');
+        completed = status === 'completed' || status === 'passed';
+        if (!completed) scormSet('cmi.core.lesson_status', 'incomplete');
+
+        String(scorm.LMSGetValue('cmi.suspend_data') || '')
+            .split(',')
+            .filter(id => player.pageIds.includes(id))
+            .forEach(id => viewed.add(id));
+
+        const location = parseInt(scorm.LMSGetValue('cmi.core.lesson_location'), 10);
+        if (location > 0 && location < total) player.showPage(location);
+    }
+
+    if (xapi) sendStatement('launched', course());
+
+    document.addEventListener('storypack:pagechange', event => pageViewed(event.detail.index));
+    window.addEventListener('pagehide', finish);
+    window.addEventListener('beforeunload', finish);
+
+    pageViewed(player.currentPage());
+})();
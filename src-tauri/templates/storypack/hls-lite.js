@@ -0,0 +1,201 @@
+// Small HLS player for browsers without native HLS (everything but Safari
+// and most mobile browsers). It only understands the fMP4 playlists
+// ClipForge writes, and falls back to the MP4 <source> if anything fails.
+(function () {
+    const BUFFER_AHEAD = 30;
+
+    function parseAttributes(text) {
+        const attrs = {};
+        const pattern = /([A-Z0-9-]+)=("[^"]*"|[^,]*)/g;
+        let match;
+        while ((match = pattern.exec(text)) !== null) {
+            attrs[match[1]] = match[2].replace(/^"|"$/g, '');
+        }
+        return attrs;
+    }
+
+    async function fetchText(url) {
+        const response = await fetch(url);
+        if (!response.ok) throw new Error(`${url}: HTTP ${response.status}`);
+        return response.text();
+    }
+
+    async function loadMaster(url) {
+        const lines = (await fetchText(url)).split(/\r?\n/);
+        const variants = [];
+
+        lines.forEach((line, i) => {
+            if (line.startsWith('#EXT-X-STREAM-INF:')) {
+                const attrs = parseAttributes(line.slice('#EXT-X-STREAM-INF:'.length));
+                variants.push({
+                    bandwidth: parseInt(attrs.BANDWIDTH, 10) || 0,
+                    codecs: attrs.CODECS || 'avc1.4d4028,mp4a.40.2',
+                    uri: new URL(lines[i + 1].trim(), url).href
+                });
+            }
+        });
+
+        return variants.sort((a, b) => a.bandwidth - b.bandwidth);
+    }
+
+    async function loadMedia(variant) {
+        if (variant.segments) return variant;
+
+        const lines = (await fetchText(variant.uri)).split(/\r?\n/);
+        let start = 0;
+        let duration = 0;
+        variant.segments = [];
+
+        lines.forEach(raw => {
+            const line = raw.trim();
+            if (line.startsWith('#EXT-X-MAP:')) {
+                variant.init = new URL(parseAttributes(line.slice('#EXT-X-MAP:'.length)).URI, variant.uri).href;
+            } else if (line.startsWith('#EXTINF:')) {
+                duration = parseFloat(line.slice('#EXTINF:'.length));
+            } else if (line && !line.startsWith('#')) {
+                variant.segments.push({ uri: new URL(line, variant.uri).href, start, duration });
+                start += duration;
+            }
+        });
+
+        return variant;
+    }
+
+    async function attach(video, masterUrl) {
+        const variants = await loadMaster(masterUrl);
+        if (variants.length === 0) throw new Error('No renditions in master playlist');
+
+        const mime = `video/mp4; codecs="${variants[0].codecs}"`;
+        if (!MediaSource.isTypeSupported(mime)) throw new Error(`Unsupported codecs ${mime}`);
+
+        await loadMedia(variants[0]);
+        const segmentCount = variants[0].segments.length;
+        const totalDuration = variants[0].segments.reduce((sum, s) => sum + s.duration, 0);
+
+        const mediaSource = new MediaSource();
+        video.src = URL.createObjectURL(mediaSource);
+        await new Promise(resolve => mediaSource.addEventListener('sourceopen', resolve, { once: true }));
+
+        mediaSource.duration = totalDuration;
+        const buffer = mediaSource.addSourceBuffer(mime);
+
+        let level = 0;
+        let next = 0;
+        let appendedInit = null;
+        let bandwidth = 0;
+        let loading = false;
+        let failed = false;
+
+        function append(data) {
+            return new Promise((resolve, reject) => {
+                buffer.addEventListener('updateend', resolve, { once: true });
+                buffer.addEventListener('error', reject, { once: true });
+                buffer.appendBuffer(data);
+            });
+        }
+
+        async function fetchBytes(url, measure) {
+            const started = performance.now();
+            const response = await fetch(url);
+            if (!response.ok) throw new Error(`${url}: HTTP ${response.status}`);
+            const data = await response.arrayBuffer();
+
+            const seconds = (performance.now() - started) / 1000;
+            if (measure && seconds > 0) {
+                bandwidth = (data.byteLength * 8) / seconds;
+            }
+            return data;
+        }
+
+        // Highest rendition that fits comfortably in the measured bandwidth
+        function pickLevel() {
+            let chosen = 0;
+            variants.forEach((variant, i) => {
+                if (variant.bandwidth < bandwidth * 0.8) chosen = i;
+            });
+            return chosen;
+        }
+
+        function bufferedAhead() {
+            const time = video.currentTime;
+            for (let i = 0; i < buffer.buffered.length; i++) {
+                if (buffer.buffered.start(i) <= time + 0.1 && time < buffer.buffered.end(i)) {
+                    return buffer.buffered.end(i) - time;
+                }
+            }
+            return 0;
+        }
+
+        function segmentAt(time) {
+            const segments = variants[level].segments;
+            const index = segments.findIndex(s => time < s.start + s.duration);
+            return index === -1 ? segments.length - 1 : index;
+        }
+
+        async function pump() {
+            if (loading || failed) return;
+            loading = true;
+
+            try {
+                while (next < segmentCount && bufferedAhead() < BUFFER_AHEAD) {
+                    const variant = await loadMedia(variants[level]);
+
+                    if (appendedInit !== variant.init) {
+                        await append(await fetchBytes(variant.init, false));
+                        appendedInit = variant.init;
+                    }
+
+                    const target = next;
+                    const data = await fetchBytes(variant.segments[target].uri, true);
+
+                    // A seek moved the playhead while this segment was loading
+                    if (target !== next) continue;
+
+                    await append(data);
+                    if (next === target) next = target + 1;
+                    level = pickLevel();
+                }
+
+                if (next >= segmentCount && mediaSource.readyState === 'open' && !buffer.updating) {
+                    mediaSource.endOfStream();
+                }
+            } finally {
+                loading = false;
+            }
+        }
+
+        function onError(error) {
+            if (failed) return;
+            failed = true;
+            console.error('HLS playback failed, using MP4 instead:', error);
+            video.removeAttribute('src');
+            video.load();
+        }
+
+        video.addEventListener('seeking', () => {
+            if (bufferedAhead() === 0) {
+                next = segmentAt(video.currentTime);
+                pump().catch(onError);
+            }
+        });
+        video.addEventListener('timeupdate', () => pump().catch(onError));
+
+        pump().catch(onError);
+    }
+
+    document.querySelectorAll('video[data-hls]').forEach(video => {
+        const masterUrl = new URL(video.dataset.hls, document.baseURI).href;
+
+        if (video.canPlayType('application/vnd.apple.mpegurl')) {
+            video.src = masterUrl;
+        } else if (window.MediaSource) {
+            attach(video, masterUrl).catch(error => {
+                console.error('HLS playback failed, using MP4 instead:', error);
+                if (video.src.startsWith('blob:')) {
+                    video.removeAttribute('src');
+                    video.load();
+                }
+            });
+        }
+    });
+})();
@@ -0,0 +1,182 @@
+let currentPage = 0;
+const pages = document.querySelectorAll('.page');
+const totalPages = pages.length;
+const prevBtn = document.getElementById('prev-btn');
+const nextBtn = document.getElementById('next-btn');
+const pageIndicator = document.getElementById('page-indicator');
+const playNarrationBtn = document.getElementById('play-narration');
+const toggleMusicBtn = document.getElementById('toggle-music');
+const themeMusic = document.getElementById('theme-music');
+
+let musicPlaying = false;
+let currentlyPlayingAudio = null;
+
+// Check if storypack has any narration at all
+const hasAnyNarration = Array.from(pages).some(page => 
+    page.querySelector('.page-audio') !== null
+);
+
+function updateNarrationButton() {
+    if (!hasAnyNarration) {
+        playNarrationBtn.style.display = 'none';
+        return;
+    }
+    
+    // Always show button if storypack has narration
+    playNarrationBtn.style.display = 'block';
+    
+    // Find any currently playing audio
+    let playingAudio = null;
+    document.querySelectorAll('.page-audio').forEach(audio => {
+        if (!audio.paused) {
+            playingAudio = audio;
+        }
+    });
+    
+    if (playingAudio) {
+        playNarrationBtn.textContent = '⏸️ Narration';
+        currentlyPlayingAudio = playingAudio;
+    } else {
+        playNarrationBtn.textContent = '🔊 Narration';
+        currentlyPlayingAudio = null;
+    }
+}
+
+function showPage(index) {
+    pages.forEach((page, i) => {
+        page.classList.toggle('active', i === index);
+    });
+    
+    currentPage = index;
+    prevBtn.disabled = index === 0;
+    nextBtn.disabled = index === totalPages - 1;
+    pageIndicator.textContent = `Page ${index + 1} of ${totalPages}`;
+    
+    updateNarrationButton();
+    
+    document.dispatchEvent(new CustomEvent('storypack:pagechange', {
+        detail: { index, id: pages[index].dataset.page, total: totalPages }
+    }));
+}
+
+function toggleNarration() {
+    // If something is playing, pause it
+    if (currentlyPlayingAudio && !currentlyPlayingAudio.paused) {
+        currentlyPlayingAudio.pause();
+        playNarrationBtn.textContent = '🔊 Narration';
+        currentlyPlayingAudio = null;
+        return;
+    }
+    
+    // Find next page with audio, starting from current page
+    let audioToPlay = null;
+    let searchIndex = currentPage;
+    
+    // First try current page
+    audioToPlay = pages[currentPage].querySelector('.page-audio');
+    
+    // If current page has no audio, find the next page with audio
+    if (!audioToPlay) {
+        for (let i = 1; i <= totalPages; i++) {
+            const nextIndex = (currentPage + i) % totalPages;
+            audioToPlay = pages[nextIndex].querySelector('.page-audio');
+            if (audioToPlay) {
+                searchIndex = nextIndex;
+                break;
+            }
+        }
+    }
+    
+    if (!audioToPlay) return;
+    
+    // Jump to that page and play
+    if (searchIndex !== currentPage) {
+        showPage(searchIndex);
+    }
+    
+    audioToPlay.currentTime = 0;
+    const playPromise = audioToPlay.play();
+    
+    if (playPromise !== undefined) {
+        playPromise.then(() => {
+            currentlyPlayingAudio = audioToPlay;
+            playNarrationBtn.textContent = '⏸️ Narration';
+        }).catch(error => {
+            console.log('Play interrupted:', error);
+        });
+    }
+}
+
+function toggleMusic() {
+    if (!themeMusic) return;
+    
+    if (musicPlaying) {
+        themeMusic.pause();
+        toggleMusicBtn.textContent = '🎵 Music';
+        musicPlaying = false;
+    } else {
+        themeMusic.play();
+        toggleMusicBtn.textContent = '⏸️ Music';
+        musicPlaying = true;
+    }
+}
+
+prevBtn.addEventListener('click', () => {
+    if (currentPage > 0) {
+        showPage(currentPage - 1);
+    }
+});
+
+nextBtn.addEventListener('click', () => {
+    if (currentPage < totalPages - 1) {
+        showPage(currentPage + 1);
+    }
+});
+
+playNarrationBtn.addEventListener('click', toggleNarration);
+toggleMusicBtn.addEventListener('click', toggleMusic);
+
+// When a clip ends, continue with the page's next clip or clean up
+pages.forEach(page => {
+    const clips = page.querySelectorAll('.page-audio');
+    clips.forEach((audio, i) => {
+        audio.addEventListener('ended', () => {
+            const nextClip = clips[i + 1];
+            if (nextClip) {
+                nextClip.currentTime = 0;
+                nextClip.play();
+                currentlyPlayingAudio = nextClip;
+                return;
+            }
+            currentlyPlayingAudio = null;
+            updateNarrationButton();
+        });
+    });
+});
+
+// Hide music button if no theme music
+if (!themeMusic) {
+    toggleMusicBtn.style.display = 'none';
+}
+
+// Keyboard navigation
+document.addEventListener('keydown', (e) => {
+    if (e.key === 'ArrowLeft' && currentPage > 0) {
+        showPage(currentPage - 1);
+    } else if (e.key === 'ArrowRight' && currentPage < totalPages - 1) {
+        showPage(currentPage + 1);
+    } else if (e.key === ' ') {
+        e.preventDefault();
+        toggleNarration();
+    }
+});
+
+// Lets tracking.js (LMS packages) resume and follow the reader
+window.storypackPlayer = {
+    showPage,
+    currentPage: () => currentPage,
+    pageIds: Array.from(pages).map(page => page.dataset.page)
+};
+
+// Initialize
+showPage(0);
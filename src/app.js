@@ -74,6 +74,12 @@ document.getElementById('add-clips').addEventListener('click', async () => {
             renderClipList();
             document.getElementById('concat-videos').disabled = state.clips.length < 2;
             
+            updatePreviewButton();
+            
+            // Build low-res proxies in the background for previews
+            window.__TAURI__.core.invoke('generate_proxies', { clips: filePaths })
+                .catch(error => console.error('Error starting proxy generation:', error));
+            
             console.log('Added clips:', filePaths);
         } else {
             console.log('No files selected');
@@ -97,6 +103,7 @@ document.getElementById('clear-clips').addEventListener('click', async () => {
             state.clips = [];
             renderClipList();
             document.getElementById('concat-videos').disabled = true;
+            updatePreviewButton();
             console.log('Cleared all clips');
         }
     } catch (error) {
@@ -135,6 +142,7 @@ function renderClipList() {
         const fileName = clip.path.split('\\').pop();
         const fileSize = clip.size ? formatFileSize(clip.size) : '---';
         const duration = clip.duration ? formatDuration(clip.duration) : '--:--';
+        const proxy = clip.proxyPath ? ' • proxy ready' : '';
         
         return `
             <div class="clip-item">
@@ -142,7 +150,7 @@ function renderClipList() {
                     <span class="clip-order">${idx + 1}.</span>
                     <div class="clip-details">
                         <span class="clip-name">${fileName}</span>
-                        <span class="clip-meta">${duration} • ${fileSize}</span>
+                        <span class="clip-meta">${duration} • ${fileSize}${proxy}</span>
                     </div>
                 </div>
                 <div class="clip-actions">
@@ -207,8 +215,55 @@ function removeClip(index) {
     state.clips.splice(index, 1);
     renderClipList();
     document.getElementById('concat-videos').disabled = state.clips.length < 2;
+    updatePreviewButton();
+}
+
+// Proxy status: mark clips whose preview proxy has finished
+window.__TAURI__.event.listen('proxy-ready', (event) => {
+    const status = event.payload;
+    
+    if (status.error) {
+        console.error('Proxy failed for', status.original_path, ':', status.error);
+        return;
+    }
+    
+    state.clips
+        .filter(clip => clip.path === status.original_path)
+        .forEach(clip => { clip.proxyPath = status.proxy_path; });
+    
+    renderClipList();
+    updatePreviewButton();
+});
+
+function updatePreviewButton() {
+    const allProxied = state.clips.length >= 2 && state.clips.every(clip => clip.proxyPath);
+    document.getElementById('preview-videos').disabled = !allProxied;
 }
 
+// ClipForge: Preview button (uses proxies, never the originals)
+document.getElementById('preview-videos').addEventListener('click', async () => {
+    const btn = document.getElementById('preview-videos');
+    btn.disabled = true;
+    btn.textContent = 'Building preview...';
+    
+    try {
+        const previewPath = await window.__TAURI__.core.invoke('preview_concat_videos', {
+            clips: state.clips.map(c => c.path)
+        });
+        
+        await window.__TAURI__.core.invoke('open_folder', { path: previewPath });
+    } catch (error) {
+        console.error('Error building preview:', error);
+        await window.__TAURI__.core.invoke('confirm_dialog', {
+            title: 'Preview Error',
+            message: error.toString()
+        });
+    }
+    
+    btn.textContent = 'Preview';
+    updatePreviewButton();
+});
+
 // Move a clip up or down
 function moveClip(index, direction) {
     const newIndex = index + direction;
@@ -264,6 +319,7 @@ document.getElementById('concat-videos').addEventListener('click', async () => {
             state.clips = [];
             renderClipList();
             btn.disabled = true;
+            updatePreviewButton();
         }
         
     } catch (error) {
@@ -292,8 +348,11 @@ const storyspackState = {
     epilogue: null,
     credits: null,
     narrationAudio: [],
+    narrationPages: [],
     themeAudio: null,
     videoSource: null,
+    transcriptions: [],
+    outputFolder: null,
     workerUrl: savedWorkerUrl,
     autoTranscribe: false
 };
@@ -374,6 +433,12 @@ document.querySelectorAll('.file-select-btn').forEach(button => {
                 }
             }
             
+            // Page list or narration changed, refresh the mapping
+            if (field === 'narrationAudio') {
+                storyspackState.narrationPages = [];
+            }
+            renderNarrationMapping();
+            
             // Update generate button state
             updateGenerateButton();
             
@@ -383,6 +448,61 @@ document.querySelectorAll('.file-select-btn').forEach(button => {
     });
 });
 
+// Page ids in reading order, matching the ids the backend assigns
+function storyPageIds() {
+    const pages = [];
+    if (storyspackState.cover) pages.push({ id: 'cover', label: 'Cover' });
+    if (storyspackState.prologue) pages.push({ id: 'prologue', label: 'Prologue' });
+    (storyspackState.chapters || []).forEach((_, i) => {
+        pages.push({ id: `chapter${i + 1}`, label: `Chapter ${i + 1}` });
+    });
+    if (storyspackState.epilogue) pages.push({ id: 'epilogue', label: 'Epilogue' });
+    if (storyspackState.credits) pages.push({ id: 'credits', label: 'Credits' });
+    return pages;
+}
+
+// Narration mapping: each audio file is bound to a page explicitly
+function renderNarrationMapping() {
+    const container = document.getElementById('narration-mapping');
+    const pages = storyPageIds();
+    
+    if (storyspackState.narrationAudio.length === 0) {
+        container.innerHTML = '';
+        return;
+    }
+    
+    // Default to reading order for files that haven't been mapped yet
+    storyspackState.narrationAudio.forEach((_, i) => {
+        if (storyspackState.narrationPages[i] === undefined) {
+            storyspackState.narrationPages[i] = pages[i] ? pages[i].id : '';
+        }
+    });
+    
+    container.innerHTML = storyspackState.narrationAudio.map((path, i) => {
+        const fileName = path.split('\\').pop();
+        const selected = storyspackState.narrationPages[i];
+        const options = pages.map(page =>
+            `<option value="${page.id}" ${page.id === selected ? 'selected' : ''}>${page.label}</option>`
+        ).join('');
+        
+        return `
+            <div class="narration-mapping-row">
+                <span class="file-name">${escapeHtml(fileName)}</span>
+                <select class="video-type-select" data-index="${i}">
+                    <option value="" ${selected ? '' : 'selected'}>Not used</option>
+                    ${options}
+                </select>
+            </div>
+        `;
+    }).join('');
+    
+    container.querySelectorAll('select').forEach(select => {
+        select.addEventListener('change', (e) => {
+            storyspackState.narrationPages[parseInt(e.target.dataset.index)] = e.target.value;
+        });
+    });
+}
+
 // Video source handling
 const videoTypeSelect = document.getElementById('video-type');
 const youtubeInput = document.getElementById('youtube-input');
@@ -461,14 +581,18 @@ selectLocalVideoBtn.addEventListener('click', async () => {
 document.getElementById('clear-storypack').addEventListener('click', () => {
     // Reset all fields
     projectNameInput.value = '';
+    document.getElementById('output-conflict').value = 'new_version';
     storyspackState.cover = null;
     storyspackState.prologue = null;
     storyspackState.chapters = [];
     storyspackState.epilogue = null;
     storyspackState.credits = null;
     storyspackState.narrationAudio = [];
+    storyspackState.narrationPages = [];
     storyspackState.themeAudio = null;
     storyspackState.videoSource = null;
+    storyspackState.transcriptions = [];
+    storyspackState.outputFolder = null;
     storyspackState.autoTranscribe = false;
     
     // Reset UI
@@ -484,12 +608,259 @@ document.getElementById('clear-storypack').addEventListener('click', () => {
     document.getElementById('youtube-id').value = '';
     document.getElementById('hosted-url').value = '';
     document.getElementById('auto-transcribe').checked = false;
+    document.getElementById('optimize-images').checked = true;
+    document.getElementById('transcode-audio').checked = false;
+    document.getElementById('video-webm').checked = false;
+    document.getElementById('video-hls').checked = false;
+    document.getElementById('single-file').checked = false;
+    document.getElementById('single-file-audio').checked = false;
+    document.getElementById('epub-export').checked = false;
+    document.getElementById('pdf-export').checked = false;
+    document.getElementById('pdf-page-size').value = 'a4';
+    document.getElementById('pdf-bleed').checked = false;
+    document.getElementById('zip-export').checked = false;
+    document.getElementById('lms-export').checked = false;
+    document.getElementById('lms-xapi').checked = false;
+    document.getElementById('lms-xapi-endpoint').value = '';
+    document.getElementById('theme-preset').value = 'classic_book';
+    document.getElementById('theme-custom-colors').checked = false;
+    document.getElementById('theme-colors').style.display = 'none';
+    document.getElementById('theme-page-font').value = '';
+    document.getElementById('theme-heading-font').value = '';
     
+    renderNarrationMapping();
     updateGenerateButton();
     
     console.log('Storypack form cleared');
 });
 
+// Fill the form from a backend config. Configs with pages (from an opened storypack) are mapped onto the
+// form's fixed slots; returns notes about anything the form can't show.
+function fillStorypackForm(config, outputFolder) {
+    const notes = [];
+    
+    document.getElementById('clear-storypack').click();
+    projectNameInput.value = config.project_name;
+    document.getElementById('output-conflict').value = config.on_conflict;
+    
+    if (config.pages.length > 0) {
+        // Imported page ids are mapped to the ids the form assigns
+        const pageIds = {};
+        config.pages.forEach(page => {
+            const label = page.title || page.id;
+            
+            if (!page.image) {
+                notes.push(`"${label}" has no image and was left out`);
+            } else if (page.kind === 'chapter') {
+                storyspackState.chapters.push(page.image);
+                pageIds[page.id] = `chapter${storyspackState.chapters.length}`;
+            } else if (['cover', 'prologue', 'epilogue', 'credits'].includes(page.kind) && !storyspackState[page.kind]) {
+                storyspackState[page.kind] = page.image;
+                pageIds[page.id] = page.kind;
+            } else {
+                notes.push(`"${label}" can't be edited in this form and was left out`);
+            }
+            
+            if (page.video) {
+                notes.push(`The video on "${label}" can't be edited in this form and was left out`);
+            }
+        });
+        
+        config.pages.filter(page => pageIds[page.id]).forEach(page => {
+            page.narration.forEach(path => {
+                storyspackState.narrationAudio.push(path);
+                storyspackState.narrationPages.push(pageIds[page.id]);
+            });
+        });
+        
+        storyspackState.transcriptions = config.transcriptions
+            .filter(transcript => pageIds[transcript.page_id])
+            .map(transcript => ({ page_id: pageIds[transcript.page_id], text: transcript.text }));
+    } else {
+        storyspackState.cover = config.cover_image;
+        storyspackState.prologue = config.prologue_image;
+        storyspackState.chapters = [...config.chapter_images];
+        storyspackState.epilogue = config.epilogue_image;
+        storyspackState.credits = config.credits_image;
+        
+        config.narration.forEach(assignment => {
+            storyspackState.narrationAudio.push(assignment.path);
+            storyspackState.narrationPages.push(assignment.page_id);
+        });
+        // Unassigned narration keeps reading order
+        config.narration_audio.forEach(path => storyspackState.narrationAudio.push(path));
+        
+        storyspackState.transcriptions = [...config.transcriptions];
+    }
+    
+    storyspackState.themeAudio = config.theme_audio;
+    storyspackState.outputFolder = outputFolder || null;
+    
+    ['cover', 'prologue', 'chapters', 'epilogue', 'credits', 'narrationAudio', 'themeAudio'].forEach(field => {
+        const value = storyspackState[field];
+        const paths = Array.isArray(value) ? value : (value ? [value] : []);
+        if (paths.length === 0) return;
+        
+        const nameSpan = document.getElementById(`${field}-name`);
+        nameSpan.textContent = paths.map(p => p.split(/[\\/]/).pop()).join(', ');
+        nameSpan.style.color = '#4fc3f7';
+    });
+    
+    const video = config.video_source;
+    if (video) {
+        videoTypeSelect.value = video.type === 'YouTube' ? 'youtube' : video.type.toLowerCase();
+        videoTypeSelect.dispatchEvent(new Event('change'));
+        storyspackState.videoSource = video;
+        
+        if (video.type === 'YouTube') {
+            document.getElementById('youtube-id').value = video.value.video_id;
+        } else if (video.type === 'Hosted') {
+            document.getElementById('hosted-url').value = video.value.url;
+        } else {
+            document.getElementById('local-video-name').textContent = video.value.path.split(/[\\/]/).pop();
+            document.getElementById('local-video-name').style.color = '#4fc3f7';
+        }
+    }
+    
+    document.getElementById('optimize-images').checked = config.images.optimize;
+    document.getElementById('transcode-audio').checked = config.audio.transcode;
+    document.getElementById('video-webm').checked = config.video.webm;
+    document.getElementById('video-hls').checked = config.video.hls;
+    document.getElementById('single-file').checked = config.single_file.enabled;
+    document.getElementById('single-file-audio').checked = config.single_file.inline_audio;
+    document.getElementById('epub-export').checked = config.epub.enabled;
+    document.getElementById('pdf-export').checked = config.pdf.enabled;
+    document.getElementById('pdf-page-size').value = config.pdf.page_size;
+    document.getElementById('pdf-bleed').checked = config.pdf.bleed_mm > 0;
+    document.getElementById('zip-export').checked = config.archive.enabled;
+    document.getElementById('lms-export').checked = config.lms.enabled;
+    document.getElementById('lms-xapi').checked = config.lms.xapi.enabled;
+    document.getElementById('lms-xapi-endpoint').value = config.lms.xapi.endpoint || '';
+    
+    const theme = config.theme;
+    const customColors = Boolean(theme.primary_color || theme.accent_color);
+    document.getElementById('theme-preset').value = theme.preset;
+    document.getElementById('theme-custom-colors').checked = customColors;
+    document.getElementById('theme-colors').style.display = customColors ? 'flex' : 'none';
+    if (theme.primary_color) document.getElementById('theme-primary').value = theme.primary_color;
+    if (theme.accent_color) document.getElementById('theme-accent').value = theme.accent_color;
+    document.getElementById('theme-page-font').value = theme.page_font || '';
+    document.getElementById('theme-heading-font').value = theme.heading_font || '';
+    
+    renderNarrationMapping();
+    updateGenerateButton();
+    
+    return notes;
+}
+
+// Open an existing storypack: fill the form from a generated folder so it can be fixed and regenerated in place
+document.getElementById('import-storypack').addEventListener('click', async () => {
+    try {
+        const folder = await window.__TAURI__.core.invoke('select_storypack_folder');
+        if (!folder) return;
+        
+        const imported = await window.__TAURI__.core.invoke('import_storypack', { folder });
+        const notes = [...imported.warnings, ...fillStorypackForm(imported.config, imported.output_folder)];
+        
+        if (notes.length > 0) {
+            await window.__TAURI__.core.invoke('confirm_dialog', {
+                title: 'Storypack Opened',
+                message: `Opened from ${imported.source}:\n\n${notes.join('\n')}`
+            });
+        }
+    } catch (error) {
+        console.error('Error opening storypack:', error);
+        await window.__TAURI__.core.invoke('confirm_dialog', {
+            title: 'Error',
+            message: `Failed to open storypack:\n${error}`
+        });
+    }
+});
+
+// Fill the form from a folder of assets, placing files by their names
+document.getElementById('autofill-storypack').addEventListener('click', async () => {
+    try {
+        const folder = await window.__TAURI__.core.invoke('select_asset_folder');
+        if (!folder) return;
+        
+        const report = await window.__TAURI__.core.invoke('autofill_storypack_from_folder', { folder });
+        fillStorypackForm(report.config, null);
+        
+        // Marks how each file was placed, so guesses stand out
+        const confidenceLabels = { high: '✓', medium: '~', low: '?' };
+        const lines = report.matched.map(m => `${confidenceLabels[m.confidence]} ${m.file} → ${m.field}: ${m.reason}`);
+        const unplaced = report.unplaced.map(u => `✗ ${u.file}: ${u.reason}`);
+        const warnings = report.warnings.map(w => `• ${w}`);
+        
+        await window.__TAURI__.core.invoke('confirm_dialog', {
+            title: 'Storypack Filled From Folder',
+            message: [
+                `Placed ${report.matched.length} file(s) (✓ by name, ~ by number, ? guessed):`,
+                ...lines,
+                ...(unplaced.length > 0 ? ['', 'Not placed:', ...unplaced] : []),
+                ...(warnings.length > 0 ? ['', ...warnings] : [])
+            ].join('\n')
+        });
+    } catch (error) {
+        console.error('Error filling storypack from folder:', error);
+        await window.__TAURI__.core.invoke('confirm_dialog', {
+            title: 'Error',
+            message: `Failed to fill storypack from folder:\n${error}`
+        });
+    }
+});
+
+// Save the form as a .storypack project file
+document.getElementById('save-storypack-project').addEventListener('click', async () => {
+    try {
+        const path = await window.__TAURI__.core.invoke('select_storypack_project_save_path', {
+            name: projectNameInput.value.trim() || 'Untitled'
+        });
+        if (!path) return;
+        
+        const saved = await window.__TAURI__.core.invoke('save_storypack_project', {
+            path,
+            config: storypackConfig(storyspackState.transcriptions),
+            outputFolder: storyspackState.outputFolder
+        });
+        
+        console.log('Project saved:', saved);
+    } catch (error) {
+        console.error('Error saving project:', error);
+        await window.__TAURI__.core.invoke('confirm_dialog', {
+            title: 'Error',
+            message: `Failed to save project:\n${error}`
+        });
+    }
+});
+
+// Open a .storypack project file
+document.getElementById('open-storypack-project').addEventListener('click', async () => {
+    try {
+        const path = await window.__TAURI__.core.invoke('select_storypack_project');
+        if (!path) return;
+        
+        const project = await window.__TAURI__.core.invoke('open_storypack_project', { path });
+        const notes = fillStorypackForm(project.config, project.output_folder);
+        
+        project.relinked.forEach(asset => notes.push(`Found ${asset.saved} at ${asset.found}`));
+        project.missing.forEach(asset => notes.push(`Missing: ${asset}`));
+        
+        if (notes.length > 0) {
+            await window.__TAURI__.core.invoke('confirm_dialog', {
+                title: 'Project Opened',
+                message: notes.join('\n')
+            });
+        }
+    } catch (error) {
+        console.error('Error opening project:', error);
+        await window.__TAURI__.core.invoke('confirm_dialog', {
+            title: 'Error',
+            message: `Failed to open project:\n${error}`
+        });
+    }
+});
+
 // Global state for transcription review
 let reviewTranscriptions = [];
 let pendingOutputFolder = null;
@@ -521,8 +892,9 @@ document.getElementById('generate-website').addEventListener('click', async () =
     console.log('Storypack state:', storyspackState);
     
     try {
-        // Select output folder
-        const outputFolder = await window.__TAURI__.core.invoke('select_output_folder');
+        // An opened storypack is regenerated where it is
+        const outputFolder = storyspackState.outputFolder
+            || await window.__TAURI__.core.invoke('select_output_folder');
         if (!outputFolder) {
             console.log('User cancelled folder selection');
             return;
@@ -544,19 +916,25 @@ document.getElementById('generate-website').addEventListener('click', async () =
             
             for (let i = 0; i < storyspackState.narrationAudio.length; i++) {
                 const audioPath = storyspackState.narrationAudio[i];
+                const pageId = storyspackState.narrationPages[i];
                 uploadMsg.textContent = 
                     `Transcribing audio ${i + 1} of ${storyspackState.narrationAudio.length}...`;
                 
+                // Unmapped narration isn't on any page, so there is nothing to transcribe
+                if (!pageId) continue;
+                
                 try {
-                    const segments = await window.__TAURI__.core.invoke('transcribe_audio', {
+                    // Each file is transcribed for its own page, so the text carries that page id
+                    const transcript = await window.__TAURI__.core.invoke('transcribe_page', {
                         config: {
                             worker_url: storyspackState.workerUrl
                         },
+                        pageId,
                         audioPath
                     });
                     
-                    console.log(`Transcribed audio ${i + 1}:`, segments.length, 'segments');
-                    transcriptions.push(...segments);
+                    console.log(`Transcribed audio ${i + 1} for page ${pageId}`);
+                    transcriptions.push(transcript);
                 } catch (error) {
                     console.error(`Failed to transcribe audio ${i + 1}:`, error);
                 }
@@ -571,6 +949,15 @@ document.getElementById('generate-website').addEventListener('click', async () =
             return; // Wait for user to confirm
         }
         
+        // Text from an opened storypack goes through review so it can be fixed
+        if (storyspackState.transcriptions.length > 0) {
+            document.getElementById('generation-status').style.display = 'none';
+            document.getElementById('storypack-form').style.display = 'block';
+            
+            showTranscriptionReview(storyspackState.transcriptions);
+            return;
+        }
+        
         // If no transcription, generate directly
         await actuallyGenerateStorypack(transcriptions, outputFolder);
         
@@ -588,31 +975,107 @@ document.getElementById('generate-website').addEventListener('click', async () =
     }
 });
 
-async function actuallyGenerateStorypack(transcriptions, outputFolder) {
-    const projectName = projectNameInput.value.trim();
+document.getElementById('theme-custom-colors').addEventListener('change', (e) => {
+    document.getElementById('theme-colors').style.display = e.target.checked ? 'flex' : 'none';
+});
+
+function themeConfig() {
+    const customColors = document.getElementById('theme-custom-colors').checked;
+    const pageFont = document.getElementById('theme-page-font').value.trim();
+    const headingFont = document.getElementById('theme-heading-font').value.trim();
     
+    return {
+        preset: document.getElementById('theme-preset').value,
+        primary_color: customColors ? document.getElementById('theme-primary').value : null,
+        accent_color: customColors ? document.getElementById('theme-accent').value : null,
+        page_font: pageFont || null,
+        heading_font: headingFont || null
+    };
+}
+
+// Config for the backend from the form
+function storypackConfig(transcriptions) {
+    return {
+        project_name: projectNameInput.value.trim(),
+        on_conflict: document.getElementById('output-conflict').value,
+        cover_image: storyspackState.cover,
+        prologue_image: storyspackState.prologue,
+        chapter_images: storyspackState.chapters || [],
+        epilogue_image: storyspackState.epilogue,
+        credits_image: storyspackState.credits,
+        narration: storyspackState.narrationAudio
+            .map((path, i) => ({ page_id: storyspackState.narrationPages[i], path }))
+            .filter(assignment => assignment.page_id),
+        theme_audio: storyspackState.themeAudio,
+        video_source: storyspackState.videoSource,
+        transcriptions: transcriptions,
+        theme: themeConfig(),
+        images: { optimize: document.getElementById('optimize-images').checked },
+        audio: { transcode: document.getElementById('transcode-audio').checked },
+        video: {
+            webm: document.getElementById('video-webm').checked,
+            hls: document.getElementById('video-hls').checked
+        },
+        single_file: {
+            enabled: document.getElementById('single-file').checked,
+            inline_audio: document.getElementById('single-file-audio').checked
+        },
+        epub: { enabled: document.getElementById('epub-export').checked },
+        pdf: {
+            enabled: document.getElementById('pdf-export').checked,
+            page_size: document.getElementById('pdf-page-size').value,
+            bleed_mm: document.getElementById('pdf-bleed').checked ? 3 : 0
+        },
+        archive: { enabled: document.getElementById('zip-export').checked },
+        lms: {
+            enabled: document.getElementById('lms-export').checked,
+            xapi: {
+                enabled: document.getElementById('lms-xapi').checked,
+                endpoint: document.getElementById('lms-xapi-endpoint').value.trim() || null
+            }
+        }
+    };
+}
+
+async function actuallyGenerateStorypack(transcriptions, outputFolder) {
     try {
         // Show loading
         document.getElementById('storypack-form').style.display = 'none';
         document.getElementById('generation-status').style.display = 'block';
         document.getElementById('upload-message').textContent = 'Creating storypack files...';
         
-        // Prepare config
-        const config = {
-            project_name: projectName,
-            cover_image: storyspackState.cover,
-            prologue_image: storyspackState.prologue,
-            chapter_images: storyspackState.chapters || [],
-            epilogue_image: storyspackState.epilogue,
-            credits_image: storyspackState.credits,
-            narration_audio: storyspackState.narrationAudio || [],
-            theme_audio: storyspackState.themeAudio,
-            video_source: storyspackState.videoSource,
-            transcriptions: transcriptions
-        };
+        const config = storypackConfig(transcriptions);
         
         console.log('Generating with config:', config);
         
+        // Check files and settings up front instead of failing halfway through
+        const validation = await window.__TAURI__.core.invoke('validate_storypack', { config });
+        const describeIssues = issues => issues.map(issue => `• ${issue.field}: ${issue.message}`).join('\n');
+        
+        if (validation.errors.length > 0) {
+            document.getElementById('generation-status').style.display = 'none';
+            document.getElementById('storypack-form').style.display = 'block';
+            
+            await window.__TAURI__.core.invoke('confirm_dialog', {
+                title: 'Storypack Settings',
+                message: `Please fix these before generating:\n\n${describeIssues(validation.errors)}`
+            });
+            return;
+        }
+        
+        if (validation.warnings.length > 0) {
+            const proceed = await window.__TAURI__.core.invoke('confirm_dialog', {
+                title: 'Storypack Warnings',
+                message: `${describeIssues(validation.warnings)}\n\nGenerate anyway?`
+            });
+            
+            if (!proceed) {
+                document.getElementById('generation-status').style.display = 'none';
+                document.getElementById('storypack-form').style.display = 'block';
+                return;
+            }
+        }
+        
         const result = await window.__TAURI__.core.invoke('generate_storypack', {
             config,
             outputFolder
@@ -625,13 +1088,19 @@ async function actuallyGenerateStorypack(transcriptions, outputFolder) {
         document.getElementById('storypack-form').style.display = 'block';
         
         // Show success with option to open folder
+        const exportText = result.exports.length > 0
+            ? `\n\nAlso created:\n${result.exports.map(e => `• ${e}`).join('\n')}`
+            : '';
+        const warningText = result.warnings.length > 0
+            ? `\n\nWarnings:\n${result.warnings.map(w => `• ${w}`).join('\n')}`
+            : '';
         const shouldOpen = await window.__TAURI__.core.invoke('confirm_dialog', {
             title: 'Success!',
-            message: `Storypack created successfully!\n\nLocation: ${result}\n\nWould you like to open the folder?`
+            message: `Storypack created successfully!\n\nLocation: ${result.path}${exportText}${warningText}\n\nWould you like to open the folder?`
         });
         
         if (shouldOpen) {
-            await window.__TAURI__.core.invoke('open_folder', { path: result });
+            await window.__TAURI__.core.invoke('open_folder', { path: result.path });
         }
         
     } catch (error) {
@@ -651,10 +1120,12 @@ async function actuallyGenerateStorypack(transcriptions, outputFolder) {
 // Transcription Review Modal Functions
 function showTranscriptionReview(transcriptions) {
     // Parse transcriptions into structured format
-    reviewTranscriptions = transcriptions.map((text, index) => {
+    const pageLabels = Object.fromEntries(storyPageIds().map(page => [page.id, page.label]));
+    
+    reviewTranscriptions = transcriptions.map(({ page_id, text }, index) => {
         // Extract title from <strong> tags if present
         const strongMatch = text.match(/<strong>(.*?)<\/strong>/);
-        const title = strongMatch ? strongMatch[1] : `Segment ${index + 1}`;
+        const title = strongMatch ? strongMatch[1] : (pageLabels[page_id] || `Segment ${index + 1}`);
         
         // Remove <strong> tags and extra whitespace from content
         const content = text
@@ -668,7 +1139,7 @@ function showTranscriptionReview(transcriptions) {
         else if (title.toLowerCase().includes('epilogue')) type = 'epilogue';
         else if (title.toLowerCase().includes('credits')) type = 'credits';
         
-        return { title, content, type };
+        return { title, content, type, page_id };
     });
 
     renderTranscriptionReview();
@@ -793,7 +1264,8 @@ function addNewSegment() {
     reviewTranscriptions.push({
         title: `Chapter ${chapterNum}. New Chapter`,
         content: 'Enter your chapter text here...',
-        type: 'chapter'
+        type: 'chapter',
+        page_id: null
     });
     
     reRenderTranscriptionList();
@@ -806,14 +1278,20 @@ function closeTranscriptionReview() {
 }
 
 async function confirmTranscriptions() {
-    // Convert back to format expected by backend
+    // Convert back to format expected by backend; untagged text is placed by its heading
     const formattedTranscriptions = reviewTranscriptions.map(seg => {
-        return `<strong>${seg.title}</strong>\n\n${seg.content}`;
+        return {
+            page_id: seg.page_id || null,
+            text: `<strong>${seg.title}</strong>\n\n${seg.content}`
+        };
     });
     
     // Save outputFolder before closing (closeTranscriptionReview sets it to null)
     const outputFolder = pendingOutputFolder;
     
+    // Keep the reviewed text so it is saved with the project
+    storyspackState.transcriptions = formattedTranscriptions;
+    
     closeTranscriptionReview();
     await actuallyGenerateStorypack(formattedTranscriptions, outputFolder);
 }
@@ -1013,4 +1491,94 @@ document.getElementById('upload-ftp').addEventListener('click', async () => {
     }
 });
 
-console.log('ClipForge Storypack initialized');
\ No newline at end of file
+// Media tools settings
+function showMediaToolsReport(report) {
+    const describe = (name, tool) => tool
+        ? `${name}: ${tool.path} (${tool.source})${tool.version ? ` - ${tool.version}` : ''}`
+        : `${name}: not found`;
+    
+    const lines = [describe('FFmpeg', report.ffmpeg), describe('ffprobe', report.ffprobe)];
+    lines.push(...report.problems.map(p => `• ${p}`));
+    
+    const status = document.getElementById('media-tools-status');
+    status.textContent = lines.join('\n');
+    status.style.whiteSpace = 'pre-line';
+    status.style.color = report.ok ? '#4fc3f7' : '#ff6b6b';
+}
+
+async function loadMediaToolPaths() {
+    try {
+        const settings = await window.__TAURI__.core.invoke('get_media_tool_paths');
+        document.getElementById('ffmpeg-path').value = settings.ffmpeg_path || '';
+        document.getElementById('ffprobe-path').value = settings.ffprobe_path || '';
+    } catch (error) {
+        console.error('Error loading media tool paths:', error);
+    }
+}
+
+[['select-ffmpeg', 'ffmpeg-path', 'Select FFmpeg'], ['select-ffprobe', 'ffprobe-path', 'Select ffprobe']]
+    .forEach(([buttonId, inputId, title]) => {
+        document.getElementById(buttonId).addEventListener('click', async () => {
+            try {
+                const path = await window.__TAURI__.core.invoke('select_media_tool', { title });
+                
+                if (path) {
+                    document.getElementById(inputId).value = path;
+                }
+            } catch (error) {
+                console.error('Error selecting media tool:', error);
+            }
+        });
+    });
+
+document.getElementById('save-media-tools').addEventListener('click', async () => {
+    const ffmpegPath = document.getElementById('ffmpeg-path').value.trim();
+    const ffprobePath = document.getElementById('ffprobe-path').value.trim();
+    
+    try {
+        const report = await window.__TAURI__.core.invoke('set_media_tool_paths', {
+            settings: {
+                ffmpeg_path: ffmpegPath || null,
+                ffprobe_path: ffprobePath || null
+            }
+        });
+        showMediaToolsReport(report);
+    } catch (error) {
+        console.error('Error saving media tool paths:', error);
+        
+        await window.__TAURI__.core.invoke('confirm_dialog', {
+            title: 'Save Failed',
+            message: error.toString()
+        });
+    }
+});
+
+document.getElementById('check-media-tools').addEventListener('click', async () => {
+    try {
+        showMediaToolsReport(await window.__TAURI__.core.invoke('check_media_tools'));
+    } catch (error) {
+        console.error('Error checking media tools:', error);
+    }
+});
+
+console.log('ClipForge Storypack initialized');
+
+// Check FFmpeg/ffprobe up front so jobs don't fail halfway
+(async () => {
+    await loadMediaToolPaths();
+    
+    try {
+        const report = await window.__TAURI__.core.invoke('check_media_tools');
+        console.log('Media tools:', report);
+        showMediaToolsReport(report);
+        
+        if (!report.ok) {
+            await window.__TAURI__.core.invoke('confirm_dialog', {
+                title: 'Media Tools Problem',
+                message: `Some video/audio features will not work:\n\n${report.problems.map(p => `• ${p}`).join('\n')}\n\nSet the tool locations on the Settings tab.`
+            });
+        }
+    } catch (error) {
+        console.error('Error checking media tools:', error);
+    }
+})();